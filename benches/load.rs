@@ -0,0 +1,131 @@
+//! Bulk loading benchmarks: `load_rows` against inserting row by row, in
+//! memory and on disk.
+//!
+//! Run with `cargo bench --bench load`.
+
+use criterion::{BatchSize, Criterion, Throughput, criterion_group, criterion_main};
+use selfhealdb::storage::{
+    BitcaskStorage, Column, ColumnType, DurabilityMode, IntWidth, Row, TableSchema, Value,
+};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Rows loaded per benchmark iteration
+const ROWS: usize = 10_000;
+
+fn column(name: &str, column_type: ColumnType, primary_key: bool) -> Column {
+    Column {
+        name: name.to_string(),
+        column_type,
+        int_width: IntWidth::Big,
+        nullable: !primary_key,
+        unique: primary_key,
+        primary_key,
+        auto_increment: false,
+        default: None,
+    }
+}
+
+/// Create `users (id INTEGER PRIMARY KEY, name TEXT, age INTEGER)` with a
+/// range index on age, so loading also maintains the primary key's index
+/// and a range index
+fn create_users(storage: &mut BitcaskStorage) {
+    let schema = TableSchema::new(
+        "users".to_string(),
+        vec![
+            column("id", ColumnType::Integer, true),
+            column("name", ColumnType::Text, false),
+            column("age", ColumnType::Integer, false),
+        ],
+    );
+    storage.create_table(schema).unwrap();
+    storage.create_range_index("users", "age").unwrap();
+}
+
+fn users() -> Vec<Row> {
+    (0..ROWS as i64)
+        .map(|i| {
+            Row::new(vec![
+                Value::Integer(i),
+                Value::Text(format!("user{}", i)),
+                Value::Integer(18 + i % 60),
+            ])
+        })
+        .collect()
+}
+
+/// A fresh database file path in a directory removed by `remove_scratch_dir`
+fn scratch_path() -> String {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let dir = scratch_dir();
+    fs::create_dir_all(&dir).unwrap();
+    let id = NEXT.fetch_add(1, Ordering::Relaxed);
+    dir.join(format!("load{}.db", id))
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn scratch_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("selfhealdb-bench-{}", std::process::id()))
+}
+
+fn remove_scratch_dir() {
+    let _ = fs::remove_dir_all(scratch_dir());
+}
+
+fn bench_load(c: &mut Criterion) {
+    let mut group = c.benchmark_group("load");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(ROWS as u64));
+
+    group.bench_function("load_rows/in_memory", |b| {
+        b.iter_batched(
+            || {
+                let mut storage = BitcaskStorage::in_memory();
+                create_users(&mut storage);
+                (storage, users())
+            },
+            |(mut storage, rows)| storage.load_rows("users", rows).unwrap(),
+            BatchSize::PerIteration,
+        )
+    });
+
+    group.bench_function("insert_per_row/in_memory", |b| {
+        b.iter_batched(
+            || {
+                let mut storage = BitcaskStorage::in_memory();
+                create_users(&mut storage);
+                (storage, users())
+            },
+            |(mut storage, rows)| {
+                for row in rows {
+                    storage.insert("users", row).unwrap();
+                }
+            },
+            BatchSize::PerIteration,
+        )
+    });
+
+    // Syncing after every write is where loading row by row is slowest;
+    // load_rows syncs once
+    group.bench_function("load_rows/on_disk_on_commit", |b| {
+        b.iter_batched(
+            || {
+                let mut storage =
+                    BitcaskStorage::with_durability(&scratch_path(), DurabilityMode::OnCommit)
+                        .unwrap();
+                create_users(&mut storage);
+                (storage, users())
+            },
+            |(mut storage, rows)| storage.load_rows("users", rows).unwrap(),
+            BatchSize::PerIteration,
+        )
+    });
+
+    group.finish();
+    remove_scratch_dir();
+}
+
+criterion_group!(benches, bench_load);
+criterion_main!(benches);
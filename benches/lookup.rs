@@ -0,0 +1,101 @@
+//! Index lookup benchmarks: a hash index lookup that finds 10k rows spread
+//! across an on-disk log, against finding the same rows with a full scan.
+//!
+//! Run with `cargo bench --bench lookup`.
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use selfhealdb::storage::{BitcaskStorage, Column, ColumnType, IntWidth, Row, TableSchema, Value};
+use std::fs;
+use std::path::PathBuf;
+
+/// Rows in the table
+const ROWS: usize = 100_000;
+
+/// Distinct values of the indexed column, so each lookup finds `ROWS / GROUPS` rows
+const GROUPS: i64 = 10;
+
+fn column(name: &str, column_type: ColumnType) -> Column {
+    Column {
+        name: name.to_string(),
+        column_type,
+        int_width: IntWidth::Big,
+        nullable: true,
+        unique: false,
+        primary_key: false,
+        auto_increment: false,
+        default: None,
+    }
+}
+
+/// Create `users (id, name, grp)` on disk with a hash index on grp. Rows of
+/// the same group are interleaved with the others, so a lookup's rows are
+/// spread over the whole log.
+fn create_users(path: &str) -> BitcaskStorage {
+    let mut storage = BitcaskStorage::new(path).unwrap();
+    let schema = TableSchema::new(
+        "users".to_string(),
+        vec![
+            column("id", ColumnType::Integer),
+            column("name", ColumnType::Text),
+            column("grp", ColumnType::Integer),
+        ],
+    );
+    storage.create_table(schema).unwrap();
+    storage.create_index("users", &["grp"]).unwrap();
+
+    let rows: Vec<Row> = (0..ROWS as i64)
+        .map(|i| {
+            Row::new(vec![
+                Value::Integer(i),
+                Value::Text(format!("user{}", i)),
+                Value::Integer(i % GROUPS),
+            ])
+        })
+        .collect();
+    storage.load_rows("users", rows).unwrap();
+    storage
+}
+
+fn scratch_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("selfhealdb-bench-{}", std::process::id()))
+}
+
+fn bench_lookup(c: &mut Criterion) {
+    fs::create_dir_all(scratch_dir()).unwrap();
+    let path = scratch_dir()
+        .join("lookup.db")
+        .to_string_lossy()
+        .into_owned();
+    let storage = create_users(&path);
+    let key = Value::Integer(3);
+
+    let mut group = c.benchmark_group("lookup");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(ROWS as u64 / GROUPS as u64));
+
+    group.bench_function("index_lookup", |b| {
+        b.iter(|| {
+            let rows = storage.index_lookup("users", "grp", &key).unwrap();
+            assert_eq!(rows.len(), ROWS / GROUPS as usize);
+        })
+    });
+
+    group.bench_function("full_scan", |b| {
+        b.iter(|| {
+            let rows: Vec<Row> = storage
+                .scan_iter("users")
+                .unwrap()
+                .map(|entry| entry.unwrap().1)
+                .filter(|row| row.get(2) == Some(&key))
+                .collect();
+            assert_eq!(rows.len(), ROWS / GROUPS as usize);
+        })
+    });
+
+    group.finish();
+    drop(storage);
+    let _ = fs::remove_dir_all(scratch_dir());
+}
+
+criterion_group!(benches, bench_lookup);
+criterion_main!(benches);
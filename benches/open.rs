@@ -0,0 +1,92 @@
+//! Open benchmarks: opening a 100k-row database whose checkpoint covers the
+//! whole log, against opening one that has to replay the log from the start.
+//!
+//! Run with `cargo bench --bench open`.
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use selfhealdb::storage::{BitcaskStorage, Column, ColumnType, IntWidth, Row, TableSchema, Value};
+use std::fs;
+use std::path::PathBuf;
+
+/// Rows in the database
+const ROWS: usize = 100_000;
+
+fn column(name: &str, column_type: ColumnType) -> Column {
+    Column {
+        name: name.to_string(),
+        column_type,
+        int_width: IntWidth::Big,
+        nullable: true,
+        unique: false,
+        primary_key: false,
+        auto_increment: false,
+        default: None,
+    }
+}
+
+/// Write `users (id, name, age)` with `ROWS` rows, deleting every tenth,
+/// and checkpoint it at the end if asked to
+fn create_users(path: &str, checkpoint: bool) {
+    let mut storage = BitcaskStorage::new(path).unwrap();
+    storage.set_checkpoint_interval(0);
+    let schema = TableSchema::new(
+        "users".to_string(),
+        vec![
+            column("id", ColumnType::Integer),
+            column("name", ColumnType::Text),
+            column("age", ColumnType::Integer),
+        ],
+    );
+    storage.create_table(schema).unwrap();
+
+    let rows: Vec<Row> = (0..ROWS as i64)
+        .map(|i| {
+            Row::new(vec![
+                Value::Integer(i),
+                Value::Text(format!("user{}", i)),
+                Value::Integer(18 + i % 60),
+            ])
+        })
+        .collect();
+    let offsets = storage.insert_batch("users", rows).unwrap();
+    for offset in offsets.into_iter().step_by(10) {
+        storage.delete("users", offset).unwrap();
+    }
+
+    if checkpoint {
+        storage.checkpoint().unwrap();
+    }
+}
+
+fn scratch_path(name: &str) -> String {
+    scratch_dir().join(name).to_string_lossy().into_owned()
+}
+
+fn scratch_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("selfhealdb-bench-{}", std::process::id()))
+}
+
+fn bench_open(c: &mut Criterion) {
+    fs::create_dir_all(scratch_dir()).unwrap();
+    let checkpointed = scratch_path("checkpointed.db");
+    let replayed = scratch_path("replayed.db");
+    create_users(&checkpointed, true);
+    create_users(&replayed, false);
+
+    let mut group = c.benchmark_group("open");
+    group.sample_size(10);
+
+    group.bench_function("with_checkpoint", |b| {
+        b.iter(|| BitcaskStorage::open_read_only(&checkpointed).unwrap())
+    });
+
+    group.bench_function("full_replay", |b| {
+        b.iter(|| BitcaskStorage::open_read_only(&replayed).unwrap())
+    });
+
+    group.finish();
+    let _ = fs::remove_dir_all(scratch_dir());
+}
+
+criterion_group!(benches, bench_open);
+criterion_main!(benches);
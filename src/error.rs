@@ -0,0 +1,95 @@
+use sqlparser::parser::ParserError;
+use sqlparser::tokenizer::TokenizerError;
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Result of a database operation
+pub type DbResult<T> = Result<T, DbError>;
+
+/// Everything that can go wrong in the storage engine or the executor,
+/// split up so callers can tell a bad statement from a broken file
+#[derive(Debug)]
+pub enum DbError {
+    /// SQL that couldn't be parsed
+    Parse(String),
+    /// A row that breaks its table's schema or constraints: a missing NOT
+    /// NULL value, a repeated UNIQUE value, a value out of range
+    SchemaViolation(String),
+    /// No table of this name
+    TableNotFound(String),
+    /// No column of this name in the table
+    ColumnNotFound { table: String, column: String },
+    /// No index on the described columns, e.g. `users.email`
+    IndexNotFound(String),
+    /// A value of the wrong type for where it is used
+    TypeMismatch(String),
+    /// A table or index that is being created already exists
+    AlreadyExists(String),
+    /// A statement or call that is unsupported or not allowed as written
+    InvalidInput(String),
+    /// A statement ran past its time limit or read more rows than allowed
+    Timeout(String),
+    /// The error of one row of a multi-row statement, with its position
+    Row { index: usize, source: Box<DbError> },
+    /// Reading or writing the database files failed, or found them corrupt
+    Io(io::Error),
+}
+
+impl DbError {
+    /// Attach the position of the row that caused the error
+    pub fn in_row(self, index: usize) -> DbError {
+        DbError::Row {
+            index,
+            source: Box::new(self),
+        }
+    }
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbError::Parse(message) => write!(f, "Parse error: {}", message),
+            DbError::TableNotFound(table) => write!(f, "Table '{}' not found", table),
+            DbError::ColumnNotFound { table, column } => {
+                write!(f, "Column '{}' not found in table '{}'", column, table)
+            }
+            DbError::IndexNotFound(index) => write!(f, "No index on {}", index),
+            DbError::SchemaViolation(message)
+            | DbError::TypeMismatch(message)
+            | DbError::AlreadyExists(message)
+            | DbError::InvalidInput(message)
+            | DbError::Timeout(message) => f.write_str(message),
+            DbError::Row { index, source } => write!(f, "Row {}: {}", index, source),
+            DbError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for DbError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DbError::Row { source, .. } => Some(source.as_ref()),
+            DbError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for DbError {
+    fn from(e: io::Error) -> Self {
+        DbError::Io(e)
+    }
+}
+
+impl From<ParserError> for DbError {
+    fn from(e: ParserError) -> Self {
+        DbError::Parse(e.to_string())
+    }
+}
+
+impl From<TokenizerError> for DbError {
+    fn from(e: TokenizerError) -> Self {
+        DbError::Parse(e.to_string())
+    }
+}
@@ -0,0 +1,242 @@
+use super::{column_at, column_ref, find_column, unresolved_column};
+use crate::error::{DbError, DbResult};
+use crate::storage::decimal::MAX_PRECISION;
+use crate::storage::{ColumnType, Row, TableSchema, Value};
+use sqlparser::ast::{
+    DuplicateTreatment, Expr, Function, FunctionArg, FunctionArgExpr, FunctionArguments,
+};
+use std::collections::{BTreeMap, HashSet};
+
+/// An aggregate function call from a SELECT list.
+/// Column arguments are stored as schema column indices.
+#[derive(Debug, Clone)]
+pub enum Aggregate {
+    /// COUNT(*)
+    CountStar,
+    /// COUNT(column) - counts non-NULL values
+    Count(usize),
+    /// COUNT(DISTINCT column) - counts distinct non-NULL values
+    CountDistinct(usize),
+    Sum(usize),
+    Avg(usize),
+    Min(usize),
+    Max(usize),
+}
+
+impl Aggregate {
+    /// Recognise an aggregate function call. Returns `None` for any other
+    /// expression, and an error for unsupported forms of a known aggregate.
+    pub fn from_expr(expr: &Expr, schema: &TableSchema) -> DbResult<Option<Aggregate>> {
+        let Expr::Function(function) = expr else {
+            return Ok(None);
+        };
+
+        let name = function.name.to_string().to_lowercase();
+        let distinct = is_distinct_call(function);
+        let aggregate = match name.as_str() {
+            "count" if distinct => Aggregate::CountDistinct(resolve_argument(function, schema)?),
+            "count" if is_wildcard_call(function) => Aggregate::CountStar,
+            "sum" | "avg" | "min" | "max" if distinct => {
+                return Err(DbError::InvalidInput(format!(
+                    "Unsupported aggregate: {}",
+                    function
+                )));
+            }
+            "count" => Aggregate::Count(resolve_argument(function, schema)?),
+            "sum" => Aggregate::Sum(resolve_numeric_argument(function, schema)?),
+            "avg" => Aggregate::Avg(resolve_numeric_argument(function, schema)?),
+            "min" => Aggregate::Min(resolve_argument(function, schema)?),
+            "max" => Aggregate::Max(resolve_argument(function, schema)?),
+            _ => return Ok(None),
+        };
+
+        Ok(Some(aggregate))
+    }
+
+    /// Type of the values the aggregate produces
+    pub fn output_type(&self, schema: &TableSchema) -> ColumnType {
+        match self {
+            Aggregate::CountStar | Aggregate::Count(_) | Aggregate::CountDistinct(_) => {
+                ColumnType::Integer
+            }
+            Aggregate::Avg(_) => ColumnType::Float,
+            // A sum can have more digits than the values it adds up
+            Aggregate::Sum(col_idx) => match column_at(schema, *col_idx).column_type {
+                ColumnType::Decimal { scale, .. } => ColumnType::Decimal {
+                    precision: MAX_PRECISION,
+                    scale,
+                },
+                ref column_type => column_type.clone(),
+            },
+            Aggregate::Min(col_idx) | Aggregate::Max(col_idx) => {
+                column_at(schema, *col_idx).column_type.clone()
+            }
+        }
+    }
+
+    /// Compute the aggregate over a set of rows. NULL values are skipped,
+    /// and SUM/AVG/MIN/MAX of no values is NULL.
+    pub fn evaluate(&self, rows: &[Row]) -> DbResult<Value> {
+        match self {
+            Aggregate::CountStar => Ok(Value::Integer(rows.len() as i64)),
+            Aggregate::Count(col_idx) => {
+                Ok(Value::Integer(non_null_values(rows, *col_idx).count() as i64))
+            }
+            Aggregate::CountDistinct(col_idx) => {
+                let distinct: HashSet<&Value> = non_null_values(rows, *col_idx).collect();
+                Ok(Value::Integer(distinct.len() as i64))
+            }
+            Aggregate::Sum(col_idx) => sum(non_null_values(rows, *col_idx)),
+            Aggregate::Avg(col_idx) => {
+                let mut total = 0.0;
+                let mut count = 0;
+                for value in non_null_values(rows, *col_idx) {
+                    total += as_f64(value)?;
+                    count += 1;
+                }
+
+                if count == 0 {
+                    Ok(Value::Null)
+                } else {
+                    Ok(Value::Float(total / count as f64))
+                }
+            }
+            Aggregate::Min(col_idx) => {
+                Ok(non_null_values(rows, *col_idx).min().cloned().unwrap_or(Value::Null))
+            }
+            Aggregate::Max(col_idx) => {
+                Ok(non_null_values(rows, *col_idx).max().cloned().unwrap_or(Value::Null))
+            }
+        }
+    }
+}
+
+/// One output column of a grouped or aggregate query
+#[derive(Debug, Clone)]
+pub enum GroupedColumn {
+    /// The group's value for the GROUP BY column at this position
+    Key(usize),
+    /// An aggregate computed over the group's rows
+    Aggregate(Aggregate),
+}
+
+/// Bucket rows by the values of the grouping columns, ordered by group key
+pub fn group_rows(rows: Vec<Row>, key_columns: &[usize]) -> BTreeMap<Vec<Value>, Vec<Row>> {
+    let mut groups: BTreeMap<Vec<Value>, Vec<Row>> = BTreeMap::new();
+
+    for row in rows {
+        let key = key_columns
+            .iter()
+            .map(|&i| row.get(i).cloned().unwrap_or(Value::Null))
+            .collect();
+        groups.entry(key).or_default().push(row);
+    }
+
+    groups
+}
+
+/// Whether the call is `f(*)`
+fn is_wildcard_call(function: &Function) -> bool {
+    matches!(
+        &function.args,
+        FunctionArguments::List(list)
+            if matches!(list.args.as_slice(), [FunctionArg::Unnamed(FunctionArgExpr::Wildcard)])
+    )
+}
+
+/// Whether the call is `f(DISTINCT ...)`
+fn is_distinct_call(function: &Function) -> bool {
+    matches!(
+        &function.args,
+        FunctionArguments::List(list)
+            if list.duplicate_treatment == Some(DuplicateTreatment::Distinct)
+    )
+}
+
+/// Resolve the single column argument of an aggregate to a column index
+fn resolve_argument(function: &Function, schema: &TableSchema) -> DbResult<usize> {
+    let col_name = match &function.args {
+        FunctionArguments::List(list) => match list.args.as_slice() {
+            [FunctionArg::Unnamed(FunctionArgExpr::Expr(expr))] if column_ref(expr).is_some() => {
+                column_ref(expr).unwrap_or_default()
+            }
+            _ => {
+                return Err(DbError::InvalidInput(format!(
+                    "Unsupported aggregate: {}",
+                    function
+                )));
+            }
+        },
+        _ => {
+            return Err(DbError::InvalidInput(format!(
+                "Unsupported aggregate: {}",
+                function
+            )));
+        }
+    };
+
+    find_column(schema, &col_name).ok_or_else(|| unresolved_column(schema, &col_name))
+}
+
+/// Resolve the column argument of SUM/AVG, which must be numeric
+fn resolve_numeric_argument(function: &Function, schema: &TableSchema) -> DbResult<usize> {
+    let col_idx = resolve_argument(function, schema)?;
+    let column = column_at(schema, col_idx);
+
+    if !matches!(
+        column.column_type,
+        ColumnType::Integer | ColumnType::Float | ColumnType::Decimal { .. }
+    ) {
+        return Err(DbError::TypeMismatch(format!(
+            "{} requires a numeric column, but '{}' is {:?}",
+            function.name, column.name, column.column_type
+        )));
+    }
+
+    Ok(col_idx)
+}
+
+/// Iterate over the non-NULL values of a column
+fn non_null_values(rows: &[Row], col_idx: usize) -> impl Iterator<Item = &Value> {
+    rows.iter()
+        .filter_map(move |row| row.get(col_idx))
+        .filter(|value| !matches!(value, Value::Null))
+}
+
+/// Sum numeric values, staying an Integer or exact Decimal unless a Float
+/// is involved
+fn sum<'a>(values: impl Iterator<Item = &'a Value>) -> DbResult<Value> {
+    let mut total: Option<Value> = None;
+
+    for value in values {
+        total = Some(match (total, value) {
+            (None, value) => {
+                as_f64(value)?;
+                value.clone()
+            }
+            (Some(Value::Integer(acc)), Value::Integer(i)) => Value::Integer(
+                acc.checked_add(*i)
+                    .ok_or_else(|| DbError::InvalidInput("Integer overflow in SUM".to_string()))?,
+            ),
+            (Some(Value::Decimal(acc)), Value::Decimal(d)) => Value::Decimal(
+                acc.checked_add(*d)
+                    .ok_or_else(|| DbError::InvalidInput("Decimal overflow in SUM".to_string()))?,
+            ),
+            (Some(acc), value) => Value::Float(as_f64(&acc)? + as_f64(value)?),
+        });
+    }
+
+    Ok(total.unwrap_or(Value::Null))
+}
+
+fn as_f64(value: &Value) -> DbResult<f64> {
+    match value {
+        Value::Integer(i) => Ok(*i as f64),
+        Value::Float(f) => Ok(*f),
+        Value::Decimal(d) => Ok(d.to_f64()),
+        other => Err(DbError::TypeMismatch(format!(
+            "Expected a numeric value, got {:?}",
+            other
+        ))),
+    }
+}
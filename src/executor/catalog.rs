@@ -0,0 +1,103 @@
+use super::QueryExecutor;
+use crate::error::{DbError, DbResult};
+use crate::storage::{Column, ColumnType, IntWidth, Row, Storage, TableSchema, Value};
+
+impl<S: Storage> QueryExecutor<S> {
+    /// `SHOW TABLES`: one row per table with its name and column definitions
+    pub(super) fn show_tables(&self) -> (TableSchema, Vec<Row>) {
+        let rows = self
+            .storage
+            .list_tables()
+            .into_iter()
+            .map(|schema| {
+                let columns: Vec<String> = schema.columns.iter().map(Column::to_string).collect();
+                vec![schema.name.clone(), columns.join(", ")]
+            })
+            .collect();
+
+        text_result("tables", &["name", "columns"], rows)
+    }
+
+    /// `SHOW STATS`: one row per figure from the storage engine's stats, to
+    /// help decide when to compact
+    pub(super) fn show_stats(&self) -> DbResult<(TableSchema, Vec<Row>)> {
+        let stats = self.storage.stats()?;
+        let rows = [
+            ("file_size", stats.file_size.to_string()),
+            ("tables", stats.tables.to_string()),
+            ("live_rows", stats.live_rows.to_string()),
+            ("total_records", stats.total_records.to_string()),
+            ("stale_records", stats.stale_records.to_string()),
+            ("index_memory", stats.index_memory.to_string()),
+        ]
+        .into_iter()
+        .map(|(name, value)| vec![name.to_string(), value])
+        .collect();
+
+        Ok(text_result("stats", &["stat", "value"], rows))
+    }
+
+    /// `DESCRIBE table`: one row per column with its type, whether it
+    /// accepts NULL, whether it is a key, and its default
+    pub(super) fn describe_table(&self, table_name: &str) -> DbResult<(TableSchema, Vec<Row>)> {
+        let schema = self
+            .storage
+            .get_schema(table_name)
+            .ok_or_else(|| DbError::TableNotFound(table_name.to_string()))?;
+
+        let rows = schema
+            .columns
+            .iter()
+            .map(|column| {
+                let nullable = if column.nullable { "YES" } else { "NO" };
+                let key = if column.primary_key {
+                    "PRIMARY KEY"
+                } else if column.unique {
+                    "UNIQUE"
+                } else {
+                    ""
+                };
+                vec![
+                    column.name.clone(),
+                    column.type_name(),
+                    nullable.to_string(),
+                    key.to_string(),
+                    column.default_sql().unwrap_or_default(),
+                ]
+            })
+            .collect();
+
+        Ok(text_result(
+            table_name,
+            &["column", "type", "nullable", "key", "default"],
+            rows,
+        ))
+    }
+}
+
+/// A result whose columns all hold text, such as a catalog listing
+pub(super) fn text_result(
+    name: &str,
+    column_names: &[&str],
+    rows: Vec<Vec<String>>,
+) -> (TableSchema, Vec<Row>) {
+    let columns = column_names
+        .iter()
+        .map(|column_name| Column {
+            name: column_name.to_string(),
+            column_type: ColumnType::Text,
+            int_width: IntWidth::Big,
+            nullable: false,
+            unique: false,
+            primary_key: false,
+            auto_increment: false,
+            default: None,
+        })
+        .collect();
+    let rows = rows
+        .into_iter()
+        .map(|values| Row::new(values.into_iter().map(Value::Text).collect()))
+        .collect();
+
+    (TableSchema::new(name.to_string(), columns), rows)
+}
@@ -0,0 +1,342 @@
+use super::scalar::{self, ScalarFunction};
+use super::{column_ref, like_match, QueryExecutor};
+use crate::error::{DbError, DbResult};
+use crate::storage::{Row, Storage, TableSchema, Value};
+use sqlparser::ast::{BinaryOperator, Expr, UnaryOperator};
+
+/// A WHERE, ON or SELECT list expression with its columns resolved to
+/// positions in the row and its literals converted to values, so it can be
+/// evaluated against many rows without looking anything up again.
+#[derive(Debug, Clone)]
+pub(super) enum CompiledExpr {
+    /// A column of the row, by position
+    Column(usize),
+    /// A literal or placeholder
+    Literal(Value),
+    Not(Box<CompiledExpr>),
+    And(Box<CompiledExpr>, Box<CompiledExpr>),
+    Or(Box<CompiledExpr>, Box<CompiledExpr>),
+    Like {
+        expr: Box<CompiledExpr>,
+        pattern: Box<CompiledExpr>,
+        escape: char,
+        negated: bool,
+    },
+    InList {
+        expr: Box<CompiledExpr>,
+        list: Vec<CompiledExpr>,
+        negated: bool,
+    },
+    IsNull(Box<CompiledExpr>),
+    IsNotNull(Box<CompiledExpr>),
+    Between {
+        expr: Box<CompiledExpr>,
+        low: Box<CompiledExpr>,
+        high: Box<CompiledExpr>,
+        negated: bool,
+    },
+    /// A comparison, arithmetic or `||`
+    Binary {
+        left: Box<CompiledExpr>,
+        op: BinaryOperator,
+        right: Box<CompiledExpr>,
+    },
+    /// Unary minus or plus
+    Sign(UnaryOperator, Box<CompiledExpr>),
+    Function(ScalarFunction, Box<CompiledExpr>),
+}
+
+impl<S: Storage> QueryExecutor<S> {
+    /// Compile a WHERE or ON condition over rows described by `schema`.
+    /// Fails unless the whole condition is one `matches` can decide, so a
+    /// filter never silently keeps rows it doesn't understand.
+    pub(super) fn compile_condition(
+        &self,
+        expr: &Expr,
+        schema: &TableSchema,
+    ) -> DbResult<CompiledExpr> {
+        let condition = self.compile(expr, schema)?;
+        if !condition.is_condition() {
+            return Err(DbError::InvalidInput(format!(
+                "Expected a condition but got {}",
+                expr
+            )));
+        }
+        Ok(condition)
+    }
+
+    /// Compile an expression over rows described by `schema`, checking its
+    /// columns and operand types as `expr_type` does for a SELECT list
+    pub(super) fn compile(&self, expr: &Expr, schema: &TableSchema) -> DbResult<CompiledExpr> {
+        let compile = |expr: &Expr| self.compile(expr, schema).map(Box::new);
+        Ok(match expr {
+            Expr::Identifier(_) | Expr::CompoundIdentifier(_) => {
+                let col_name = column_ref(expr).unwrap_or_default();
+                CompiledExpr::Column(self.resolve_column(&col_name, schema)?)
+            }
+            Expr::Value(_) | Expr::TypedString { .. } => {
+                CompiledExpr::Literal(self.expr_to_value(expr)?)
+            }
+            Expr::Nested(inner) => self.compile(inner, schema)?,
+            Expr::UnaryOp {
+                op: UnaryOperator::Not,
+                expr: inner,
+            } => CompiledExpr::Not(compile(inner)?),
+            Expr::UnaryOp {
+                op: op @ (UnaryOperator::Minus | UnaryOperator::Plus),
+                expr: inner,
+            } => {
+                self.expr_type(expr, schema)?;
+                CompiledExpr::Sign(*op, compile(inner)?)
+            }
+            Expr::BinaryOp {
+                left,
+                op: BinaryOperator::And,
+                right,
+            } => CompiledExpr::And(compile(left)?, compile(right)?),
+            Expr::BinaryOp {
+                left,
+                op: BinaryOperator::Or,
+                right,
+            } => CompiledExpr::Or(compile(left)?, compile(right)?),
+            Expr::BinaryOp { left, op, right }
+                if is_comparison(op)
+                    || scalar::is_arithmetic(op)
+                    || *op == BinaryOperator::StringConcat =>
+            {
+                if !is_comparison(op) {
+                    self.expr_type(expr, schema)?;
+                }
+                CompiledExpr::Binary {
+                    left: compile(left)?,
+                    op: op.clone(),
+                    right: compile(right)?,
+                }
+            }
+            Expr::Like {
+                negated,
+                any: false,
+                expr: inner,
+                pattern,
+                escape_char,
+            } => CompiledExpr::Like {
+                expr: compile(inner)?,
+                pattern: compile(pattern)?,
+                escape: escape_char
+                    .as_deref()
+                    .and_then(|escape| escape.chars().next())
+                    .unwrap_or('\\'),
+                negated: *negated,
+            },
+            Expr::InList {
+                expr: inner,
+                list,
+                negated,
+            } => CompiledExpr::InList {
+                expr: compile(inner)?,
+                list: list
+                    .iter()
+                    .map(|item| self.compile(item, schema))
+                    .collect::<DbResult<_>>()?,
+                negated: *negated,
+            },
+            Expr::IsNull(inner) => CompiledExpr::IsNull(compile(inner)?),
+            Expr::IsNotNull(inner) => CompiledExpr::IsNotNull(compile(inner)?),
+            Expr::Between {
+                expr: inner,
+                negated,
+                low,
+                high,
+            } => CompiledExpr::Between {
+                expr: compile(inner)?,
+                low: compile(low)?,
+                high: compile(high)?,
+                negated: *negated,
+            },
+            Expr::Function(function) => {
+                self.expr_type(expr, schema)?;
+                let (scalar_function, arg) = ScalarFunction::from_call(function)?;
+                CompiledExpr::Function(scalar_function, compile(arg)?)
+            }
+            _ => {
+                return Err(DbError::InvalidInput(format!(
+                    "Unsupported expression: {}",
+                    expr
+                )));
+            }
+        })
+    }
+}
+
+impl CompiledExpr {
+    /// Whether the expression is a condition, true or false (or unknown)
+    /// for each row, rather than a value such as a column or a sum
+    fn is_condition(&self) -> bool {
+        match self {
+            CompiledExpr::Not(inner) => inner.is_condition(),
+            CompiledExpr::And(left, right) | CompiledExpr::Or(left, right) => {
+                left.is_condition() && right.is_condition()
+            }
+            CompiledExpr::Like { .. }
+            | CompiledExpr::InList { .. }
+            | CompiledExpr::IsNull(_)
+            | CompiledExpr::IsNotNull(_)
+            | CompiledExpr::Between { .. } => true,
+            CompiledExpr::Binary { op, .. } => is_comparison(op),
+            CompiledExpr::Column(_)
+            | CompiledExpr::Literal(_)
+            | CompiledExpr::Sign(..)
+            | CompiledExpr::Function(..) => false,
+        }
+    }
+
+    /// Whether a row satisfies the condition. A condition that is unknown
+    /// because of a NULL doesn't match, so `age != 30` leaves out rows whose
+    /// age is NULL, just as `age = 30` does.
+    pub(super) fn matches(&self, row: &Row) -> bool {
+        self.evaluate(row) == Some(true)
+    }
+
+    /// Evaluate a condition with SQL's three-valued logic: `None` is unknown,
+    /// the result of comparing with NULL. NOT of unknown is unknown; AND is
+    /// false if either side is false, and OR true if either side is true.
+    fn evaluate(&self, row: &Row) -> Option<bool> {
+        match self {
+            CompiledExpr::Not(inner) => inner.evaluate(row).map(|matched| !matched),
+            CompiledExpr::And(left, right) => match (left.evaluate(row), right.evaluate(row)) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            },
+            CompiledExpr::Or(left, right) => match (left.evaluate(row), right.evaluate(row)) {
+                (Some(true), _) | (_, Some(true)) => Some(true),
+                (Some(false), Some(false)) => Some(false),
+                _ => None,
+            },
+            CompiledExpr::Like {
+                expr,
+                pattern,
+                escape,
+                negated,
+            } => {
+                // LIKE only applies to text; NULL or anything else is
+                // unknown, so neither LIKE nor NOT LIKE matches it
+                let (Value::Text(text), Value::Text(pattern)) =
+                    (expr.value(row), pattern.value(row))
+                else {
+                    return None;
+                };
+                Some(like_match(&text, &pattern, *escape) != *negated)
+            }
+            CompiledExpr::InList {
+                expr,
+                list,
+                negated,
+            } => {
+                let value = expr.value(row);
+                let items: Vec<Value> = list
+                    .iter()
+                    .map(|item| item.value(row).coerce_to_match(&value))
+                    .collect();
+
+                // A NULL value is unknown, as is a value not found in a list
+                // holding NULL, even for NOT IN
+                if value == Value::Null {
+                    return None;
+                }
+                let found = items
+                    .iter()
+                    .any(|item| value.is_comparable(item) && value == *item);
+
+                if !found && items.contains(&Value::Null) {
+                    None
+                } else {
+                    Some(found != *negated)
+                }
+            }
+            CompiledExpr::IsNull(inner) => Some(inner.value(row) == Value::Null),
+            CompiledExpr::IsNotNull(inner) => Some(inner.value(row) != Value::Null),
+            CompiledExpr::Between {
+                expr,
+                low,
+                high,
+                negated,
+            } => {
+                let value = expr.value(row);
+                let low = low.value(row).coerce_to_match(&value);
+                let high = high.value(row).coerce_to_match(&value);
+
+                // NULL or mismatched types are unknown, even for NOT BETWEEN
+                if !value.is_comparable(&low) || !value.is_comparable(&high) {
+                    return None;
+                }
+
+                Some((low <= value && value <= high) != *negated)
+            }
+            // Either side may be a column, so `a < b` compares two columns
+            // of the same row
+            CompiledExpr::Binary { left, op, right } => {
+                let left_val = left.value(row);
+                let right_val = right.value(row);
+
+                // Let a date string compare against a Timestamp on either side
+                let right_val = right_val.coerce_to_match(&left_val);
+                let left_val = left_val.coerce_to_match(&right_val);
+
+                // Comparisons with NULL, or between Text and a number, are
+                // unknown. This includes `!=` and `<>`, so `x != NULL` never
+                // matches, just like `x = NULL`.
+                if !left_val.is_comparable(&right_val) {
+                    return None;
+                }
+
+                match op {
+                    BinaryOperator::Eq => Some(left_val == right_val),
+                    BinaryOperator::NotEq => Some(left_val != right_val),
+                    BinaryOperator::Gt => Some(left_val > right_val),
+                    BinaryOperator::GtEq => Some(left_val >= right_val),
+                    BinaryOperator::Lt => Some(left_val < right_val),
+                    BinaryOperator::LtEq => Some(left_val <= right_val),
+                    _ => None,
+                }
+            }
+            // Values aren't conditions, and `compile_condition` rejects them
+            CompiledExpr::Column(_)
+            | CompiledExpr::Literal(_)
+            | CompiledExpr::Sign(..)
+            | CompiledExpr::Function(..) => None,
+        }
+    }
+
+    /// Evaluate the expression to a value for a row. Conditions are NULL.
+    pub(super) fn value(&self, row: &Row) -> Value {
+        match self {
+            CompiledExpr::Column(col_idx) => row.get(*col_idx).cloned().unwrap_or(Value::Null),
+            CompiledExpr::Literal(value) => value.clone(),
+            CompiledExpr::Sign(op, inner) => scalar::sign(op, inner.value(row)),
+            CompiledExpr::Binary { left, op, right } if scalar::is_arithmetic(op) => {
+                scalar::arithmetic(op, left.value(row), right.value(row))
+            }
+            CompiledExpr::Binary {
+                left,
+                op: BinaryOperator::StringConcat,
+                right,
+            } => scalar::concat(left.value(row), right.value(row)),
+            CompiledExpr::Function(scalar_function, arg) => scalar_function.apply(arg.value(row)),
+            _ => Value::Null,
+        }
+    }
+}
+
+/// Whether an operator compares its operands: `= != < <= > >=`
+fn is_comparison(op: &BinaryOperator) -> bool {
+    matches!(
+        op,
+        BinaryOperator::Eq
+            | BinaryOperator::NotEq
+            | BinaryOperator::Gt
+            | BinaryOperator::GtEq
+            | BinaryOperator::Lt
+            | BinaryOperator::LtEq
+    )
+}
@@ -0,0 +1,294 @@
+use super::QueryExecutor;
+use crate::error::{DbError, DbResult};
+use crate::storage::decimal::Decimal;
+use crate::storage::timestamp::parse_timestamp;
+use crate::storage::{Column, ColumnType, Row, Storage, Value};
+use std::io::{BufWriter, Read, Write};
+
+impl<S: Storage> QueryExecutor<S> {
+    /// Write a table as CSV: a header line of column names, then one line
+    /// per row. NULLs are written as empty fields, and empty text as `""`
+    /// so the two can be told apart.
+    pub fn export_csv(&mut self, table_name: &str, writer: impl Write) -> DbResult<()> {
+        let schema = self
+            .storage
+            .get_schema(table_name)
+            .ok_or_else(|| DbError::TableNotFound(table_name.to_string()))?;
+
+        let mut writer = BufWriter::new(writer);
+
+        // Write header
+        let header: Vec<String> = schema.columns.iter().map(|c| quote_field(&c.name)).collect();
+        writeln!(writer, "{}", header.join(","))?;
+
+        // Write rows
+        for entry in self.storage.scan_iter(table_name)? {
+            let (_, row) = entry?;
+            let fields: Vec<String> = row.values.iter().map(format_field).collect();
+            writeln!(writer, "{}", fields.join(","))?;
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Load rows from CSV into an existing table. The header line names the
+    /// columns; columns it leaves out take their default. Empty fields are
+    /// NULL, and `""` is empty text. All rows are inserted or none are.
+    /// Returns the number of rows imported.
+    pub fn import_csv(&mut self, table_name: &str, mut reader: impl Read) -> DbResult<usize> {
+        let schema = self
+            .storage
+            .get_schema(table_name)
+            .cloned()
+            .ok_or_else(|| DbError::TableNotFound(table_name.to_string()))?;
+
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+        let mut records = parse_records(&text)?.into_iter();
+
+        // Map header fields to schema columns
+        let Some((header_line, header)) = records.next() else {
+            return Err(invalid_line(1, "missing header"));
+        };
+        let mut target_columns = Vec::new();
+        for field in &header {
+            let col_idx = schema.get_column_index(&field.text).ok_or_else(|| {
+                invalid_line(
+                    header_line,
+                    &format!("Column '{}' not found in table '{}'", field.text, table_name),
+                )
+            })?;
+            if target_columns.contains(&col_idx) {
+                return Err(invalid_line(
+                    header_line,
+                    &format!("Column '{}' specified more than once", field.text),
+                ));
+            }
+            target_columns.push(col_idx);
+        }
+
+        // Omitted columns take their default, so a NOT NULL column needs one
+        // unless it is AUTO_INCREMENT and numbers itself
+        for (col_idx, column) in schema.columns.iter().enumerate() {
+            if !target_columns.contains(&col_idx)
+                && !column.nullable
+                && column.default.is_none()
+                && !column.auto_increment
+            {
+                return Err(invalid_line(
+                    header_line,
+                    &format!("Column '{}' is NOT NULL and has no default", column.name),
+                ));
+            }
+        }
+
+        // Parse and validate every row before inserting any
+        let mut lines = Vec::new();
+        let mut rows = Vec::new();
+        for (line, fields) in records {
+            if fields.len() != target_columns.len() {
+                return Err(invalid_line(
+                    line,
+                    &format!(
+                        "expected {} fields but found {}",
+                        target_columns.len(),
+                        fields.len()
+                    ),
+                ));
+            }
+
+            let mut values: Vec<Value> = schema
+                .columns
+                .iter()
+                .map(|column| column.default.clone().unwrap_or(Value::Null))
+                .collect();
+            for (field, &col_idx) in fields.iter().zip(&target_columns) {
+                values[col_idx] = parse_field(field, &schema.columns[col_idx])
+                    .map_err(|e| invalid_line(line, &e))?;
+            }
+
+            lines.push(line);
+            rows.push(Row::new(values));
+        }
+
+        // Number the rows before validating them, as an omitted
+        // AUTO_INCREMENT column is NULL until then
+        self.storage
+            .assign_ids(table_name, &mut rows)
+            .map_err(|e| match e {
+                DbError::Row { index, source } => invalid_line(lines[index], &source.to_string()),
+                e => e,
+            })?;
+        for (row, &line) in rows.iter().zip(&lines) {
+            schema
+                .validate_row(row)
+                .map_err(|e| invalid_line(line, &e))?;
+        }
+
+        let count = rows.len();
+        self.storage.insert_batch(table_name, rows)?;
+        Ok(count)
+    }
+}
+
+/// A field read from a CSV line
+struct Field {
+    text: String,
+    // Whether the field was in quotes, which makes an empty field empty text rather than NULL
+    quoted: bool,
+}
+
+/// Split CSV text into records, each paired with the line number it starts on.
+/// Quoted fields may contain delimiters, doubled quotes and line breaks.
+/// Blank lines are skipped.
+fn parse_records(text: &str) -> DbResult<Vec<(usize, Vec<Field>)>> {
+    let mut records = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+
+    while chars.peek().is_some() {
+        let start_line = line;
+        let mut fields = Vec::new();
+        let mut field = Field { text: String::new(), quoted: false };
+        let mut in_quotes = false;
+
+        loop {
+            let Some(c) = chars.next() else {
+                if in_quotes {
+                    return Err(invalid_line(start_line, "unterminated quoted field"));
+                }
+                break;
+            };
+
+            if in_quotes {
+                match c {
+                    '"' if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.text.push('"');
+                    }
+                    '"' => in_quotes = false,
+                    '\n' => {
+                        line += 1;
+                        field.text.push(c);
+                    }
+                    _ => field.text.push(c),
+                }
+                continue;
+            }
+
+            match c {
+                '"' if field.text.is_empty() && !field.quoted => {
+                    field.quoted = true;
+                    in_quotes = true;
+                }
+                ',' => fields.push(std::mem::replace(
+                    &mut field,
+                    Field { text: String::new(), quoted: false },
+                )),
+                '\r' if chars.peek() == Some(&'\n') => {}
+                '\n' => {
+                    line += 1;
+                    break;
+                }
+                _ => field.text.push(c),
+            }
+        }
+
+        // A blank line has a single empty, unquoted field
+        if !fields.is_empty() || !field.text.is_empty() || field.quoted {
+            fields.push(field);
+            records.push((start_line, fields));
+        }
+    }
+
+    Ok(records)
+}
+
+/// Convert a CSV field to a value of the column's type
+fn parse_field(field: &Field, column: &Column) -> Result<Value, String> {
+    if field.text.is_empty() && !field.quoted {
+        return Ok(Value::Null);
+    }
+
+    let text = field.text.as_str();
+    let value = match column.column_type {
+        ColumnType::Integer => text.trim().parse().ok().map(Value::Integer),
+        ColumnType::Float => text.trim().parse().ok().map(Value::Float),
+        ColumnType::Text => Some(Value::Text(text.to_string())),
+        ColumnType::Timestamp => parse_timestamp(text).map(Value::Timestamp),
+        ColumnType::Decimal { scale, .. } => Decimal::parse(text)
+            .and_then(|d| d.rescale(scale))
+            .map(Value::Decimal),
+    };
+
+    value.ok_or_else(|| {
+        format!(
+            "'{}' is not a valid {:?} for column {}",
+            text, column.column_type, column.name
+        )
+    })
+}
+
+fn invalid_line(line: usize, message: &str) -> DbError {
+    DbError::InvalidInput(format!("Line {}: {}", line, message))
+}
+
+/// CSV text for a single value
+fn format_field(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::Text(s) if s.is_empty() => "\"\"".to_string(),
+        value => quote_field(&value.to_string()),
+    }
+}
+
+/// Quote a field if it contains a delimiter, quote or line break,
+/// doubling any quotes inside it
+fn quote_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::ExecutionResult;
+    use crate::storage::BitcaskStorage;
+
+    #[test]
+    fn import_numbers_omitted_auto_increment_column() {
+        let mut executor = QueryExecutor::new(BitcaskStorage::in_memory());
+        executor
+            .execute("CREATE TABLE t (id SERIAL PRIMARY KEY, name TEXT NOT NULL)")
+            .unwrap();
+
+        let imported = executor
+            .import_csv("t", "name\nalice\nbob\n".as_bytes())
+            .unwrap();
+        assert_eq!(imported, 2);
+
+        let ExecutionResult::Selected(rows) = executor.execute("SELECT * FROM t").unwrap().result
+        else {
+            panic!("expected rows");
+        };
+        let ids: Vec<&Value> = rows.iter().map(|row| row.get(0).unwrap()).collect();
+        assert_eq!(ids, [&Value::Integer(1), &Value::Integer(2)]);
+    }
+
+    #[test]
+    fn import_reports_invalid_rows_by_line() {
+        let mut executor = QueryExecutor::new(BitcaskStorage::in_memory());
+        executor
+            .execute("CREATE TABLE t (id SERIAL PRIMARY KEY, name TEXT NOT NULL, age INTEGER)")
+            .unwrap();
+
+        let error = executor
+            .import_csv("t", "name,age\nalice,30\n,41\n".as_bytes())
+            .unwrap_err();
+        assert!(error.to_string().starts_with("Line 3:"), "{}", error);
+    }
+}
@@ -0,0 +1,117 @@
+use crate::storage::{Row, TableSchema, Value};
+
+/// Render rows as an ASCII table headed by the schema's column names,
+/// in the style of the sqlite3 CLI's box output:
+///
+/// ```text
+/// +----+-------+
+/// | id | name  |
+/// +----+-------+
+/// |  1 | alice |
+/// +----+-------+
+/// ```
+///
+/// NULLs are shown as `NULL` and numbers are right-aligned.
+pub fn format_rows(schema: &TableSchema, rows: &[Row]) -> String {
+    let headers: Vec<&str> = schema.columns.iter().map(|c| c.name.as_str()).collect();
+
+    let cells: Vec<Vec<(String, bool)>> = rows
+        .iter()
+        .map(|row| (0..headers.len()).map(|i| format_cell(row.get(i))).collect())
+        .collect();
+
+    // Each column is as wide as its header or widest value
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in &cells {
+        for (width, (cell, _)) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let separator = format!(
+        "+{}+\n",
+        widths
+            .iter()
+            .map(|&w| "-".repeat(w + 2))
+            .collect::<Vec<_>>()
+            .join("+")
+    );
+
+    let mut output = separator.clone();
+    let header_cells: Vec<(String, bool)> =
+        headers.iter().map(|h| (h.to_string(), false)).collect();
+    output.push_str(&format_line(&header_cells, &widths));
+    output.push_str(&separator);
+
+    if !cells.is_empty() {
+        for row in &cells {
+            output.push_str(&format_line(row, &widths));
+        }
+        output.push_str(&separator);
+    }
+
+    output
+}
+
+/// Convert rows to a JSON array with one object per row, keyed by the
+/// schema's column names. Integers, floats, text and NULL map to the native
+/// JSON types; timestamps become ISO 8601 strings, and non-finite floats,
+/// which JSON can't represent, become null.
+pub fn rows_to_json(schema: &TableSchema, rows: &[Row]) -> serde_json::Value {
+    let objects = rows
+        .iter()
+        .map(|row| {
+            let object = schema
+                .columns
+                .iter()
+                .zip(&row.values)
+                .map(|(column, value)| (column.name.clone(), value_to_json(value)))
+                .collect();
+            serde_json::Value::Object(object)
+        })
+        .collect();
+
+    serde_json::Value::Array(objects)
+}
+
+fn value_to_json(value: &Value) -> serde_json::Value {
+    match value {
+        Value::Integer(i) => serde_json::Value::from(*i),
+        Value::Float(x) => serde_json::Number::from_f64(*x)
+            .map(serde_json::Value::Number)
+            .unwrap_or(serde_json::Value::Null),
+        Value::Text(s) => serde_json::Value::String(s.clone()),
+        Value::Null => serde_json::Value::Null,
+        // Strings keep every digit, which a JSON number parsed as a float may not
+        Value::Timestamp(_) | Value::Decimal(_) => serde_json::Value::String(value.to_string()),
+    }
+}
+
+/// Text for a single value, and whether it should be right-aligned
+fn format_cell(value: Option<&Value>) -> (String, bool) {
+    match value {
+        Some(Value::Null) => ("NULL".to_string(), false),
+        Some(value @ (Value::Integer(_) | Value::Float(_) | Value::Decimal(_))) => {
+            (value.to_string(), true)
+        }
+        Some(value) => (value.to_string(), false),
+        None => (String::new(), false),
+    }
+}
+
+/// One table line with each cell padded to its column's width
+fn format_line(cells: &[(String, bool)], widths: &[usize]) -> String {
+    let padded: Vec<String> = cells
+        .iter()
+        .zip(widths)
+        .map(|((cell, right_align), &width)| {
+            if *right_align {
+                format!(" {:>width$} ", cell)
+            } else {
+                format!(" {:<width$} ", cell)
+            }
+        })
+        .collect();
+
+    format!("|{}|\n", padded.join("|"))
+}
@@ -0,0 +1,146 @@
+use super::catalog::text_result;
+use super::{parse_script, AccessPath, QueryExecutor};
+use crate::error::{DbError, DbResult};
+use crate::storage::{IndexStats, Row, Storage, TableSchema};
+use sqlparser::ast::{Expr, FromTable, Query, SetExpr, Statement};
+
+impl<S: Storage> QueryExecutor<S> {
+    /// Describe how a SELECT or DELETE would find its rows, without running
+    /// it: the table, whether an index is used and on which column, and how
+    /// many rows match. `sql` may be given with or without `EXPLAIN`.
+    pub fn explain(&self, sql: &str) -> DbResult<String> {
+        let ast = parse_script(sql)?;
+        let statement = match ast.as_slice() {
+            [Statement::Explain { statement, .. }] => statement.as_ref(),
+            [statement] => statement,
+            _ => {
+                return Err(DbError::InvalidInput(
+                    "EXPLAIN takes exactly one statement".to_string(),
+                ));
+            }
+        };
+
+        Ok(self.explain_statement(statement)?.join("\n"))
+    }
+
+    /// Describe a statement's plan, one line per step
+    pub(super) fn explain_statement(&self, statement: &Statement) -> DbResult<Vec<String>> {
+        match statement {
+            Statement::Query(query) => self.explain_query(query),
+            Statement::Delete(delete) => {
+                let from = match &delete.from {
+                    FromTable::WithFromKeyword(from) | FromTable::WithoutKeyword(from) => from,
+                };
+                let Some(from) = from.first() else {
+                    return Err(DbError::InvalidInput("No table specified".to_string()));
+                };
+
+                let table_name = from.relation.to_string();
+                let mut lines = vec![format!("DELETE FROM {}", table_name)];
+                lines.extend(self.explain_where(&table_name, delete.selection.as_ref())?);
+                Ok(lines)
+            }
+            _ => Err(DbError::InvalidInput(
+                "EXPLAIN supports only SELECT and DELETE statements".to_string(),
+            )),
+        }
+    }
+
+    fn explain_query(&self, query: &Query) -> DbResult<Vec<String>> {
+        let SetExpr::Select(select) = query.body.as_ref() else {
+            return Err(DbError::InvalidInput("Unsupported query type".to_string()));
+        };
+        let Some(from) = select.from.first() else {
+            return Err(DbError::InvalidInput("No table specified".to_string()));
+        };
+
+        if !from.joins.is_empty() {
+            let mut lines = vec![format!("SELECT FROM {}", from)];
+            lines.extend(self.describe_join(from)?);
+            return Ok(lines);
+        }
+
+        let table_name = from.relation.to_string();
+        let mut lines = vec![format!("SELECT FROM {}", table_name)];
+
+        // COUNT(*) over a whole table is answered from the stored row count
+        if self.count_all_rows(select, &table_name)?.is_some() {
+            let row_count = self.storage.row_count(&table_name).unwrap_or(0);
+            lines.push(format!("Access: {}", AccessPath::RowCount));
+            lines.push(format!("Rows: {}", row_count));
+            return Ok(lines);
+        }
+
+        lines.extend(self.explain_where(&table_name, select.selection.as_ref())?);
+        Ok(lines)
+    }
+
+    /// Describe how the rows matching an optional WHERE clause are found,
+    /// and how many there are
+    fn explain_where(&self, table_name: &str, selection: Option<&Expr>) -> DbResult<Vec<String>> {
+        let (access_path, row_count) = match selection {
+            Some(expr) => {
+                let (access_path, rows) = self.find_where(table_name, expr, None)?;
+                (access_path, rows.len() as u64)
+            }
+            None => {
+                let row_count = self
+                    .storage
+                    .row_count(table_name)
+                    .ok_or_else(|| DbError::TableNotFound(table_name.to_string()))?;
+                (AccessPath::FullScan, row_count)
+            }
+        };
+
+        let mut lines = vec![format!("Access: {}", access_path)];
+        if let Some(stats) = self.access_path_stats(table_name, &access_path) {
+            lines.push(format!(
+                "Index: {} distinct keys, {:.2} rows per key",
+                stats.cardinality, stats.avg_rows_per_key
+            ));
+        }
+        lines.push(format!("Rows: {}", row_count));
+
+        // The indexes the planner could have picked from
+        let mut indexes: Vec<String> = self
+            .storage
+            .list_indexes(table_name)
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        indexes.extend(
+            self.storage
+                .composite_index_columns(table_name)
+                .into_iter()
+                .map(|col_names| format!("({})", col_names.join(", "))),
+        );
+        if indexes.is_empty() {
+            lines.push("Available indexes: none".to_string());
+        } else {
+            lines.push(format!("Available indexes: {}", indexes.join(", ")));
+        }
+        Ok(lines)
+    }
+
+    /// Statistics of the index an access path reads, if it reads one
+    fn access_path_stats(&self, table_name: &str, access_path: &AccessPath) -> Option<IndexStats> {
+        match access_path {
+            AccessPath::PrimaryKey(col_name) | AccessPath::Index(col_name) => {
+                self.storage.index_stats(table_name, col_name)
+            }
+            AccessPath::RangeIndex(col_name) => {
+                self.storage.range_index_stats(table_name, col_name)
+            }
+            AccessPath::CompositeIndex(col_names) => {
+                self.storage.composite_index_stats(table_name, col_names)
+            }
+            AccessPath::FullScan | AccessPath::RowCount | AccessPath::IndexUnion(_) => None,
+        }
+    }
+}
+
+/// The result of `EXPLAIN`: one row per line of the plan
+pub(super) fn plan_rows(lines: Vec<String>) -> (TableSchema, Vec<Row>) {
+    let rows = lines.into_iter().map(|line| vec![line]).collect();
+    text_result("EXPLAIN", &["plan"], rows)
+}
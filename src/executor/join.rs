@@ -0,0 +1,202 @@
+use super::{column_ref, find_column, split_conjuncts, QueryExecutor};
+use crate::error::{DbError, DbResult};
+use crate::storage::{Row, Storage, TableSchema, Value};
+use sqlparser::ast::{
+    BinaryOperator, Expr, Join, JoinConstraint, JoinOperator, TableFactor, TableWithJoins,
+};
+use std::collections::BTreeMap;
+
+impl<S: Storage> QueryExecutor<S> {
+    /// Read the tables of a FROM clause and combine them with its INNER JOINs.
+    /// Columns of the result are named `table.column`, using the table's alias
+    /// when it has one, so the same column name can come from both sides.
+    pub(super) fn join_tables(&self, from: &TableWithJoins) -> DbResult<(TableSchema, Vec<Row>)> {
+        let (mut schema, mut rows) = self.qualified_table(&from.relation)?;
+
+        for join in &from.joins {
+            let on = join_condition(join)?;
+            let (right_schema, right_rows) = self.qualified_table(&join.relation)?;
+            let left_width = schema.columns.len();
+            schema.name = format!("{} JOIN {}", schema.name, right_schema.name);
+            schema.columns.extend(right_schema.columns);
+
+            // The hash join only pairs rows on one condition, so check all of ON
+            let condition = self.compile_condition(on, &schema)?;
+            let matches = |row: &Row| condition.matches(row);
+            rows = match self.equi_join_key(on, &schema, left_width) {
+                Some((left_key, right_key)) => {
+                    println!("  [Using hash join on {}]", on);
+                    hash_join(rows, right_rows, left_key, right_key - left_width, matches)
+                }
+                None => {
+                    println!("  [No equi-join condition, using nested loop join]");
+                    nested_loop_join(rows, &right_rows, matches)
+                }
+            };
+        }
+
+        Ok((schema, rows))
+    }
+
+    /// Describe how `join_tables` would combine the tables of a FROM clause,
+    /// one line per table and per join, without reading any rows
+    pub(super) fn describe_join(&self, from: &TableWithJoins) -> DbResult<Vec<String>> {
+        let (table_name, mut schema) = self.qualified_schema(&from.relation)?;
+        let mut lines = vec![self.describe_table_scan(&table_name)];
+
+        for join in &from.joins {
+            let on = join_condition(join)?;
+            let (right_name, right_schema) = self.qualified_schema(&join.relation)?;
+            lines.push(self.describe_table_scan(&right_name));
+
+            let left_width = schema.columns.len();
+            schema.columns.extend(right_schema.columns);
+            lines.push(match self.equi_join_key(on, &schema, left_width) {
+                Some(_) => format!("Join: hash join on {}", on),
+                None => format!("Join: nested loop join on {}", on),
+            });
+        }
+
+        Ok(lines)
+    }
+
+    fn describe_table_scan(&self, table_name: &str) -> String {
+        let row_count = self.storage.row_count(table_name).unwrap_or(0);
+        format!("Access: full scan of {} ({} rows)", table_name, row_count)
+    }
+
+    /// Scan a table, naming its columns `qualifier.column`
+    fn qualified_table(&self, relation: &TableFactor) -> DbResult<(TableSchema, Vec<Row>)> {
+        let (table_name, qualified) = self.qualified_schema(relation)?;
+        let rows = self.scan_table(&table_name, None)?;
+        Ok((qualified, rows.into_iter().map(|(_, row)| row).collect()))
+    }
+
+    /// Look up a table's schema, naming its columns `qualifier.column`.
+    /// Returns the table's name along with the schema.
+    fn qualified_schema(&self, relation: &TableFactor) -> DbResult<(String, TableSchema)> {
+        let TableFactor::Table { name, alias, .. } = relation else {
+            return Err(DbError::InvalidInput(format!(
+                "Unsupported table reference: {}",
+                relation
+            )));
+        };
+
+        let table_name = name.to_string();
+        let schema = self
+            .storage
+            .get_schema(&table_name)
+            .ok_or_else(|| DbError::TableNotFound(table_name.to_string()))?;
+
+        let qualifier = match alias {
+            Some(alias) => alias.name.value.clone(),
+            None => table_name.clone(),
+        };
+        let mut qualified = schema.clone();
+        qualified.name = qualifier.clone();
+        for column in &mut qualified.columns {
+            column.name = format!("{}.{}", qualifier, column.name);
+        }
+
+        Ok((table_name, qualified))
+    }
+
+    /// Find an AND-ed `left_column = right_column` condition in ON whose
+    /// columns have the same type, returning their positions in the joined row
+    fn equi_join_key(
+        &self,
+        on: &Expr,
+        schema: &TableSchema,
+        left_width: usize,
+    ) -> Option<(usize, usize)> {
+        split_conjuncts(on).into_iter().find_map(|conjunct| {
+            let Expr::BinaryOp {
+                left,
+                op: BinaryOperator::Eq,
+                right,
+            } = conjunct
+            else {
+                return None;
+            };
+
+            let a = find_column(schema, &column_ref(left)?)?;
+            let b = find_column(schema, &column_ref(right)?)?;
+            let (left_key, right_key) = match (a < left_width, b < left_width) {
+                (true, false) => (a, b),
+                (false, true) => (b, a),
+                _ => return None,
+            };
+
+            // Different types could still compare equal after coercion,
+            // which grouping by value would miss
+            let same_type =
+                schema.columns[left_key].column_type == schema.columns[right_key].column_type;
+            same_type.then_some((left_key, right_key))
+        })
+    }
+}
+
+/// The ON condition of a join, which must be an INNER JOIN
+fn join_condition(join: &Join) -> DbResult<&Expr> {
+    match &join.join_operator {
+        JoinOperator::Inner(JoinConstraint::On(expr)) => Ok(expr),
+        _ => Err(DbError::InvalidInput(format!(
+            "Unsupported join: {}",
+            join.to_string().trim()
+        ))),
+    }
+}
+
+/// Pair rows whose key columns hold equal values, keeping the combined rows
+/// that satisfy `matches`. NULL keys never match.
+fn hash_join(
+    left: Vec<Row>,
+    right: Vec<Row>,
+    left_key: usize,
+    right_key: usize,
+    matches: impl Fn(&Row) -> bool,
+) -> Vec<Row> {
+    let mut buckets: BTreeMap<Value, Vec<Row>> = BTreeMap::new();
+    for row in right {
+        match row.get(right_key) {
+            Some(Value::Null) | None => {}
+            Some(key) => buckets.entry(key.clone()).or_default().push(row),
+        }
+    }
+
+    let mut joined = Vec::new();
+    for row in left {
+        let Some(candidates) = row.get(left_key).and_then(|key| buckets.get(key)) else {
+            continue;
+        };
+        for right_row in candidates {
+            let combined = combine(&row, right_row);
+            if matches(&combined) {
+                joined.push(combined);
+            }
+        }
+    }
+
+    joined
+}
+
+/// Pair every left row with every right row, keeping the combined rows
+/// that satisfy `matches`
+fn nested_loop_join(left: Vec<Row>, right: &[Row], matches: impl Fn(&Row) -> bool) -> Vec<Row> {
+    let mut joined = Vec::new();
+    for row in &left {
+        for right_row in right {
+            let combined = combine(row, right_row);
+            if matches(&combined) {
+                joined.push(combined);
+            }
+        }
+    }
+    joined
+}
+
+fn combine(left: &Row, right: &Row) -> Row {
+    let mut values = left.values.clone();
+    values.extend(right.values.iter().cloned());
+    Row::new(values)
+}
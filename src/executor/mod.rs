@@ -1,67 +1,288 @@
-use crate::storage::{BitcaskStorage, Column, ColumnType, Row, TableSchema, Value};
-use sqlparser::ast::{Expr, Query, Select, SetExpr, Statement, Value as SqlValue};
-use sqlparser::dialect::GenericDialect;
-use sqlparser::parser::Parser;
-use std::io;
-use std::time::Instant;
-
-pub struct QueryExecutor {
-    pub storage: BitcaskStorage,
+mod aggregate;
+mod catalog;
+mod compiled;
+mod csv;
+pub mod display;
+mod explain;
+mod join;
+mod prepared;
+mod scalar;
+
+use crate::error::{DbError, DbResult};
+use crate::storage::decimal::{Decimal, MAX_PRECISION};
+use crate::storage::{
+    BitcaskStorage, Column, ColumnType, IntWidth, Row, Storage, TableSchema, Value,
+};
+use aggregate::{Aggregate, GroupedColumn};
+use compiled::CompiledExpr;
+use sqlparser::ast::{
+    Distinct, ExactNumberInfo, Expr, FromTable, GroupByExpr, Ident, ObjectName, ObjectType,
+    OrderByExpr, Query, Select, SelectItem, SetExpr, Statement, UnaryOperator, Value as SqlValue,
+};
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::ops::Bound;
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+pub use prepared::PreparedStatement;
+
+/// Rows found through an index, paired with their file offsets, and the index used
+type IndexScan = (AccessPath, Vec<(u64, Row)>);
+
+/// Share of a table a range condition is assumed to keep, absent better statistics
+const RANGE_SELECTIVITY: f64 = 1.0 / 3.0;
+
+/// Rows a full scan reads between checks of the statement's deadline
+const DEADLINE_CHECK_INTERVAL: u64 = 1024;
+
+/// Name of the pseudo-column holding a row's offset in the data log, which
+/// identifies it until it is deleted or the log is compacted. Rows read from
+/// a single table carry it after their own columns; `SELECT *` leaves it out.
+const ROWID: &str = "rowid";
+
+/// Description of the `rowid` pseudo-column
+static ROWID_COLUMN: LazyLock<Column> = LazyLock::new(|| Column {
+    name: ROWID.to_string(),
+    column_type: ColumnType::Integer,
+    int_width: IntWidth::Big,
+    nullable: false,
+    unique: true,
+    primary_key: false,
+    auto_increment: false,
+    default: None,
+});
+
+/// A lookup in a composite index answering some of a WHERE clause's conditions
+struct CompositePlan {
+    col_names: Vec<String>,
+    /// Positions of the conditions the lookup answers
+    used: Vec<usize>,
+    /// The values looked up, in index column order
+    values: Vec<Value>,
+    estimated_rows: f64,
 }
 
-impl QueryExecutor {
-    pub fn new(storage: BitcaskStorage) -> Self {
-        QueryExecutor { storage }
+pub struct QueryExecutor<S: Storage = BitcaskStorage> {
+    pub storage: S,
+    /// Values bound to the placeholders of the prepared statement being executed
+    params: Vec<Value>,
+    /// How the statement being executed found the rows it read
+    access_path: Option<AccessPath>,
+    /// Longest a statement may run before failing with `DbError::Timeout`
+    timeout: Option<Duration>,
+    /// Most rows a statement's full scans may read before failing with
+    /// `DbError::Timeout`
+    max_rows_scanned: Option<u64>,
+    /// When the statement being executed has to finish by
+    deadline: Option<Instant>,
+    /// Rows read so far by the full scans of the statement being executed
+    rows_scanned: Cell<u64>,
+    /// AUTO_INCREMENT values of the rows the statement being executed inserted
+    inserted_ids: Vec<i64>,
+}
+
+impl<S: Storage> QueryExecutor<S> {
+    pub fn new(storage: S) -> Self {
+        QueryExecutor {
+            storage,
+            params: Vec::new(),
+            access_path: None,
+            timeout: None,
+            max_rows_scanned: None,
+            deadline: None,
+            rows_scanned: Cell::new(0),
+            inserted_ids: Vec::new(),
+        }
+    }
+
+    /// Limit how long each statement may run, or lift the limit with `None`.
+    /// The clock is checked while tables are scanned, so a statement may
+    /// overrun by the time it takes to read a batch of rows.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Limit how many rows each statement may read through full table scans,
+    /// or lift the limit with `None`. Rows found through an index don't count.
+    pub fn set_max_rows_scanned(&mut self, max_rows: Option<u64>) {
+        self.max_rows_scanned = max_rows;
     }
 
-    /// Execute a SQL query string
-    pub fn execute(&mut self, sql: &str) -> io::Result<QueryResult> {
+    /// Execute a single SQL statement
+    pub fn execute(&mut self, sql: &str) -> DbResult<QueryResult> {
         let start = Instant::now();
 
-        // Parse SQL
-        let dialect = GenericDialect {};
-        let ast = Parser::parse_sql(&dialect, sql).map_err(|e| {
-            io::Error::new(io::ErrorKind::InvalidInput, format!("Parse error: {}", e))
-        })?;
+        let ast = parse_script(sql)?;
+        match ast.as_slice() {
+            [statement] => self.execute_statement(statement, start),
+            [] => Err(DbError::InvalidInput("No SQL statement found".to_string())),
+            _ => Err(DbError::InvalidInput(
+                "Found more than one SQL statement; use execute_script to run several".to_string(),
+            )),
+        }
+    }
+
+    /// Execute semicolon-separated SQL statements in order, returning a result
+    /// for each. Stops at the first statement that fails; statements before it
+    /// keep their effects.
+    pub fn execute_script(&mut self, sql: &str) -> DbResult<Vec<QueryResult>> {
+        let ast = parse_script(sql)?;
 
-        if ast.is_empty() {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "No SQL statement found",
-            ));
+        let mut results = Vec::with_capacity(ast.len());
+        for statement in &ast {
+            results.push(self.execute_statement(statement, Instant::now())?);
         }
 
-        // Execute the first statement
-        let result = match &ast[0] {
+        Ok(results)
+    }
+
+    /// Execute a parsed statement. `start` is when execution began, for timing.
+    fn execute_statement(
+        &mut self,
+        statement: &Statement,
+        start: Instant,
+    ) -> DbResult<QueryResult> {
+        let mut schema = None;
+        self.access_path = None;
+        self.inserted_ids.clear();
+        self.deadline = self.timeout.map(|timeout| start + timeout);
+        self.rows_scanned.set(0);
+        let result = match statement {
             Statement::CreateTable(create_table) => self.execute_create_table(create_table)?,
-            Statement::Insert(insert) => self.execute_insert(insert)?,
-            Statement::Query(query) => self.execute_query(query)?,
+            Statement::CreateIndex(create_index) => self.execute_create_index(create_index)?,
+            Statement::Insert(insert) => {
+                let (result, returning_schema) = self.execute_insert(insert)?;
+                schema = returning_schema;
+                result
+            }
+            Statement::Query(query) => {
+                let (result_schema, rows) = self.execute_query(query)?;
+                schema = Some(result_schema);
+                ExecutionResult::Selected(rows)
+            }
+            Statement::Delete(delete) => self.execute_delete(delete)?,
+            Statement::ShowTables { filter: None, .. } => {
+                let (tables_schema, rows) = self.show_tables();
+                schema = Some(tables_schema);
+                ExecutionResult::Selected(rows)
+            }
+            Statement::ShowVariable { variable }
+                if variable.len() == 1 && variable[0].value.eq_ignore_ascii_case("stats") =>
+            {
+                let (stats_schema, rows) = self.show_stats()?;
+                schema = Some(stats_schema);
+                ExecutionResult::Selected(rows)
+            }
+            Statement::ExplainTable { table_name, .. } => {
+                let (columns_schema, rows) = self.describe_table(&table_name.to_string())?;
+                schema = Some(columns_schema);
+                ExecutionResult::Selected(rows)
+            }
+            Statement::Explain { statement, .. } => {
+                let lines = self.explain_statement(statement)?;
+                let (plan_schema, rows) = explain::plan_rows(lines);
+                schema = Some(plan_schema);
+                ExecutionResult::Selected(rows)
+            }
+            Statement::Drop {
+                object_type: ObjectType::Table,
+                if_exists,
+                names,
+                ..
+            } => self.execute_drop_table(names, *if_exists)?,
+            Statement::Drop {
+                object_type: ObjectType::Index,
+                if_exists,
+                names,
+                ..
+            } => self.execute_drop_index(names, *if_exists)?,
+            Statement::Truncate { table_names, .. } => {
+                for target in table_names {
+                    self.storage.truncate(&target.name.to_string())?;
+                }
+                ExecutionResult::Truncated
+            }
+            Statement::StartTransaction { .. } => {
+                self.storage.begin_transaction()?;
+                ExecutionResult::TransactionStarted
+            }
+            Statement::Commit { chain: false } => {
+                self.storage.commit()?;
+                ExecutionResult::Committed
+            }
+            Statement::Rollback {
+                chain: false,
+                savepoint: None,
+            } => {
+                self.storage.rollback()?;
+                ExecutionResult::RolledBack
+            }
             _ => {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    "Unsupported SQL statement",
-                ))
+                return Err(DbError::InvalidInput(
+                    "Unsupported SQL statement".to_string(),
+                ));
             }
         };
 
+        // Hand the statement's writes to the OS before reporting success
+        self.storage.flush()?;
+
         let duration = start.elapsed();
 
+        let access_path = self.access_path.take();
         Ok(QueryResult {
             result,
             duration,
-            used_index: false, // We'll update this later
+            used_index: access_path.as_ref().is_some_and(AccessPath::uses_index),
+            access_path,
+            schema,
+            inserted_ids: std::mem::take(&mut self.inserted_ids),
+            rows_scanned: self.rows_scanned.get(),
         })
     }
 
     fn execute_create_table(
         &mut self,
         create_table: &sqlparser::ast::CreateTable,
-    ) -> io::Result<ExecutionResult> {
+    ) -> DbResult<ExecutionResult> {
         let table_name = create_table.name.to_string();
+
+        // IF NOT EXISTS turns an existing table into a no-op
+        if create_table.if_not_exists && self.storage.get_schema(&table_name).is_some() {
+            return Ok(ExecutionResult::Created);
+        }
+
+        // Columns named by a `PRIMARY KEY (column)` table constraint
+        let mut key_columns: Vec<&str> = Vec::new();
+        for constraint in &create_table.constraints {
+            if let sqlparser::ast::TableConstraint::PrimaryKey { columns, .. } = constraint {
+                key_columns.extend(columns.iter().map(|column| column.value.as_str()));
+            }
+        }
+        for key_column in &key_columns {
+            if !create_table.columns.iter().any(|c| c.name.value == *key_column) {
+                return Err(DbError::ColumnNotFound {
+                    table: table_name,
+                    column: key_column.to_string(),
+                });
+            }
+        }
+
         let mut columns = Vec::new();
 
         for col_def in &create_table.columns {
             let col_name = col_def.name.to_string();
+            let serial_width = serial_width(&col_def.data_type);
+            let int_width = match col_def.data_type {
+                sqlparser::ast::DataType::SmallInt(_) => IntWidth::Small,
+                sqlparser::ast::DataType::Int(_) | sqlparser::ast::DataType::Integer(_) => {
+                    IntWidth::Int
+                }
+                _ => serial_width.unwrap_or(IntWidth::Big),
+            };
             let col_type = match col_def.data_type {
                 sqlparser::ast::DataType::Int(_)
                 | sqlparser::ast::DataType::Integer(_)
@@ -73,223 +294,2478 @@ impl QueryExecutor {
                 sqlparser::ast::DataType::Float(_)
                 | sqlparser::ast::DataType::Real
                 | sqlparser::ast::DataType::Double => ColumnType::Float,
+                sqlparser::ast::DataType::Timestamp(_, _)
+                | sqlparser::ast::DataType::Datetime(_)
+                | sqlparser::ast::DataType::Date => ColumnType::Timestamp,
+                sqlparser::ast::DataType::Decimal(info)
+                | sqlparser::ast::DataType::Numeric(info)
+                | sqlparser::ast::DataType::Dec(info) => decimal_type(&col_name, info)?,
+                _ if serial_width.is_some() => ColumnType::Integer,
                 _ => {
-                    return Err(io::Error::new(
-                        io::ErrorKind::InvalidInput,
-                        format!("Unsupported data type: {:?}", col_def.data_type),
-                    ))
+                    return Err(DbError::InvalidInput(format!(
+                        "Unsupported data type: {:?}",
+                        col_def.data_type
+                    )));
+                }
+            };
+
+            // A primary key is implicitly UNIQUE and NOT NULL
+            let primary_key = key_columns.contains(&col_def.name.value.as_str())
+                || col_def.options.iter().any(|option| {
+                    matches!(
+                        option.option,
+                        sqlparser::ast::ColumnOption::Unique {
+                            is_primary: true,
+                            ..
+                        }
+                    )
+                });
+
+            // SERIAL is shorthand for an AUTO_INCREMENT column that is NOT NULL
+            let auto_increment = serial_width.is_some()
+                || col_def.options.iter().any(|option| {
+                    matches!(&option.option, sqlparser::ast::ColumnOption::DialectSpecific(tokens)
+                        if tokens.iter().any(|token| {
+                            let token = token.to_string();
+                            token.eq_ignore_ascii_case("AUTO_INCREMENT")
+                                || token.eq_ignore_ascii_case("AUTOINCREMENT")
+                        }))
+                });
+            if auto_increment && col_type != ColumnType::Integer {
+                return Err(DbError::TypeMismatch(format!(
+                    "AUTO_INCREMENT column '{}' must be an integer, not {:?}",
+                    col_name, col_type
+                )));
+            }
+
+            // Columns are nullable unless declared NOT NULL
+            let nullable = !primary_key
+                && serial_width.is_none()
+                && !col_def
+                    .options
+                    .iter()
+                    .any(|option| matches!(option.option, sqlparser::ast::ColumnOption::NotNull));
+            let unique = primary_key
+                || col_def.options.iter().any(|option| {
+                    matches!(
+                        option.option,
+                        sqlparser::ast::ColumnOption::Unique {
+                            is_primary: false,
+                            ..
+                        }
+                    )
+                });
+
+            let default_expr = col_def.options.iter().find_map(|option| match &option.option {
+                sqlparser::ast::ColumnOption::Default(expr) => Some(expr),
+                _ => None,
+            });
+            let default = match default_expr {
+                Some(expr) => {
+                    let value = self.literal_for_column(expr, &col_type)?;
+                    if !value.matches_type(&col_type) {
+                        return Err(DbError::TypeMismatch(format!(
+                            "Default for column '{}' must be {:?}, got {:?}",
+                            col_name, col_type, value
+                        )));
+                    }
+                    if let Value::Integer(int) = value
+                        && !int_width.contains(int)
+                    {
+                        return Err(DbError::InvalidInput(format!(
+                            "Default for column '{}' is out of range for {}: {}",
+                            col_name,
+                            int_width.sql_name(),
+                            int
+                        )));
+                    }
+                    Some(value)
                 }
+                None => None,
             };
+            if auto_increment && default.is_some() {
+                return Err(DbError::InvalidInput(format!(
+                    "AUTO_INCREMENT column '{}' can't have a default",
+                    col_name
+                )));
+            }
 
             columns.push(Column {
                 name: col_name,
                 column_type: col_type,
+                int_width,
+                nullable,
+                unique,
+                primary_key,
+                auto_increment,
+                default,
             });
         }
 
+        if columns.iter().filter(|column| column.primary_key).count() > 1 {
+            return Err(DbError::InvalidInput(
+                "Primary keys of more than one column are not supported".to_string(),
+            ));
+        }
+        if columns.iter().filter(|column| column.auto_increment).count() > 1 {
+            return Err(DbError::InvalidInput(
+                "A table can have only one AUTO_INCREMENT column".to_string(),
+            ));
+        }
+
         let schema = TableSchema::new(table_name, columns);
         self.storage.create_table(schema)?;
 
         Ok(ExecutionResult::Created)
     }
 
-    fn execute_insert(&mut self, insert: &sqlparser::ast::Insert) -> io::Result<ExecutionResult> {
+    /// Build an index on one column, or a composite index on several.
+    /// `USING BTREE` creates a range index on a single column; otherwise a
+    /// hash index is used, which ignores case for `column COLLATE NOCASE`.
+    /// Index names are not stored.
+    fn execute_create_index(
+        &mut self,
+        create_index: &sqlparser::ast::CreateIndex,
+    ) -> DbResult<ExecutionResult> {
+        let table_name = create_index.table_name.to_string();
+
+        // Each column, and whether it is collated NOCASE
+        let columns = create_index
+            .columns
+            .iter()
+            .map(|column| match &column.expr {
+                Expr::Identifier(ident) => Ok((ident.value.as_str(), false)),
+                Expr::Collate { expr, collation } => match expr.as_ref() {
+                    Expr::Identifier(ident)
+                        if collation.to_string().eq_ignore_ascii_case("nocase") =>
+                    {
+                        Ok((ident.value.as_str(), true))
+                    }
+                    _ => Err(DbError::InvalidInput(format!(
+                        "Unsupported collation: {}",
+                        column.expr
+                    ))),
+                },
+                expr => Err(DbError::InvalidInput(format!(
+                    "CREATE INDEX supports only plain columns, got {}",
+                    expr
+                ))),
+            })
+            .collect::<DbResult<Vec<(&str, bool)>>>()?;
+        let col_names: Vec<&str> = columns.iter().map(|(col_name, _)| *col_name).collect();
+
+        if create_index.unique {
+            return Err(DbError::InvalidInput(
+                "CREATE UNIQUE INDEX is not supported; declare the column UNIQUE instead"
+                    .to_string(),
+            ));
+        }
+
+        let range = match &create_index.using {
+            None => false,
+            Some(using) if using.value.eq_ignore_ascii_case("hash") => false,
+            Some(using) if using.value.eq_ignore_ascii_case("btree") => true,
+            Some(using) => {
+                return Err(DbError::InvalidInput(format!(
+                    "Unsupported index type: {}",
+                    using.value
+                )));
+            }
+        };
+
+        let result = match (range, columns.as_slice()) {
+            (false, [(col_name, true)]) => {
+                self.storage.create_case_insensitive_index(&table_name, col_name)
+            }
+            (_, columns) if columns.iter().any(|(_, nocase)| *nocase) => {
+                return Err(DbError::InvalidInput(
+                    "COLLATE NOCASE is supported only for a hash index on one column".to_string(),
+                ));
+            }
+            (true, [(col_name, _)]) => self.storage.create_range_index(&table_name, col_name),
+            (true, _) => {
+                return Err(DbError::InvalidInput(
+                    "A BTREE index supports exactly one column".to_string(),
+                ));
+            }
+            (false, _) => self.storage.create_index(&table_name, &col_names),
+        };
+
+        match result {
+            // IF NOT EXISTS turns an existing index into a no-op
+            Err(DbError::AlreadyExists(_)) if create_index.if_not_exists => {}
+            result => result?,
+        }
+
+        Ok(ExecutionResult::IndexCreated)
+    }
+
+    fn execute_drop_table(
+        &mut self,
+        names: &[ObjectName],
+        if_exists: bool,
+    ) -> DbResult<ExecutionResult> {
+        for name in names {
+            let table_name = name.to_string();
+
+            // IF EXISTS turns a missing table into a no-op
+            if if_exists && self.storage.get_schema(&table_name).is_none() {
+                continue;
+            }
+
+            self.storage.drop_table(&table_name)?;
+        }
+
+        Ok(ExecutionResult::Dropped)
+    }
+
+    /// Drop indexes named as `table.column`, since index names are not stored
+    fn execute_drop_index(
+        &mut self,
+        names: &[ObjectName],
+        if_exists: bool,
+    ) -> DbResult<ExecutionResult> {
+        for name in names {
+            let [table, column] = name.0.as_slice() else {
+                return Err(DbError::InvalidInput(format!(
+                    "DROP INDEX expects table.column, got '{}'",
+                    name
+                )));
+            };
+
+            match self.storage.drop_index(&table.value, &column.value) {
+                // IF EXISTS turns a missing index into a no-op
+                Err(DbError::IndexNotFound(_)) if if_exists => {}
+                result => result?,
+            }
+        }
+
+        Ok(ExecutionResult::IndexDropped)
+    }
+
+    /// Insert the rows of a VALUES list. With RETURNING, the result is the
+    /// listed columns of each inserted row as stored, defaults and
+    /// AUTO_INCREMENT values included, along with a schema describing them.
+    fn execute_insert(
+        &mut self,
+        insert: &sqlparser::ast::Insert,
+    ) -> DbResult<(ExecutionResult, Option<TableSchema>)> {
         let table_name = insert.table_name.to_string();
 
         let values = if let Some(source) = &insert.source {
             match &source.body.as_ref() {
                 SetExpr::Values(values) => &values.rows,
                 _ => {
-                    return Err(io::Error::new(
-                        io::ErrorKind::InvalidInput,
-                        "Unsupported INSERT syntax",
-                    ))
+                    return Err(DbError::InvalidInput(
+                        "Unsupported INSERT syntax".to_string(),
+                    ));
                 }
             }
         } else {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "No values in INSERT",
-            ));
+            return Err(DbError::InvalidInput("No values in INSERT".to_string()));
+        };
+
+        let schema = self
+            .storage
+            .get_schema(&table_name)
+            .cloned()
+            .ok_or_else(|| DbError::TableNotFound(table_name.to_string()))?;
+
+        // Map each VALUES position to a schema column; without a column
+        // list the values are in schema order
+        let target_columns: Vec<usize> = if insert.columns.is_empty() {
+            (0..schema.columns.len()).collect()
+        } else {
+            let mut target_columns = Vec::new();
+            for ident in &insert.columns {
+                let col_idx = self.resolve_column(&ident.value, &schema)?;
+                if col_idx == schema.columns.len() {
+                    return Err(DbError::InvalidInput(format!(
+                        "Cannot insert into '{}', which is set by the database",
+                        ident.value
+                    )));
+                }
+                if target_columns.contains(&col_idx) {
+                    return Err(DbError::InvalidInput(format!(
+                        "Column '{}' specified more than once",
+                        ident.value
+                    )));
+                }
+                target_columns.push(col_idx);
+            }
+            target_columns
         };
 
-        let mut inserted_count = 0;
+        // Omitted columns take their default, so a NOT NULL column needs one.
+        // An AUTO_INCREMENT column is numbered instead.
+        for (col_idx, column) in schema.columns.iter().enumerate() {
+            if !target_columns.contains(&col_idx)
+                && !column.nullable
+                && column.default.is_none()
+                && !column.auto_increment
+            {
+                return Err(DbError::SchemaViolation(format!(
+                    "Column '{}' is NOT NULL and has no default",
+                    column.name
+                )));
+            }
+        }
+
+        // Build every row before writing any, so a bad row leaves the table untouched
+        let mut rows = Vec::with_capacity(values.len());
+        for (i, value_row) in values.iter().enumerate() {
+            let row = self
+                .build_insert_row(value_row, &schema, &target_columns)
+                .map_err(|e| e.in_row(i))?;
+            rows.push(row);
+        }
+
+        // Check the RETURNING list before anything is written
+        if let Some(returning) = &insert.returning {
+            self.resolve_projection(returning, &schema)?;
+        }
+
+        // Number the rows first so the ids can be reported
+        self.inserted_ids = self.storage.assign_ids(&table_name, &mut rows)?;
+        let returned_rows = insert.returning.as_ref().map(|_| rows.clone());
+
+        // Written as one batch, which checks every row before writing any
+        let inserted_count = rows.len();
+        let offsets = self.storage.insert_batch(&table_name, rows)?;
+
+        match (&insert.returning, returned_rows) {
+            (Some(returning), Some(mut rows)) => {
+                for (row, offset) in rows.iter_mut().zip(offsets) {
+                    push_rowid(row, offset);
+                }
+                let (result_schema, rows) = self.project_rows(returning, &schema, rows)?;
+                Ok((ExecutionResult::Selected(rows), Some(result_schema)))
+            }
+            _ => Ok((ExecutionResult::Inserted(inserted_count), None)),
+        }
+    }
+
+    /// Turn one VALUES list of an INSERT into a full row, with the columns it
+    /// leaves out taking their defaults. An explicit NULL is stored as NULL
+    /// even where the column has a default; `validate_row` rejects it for a
+    /// NOT NULL column, unless an AUTO_INCREMENT column fills it in first.
+    fn build_insert_row(
+        &self,
+        value_row: &[Expr],
+        schema: &TableSchema,
+        target_columns: &[usize],
+    ) -> DbResult<Row> {
+        if value_row.len() != target_columns.len() {
+            return Err(DbError::SchemaViolation(format!(
+                "INSERT has {} values but {} columns",
+                value_row.len(),
+                target_columns.len()
+            )));
+        }
 
-        for value_row in values {
-            let mut row_values = Vec::new();
+        // Start from the defaults, then fill in the provided values
+        let mut row_values: Vec<Value> = schema
+            .columns
+            .iter()
+            .map(|column| column.default.clone().unwrap_or(Value::Null))
+            .collect();
 
-            for expr in value_row {
-                let value = self.expr_to_value(expr)?;
-                row_values.push(value);
+        for (expr, &col_idx) in value_row.iter().zip(target_columns) {
+            // The DEFAULT keyword keeps the column's default
+            if is_default_keyword(expr) {
+                continue;
             }
 
-            let row = Row::new(row_values);
-            self.storage.insert(&table_name, row)?;
-            inserted_count += 1;
+            // Column types let literals like date strings convert to the column's type
+            let column_type = &schema.columns[col_idx].column_type;
+            row_values[col_idx] = self.literal_for_column(expr, column_type)?;
         }
 
-        Ok(ExecutionResult::Inserted(inserted_count))
+        Ok(Row::new(row_values))
     }
 
-    fn execute_query(&mut self, query: &Query) -> io::Result<ExecutionResult> {
+    /// Run a SELECT, returning the result rows and a schema describing their columns
+    fn execute_query(&mut self, query: &Query) -> DbResult<(TableSchema, Vec<Row>)> {
         let select = match query.body.as_ref() {
             SetExpr::Select(select) => select,
-            _ => {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    "Unsupported query type",
-                ))
-            }
+            _ => return Err(DbError::InvalidInput("Unsupported query type".to_string())),
+        };
+
+        let order_by = query
+            .order_by
+            .as_ref()
+            .map(|order_by| order_by.exprs.as_slice())
+            .unwrap_or_default();
+
+        let offset = match &query.offset {
+            Some(offset) => self.expr_to_count(&offset.value, "OFFSET")?,
+            None => 0,
         };
+        let limit = match &query.limit {
+            Some(limit) => Some(self.expr_to_count(limit, "LIMIT")?),
+            None => None,
+        };
+
+        // The SELECT may stop reading once it has the rows OFFSET and LIMIT keep
+        let row_limit = limit.map(|limit| offset.saturating_add(limit));
+        let (schema, rows) = self.execute_select(select, order_by, row_limit)?;
+
+        // Apply OFFSET then LIMIT after filtering and ordering
+
+        if offset == 0 && limit.is_none() {
+            return Ok((schema, rows));
+        }
+
+        let rows = rows
+            .into_iter()
+            .skip(offset)
+            .take(limit.unwrap_or(usize::MAX))
+            .collect();
+        Ok((schema, rows))
+    }
 
-        self.execute_select(select)
+    /// Evaluate a LIMIT/OFFSET expression to a non-negative row count
+    fn expr_to_count(&self, expr: &Expr, clause: &str) -> DbResult<usize> {
+        match self.expr_to_value(expr)? {
+            Value::Integer(n) if n >= 0 => Ok(n as usize),
+            other => Err(DbError::InvalidInput(format!(
+                "{} must be a non-negative integer, got {:?}",
+                clause, other
+            ))),
+        }
     }
 
-    fn execute_select(&mut self, select: &Select) -> io::Result<ExecutionResult> {
+    /// Run a SELECT. Only the first `row_limit` result rows are needed, if
+    /// given; more may be returned when the query can't stop early.
+    fn execute_select(
+        &mut self,
+        select: &Select,
+        order_by: &[OrderByExpr],
+        row_limit: Option<usize>,
+    ) -> DbResult<(TableSchema, Vec<Row>)> {
         // Get table name
         if select.from.is_empty() {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "No table specified",
-            ));
+            return Err(DbError::InvalidInput("No table specified".to_string()));
         }
+        // Groups aren't filtered, so refuse HAVING rather than ignore it
+        if let Some(having) = &select.having {
+            return Err(DbError::InvalidInput(format!(
+                "HAVING is not supported: {}",
+                having
+            )));
+        }
+
+        let (schema, mut rows) = if select.from[0].joins.is_empty() {
+            let table_name = select.from[0].relation.to_string();
 
-        let table_name = select.from[0].relation.to_string();
+            // COUNT(*) over a whole table is answered from the stored row count
+            if let Some((schema, row)) = self.count_all_rows(select, &table_name)? {
+                println!("  [Using stored row count]");
+                self.access_path = Some(AccessPath::RowCount);
+                return Ok((schema, vec![row]));
+            }
+
+            let scan_limit = match self.storage.get_schema(&table_name) {
+                Some(schema) => self.scan_limit(select, order_by, schema, row_limit)?,
+                None => None,
+            };
+
+            // Plain columns of every row are projected while the table is read
+            if let Some((schema, rows)) =
+                self.projected_scan(select, &table_name, order_by, scan_limit)?
+            {
+                self.access_path = Some(AccessPath::FullScan);
+                return Ok((schema, apply_distinct(select, rows)?));
+            }
+
+            // Get rows based on WHERE clause
+            let (access_path, rows) = if let Some(ref where_clause) = select.selection {
+                let (access_path, rows) =
+                    self.execute_where(&table_name, where_clause, scan_limit)?;
+                (access_path, rows.into_iter().map(|(_, row)| row).collect())
+            } else {
+                // No WHERE clause - full scan
+                let rows = self.scan_table(&table_name, scan_limit)?;
+                let rows = rows
+                    .into_iter()
+                    .map(|(offset, mut row)| {
+                        push_rowid(&mut row, offset);
+                        row
+                    })
+                    .collect();
+                (AccessPath::FullScan, rows)
+            };
+            self.access_path = Some(access_path);
 
-        // Get rows based on WHERE clause
-        let rows = if let Some(ref where_clause) = select.selection {
-            self.execute_where(&table_name, where_clause)?
+            let schema = self
+                .storage
+                .get_schema(&table_name)
+                .cloned()
+                .ok_or_else(|| DbError::TableNotFound(table_name.to_string()))?;
+            (schema, rows)
         } else {
-            // No WHERE clause - full scan
-            self.storage.scan(&table_name)?
+            // Joined rows have no indexes, so WHERE is filtered in memory
+            let (schema, mut rows) = self.join_tables(&select.from[0])?;
+            self.access_path = Some(AccessPath::FullScan);
+            if let Some(ref where_clause) = select.selection {
+                self.check_columns(where_clause, &schema)?;
+                let condition = self.compile_condition(where_clause, &schema)?;
+                rows.retain(|row| condition.matches(row));
+            }
+            (schema, rows)
         };
+        let schema = &schema;
 
-        Ok(ExecutionResult::Selected(rows))
-    }
+        // Grouped and aggregate queries emit one row per group
+        let group_by = self.resolve_group_by(&select.group_by, schema)?;
+        if let Some(columns) =
+            self.resolve_grouped_projection(&select.projection, &group_by, schema)?
+        {
+            let mut groups: Vec<(Row, Vec<Row>)> = aggregate::group_rows(rows, &group_by)
+                .into_iter()
+                .map(|(key, group)| (Row::new(key), group))
+                .collect();
 
-    fn execute_where(&mut self, table_name: &str, expr: &Expr) -> io::Result<Vec<Row>> {
-        // Try to use index if possible
-        if let Expr::BinaryOp { left, op, right } = expr {
-            if matches!(op, sqlparser::ast::BinaryOperator::Eq) {
-                // Check if left is a column and right is a value
-                if let (Expr::Identifier(col_ident), value_expr) = (left.as_ref(), right.as_ref()) {
-                    let col_name = col_ident.value.as_str();
-                    let value = self.expr_to_value(value_expr)?;
+            // Without GROUP BY, aggregates always produce exactly one row
+            if group_by.is_empty() && groups.is_empty() {
+                groups.push((Row::new(Vec::new()), Vec::new()));
+            }
 
-                    // Try index lookup first
-                    match self.storage.index_lookup(table_name, col_name, &value) {
-                        Ok(rows) => {
-                            println!("  [Using index on {}]", col_name);
-                            return Ok(rows);
-                        }
-                        Err(_) => {
-                            // No index, fall through to scan
-                            println!("  [No index on {}, using full scan]", col_name);
-                        }
-                    }
-                }
+            if !order_by.is_empty() {
+                let sort_keys = self.resolve_sort_keys(order_by, schema, Some(&group_by))?;
+                groups.sort_by(|(a, _), (b, _)| compare_rows(a, b, &sort_keys));
+            }
+
+            let mut result = Vec::new();
+            for (key, group) in &groups {
+                let values = columns
+                    .iter()
+                    .map(|column| match column {
+                        GroupedColumn::Key(i) => Ok(key.get(*i).cloned().unwrap_or(Value::Null)),
+                        GroupedColumn::Aggregate(aggregate) => aggregate.evaluate(group),
+                    })
+                    .collect::<DbResult<Vec<Value>>>()?;
+                result.push(Row::new(values));
             }
+
+            let result_schema =
+                grouped_result_schema(&select.projection, &columns, &group_by, schema);
+            return Ok((result_schema, apply_distinct(select, result)?));
+        }
+
+        // Sort before projecting so ORDER BY can use any column
+        if !order_by.is_empty() {
+            let sort_keys = self.resolve_sort_keys(order_by, schema, None)?;
+            rows.sort_by(|a, b| compare_rows(a, b, &sort_keys));
         }
 
-        // No index available - do full scan with filter
-        let all_rows = self.storage.scan(table_name)?;
-        let schema = self.storage.get_schema(table_name).ok_or_else(|| {
-            io::Error::new(io::ErrorKind::NotFound, "Table schema not found")
-        })?;
+        let (result_schema, rows) = self.project_rows(&select.projection, schema, rows)?;
+        Ok((result_schema, apply_distinct(select, rows)?))
+    }
+
+    /// Keep only the columns of a SELECT or RETURNING list, in list order
+    fn project_rows(
+        &self,
+        projection: &[SelectItem],
+        schema: &TableSchema,
+        rows: Vec<Row>,
+    ) -> DbResult<(TableSchema, Vec<Row>)> {
+        let Some(projected) = self.resolve_projection(projection, schema)? else {
+            // Drop the rowid that rows read from a table carry
+            let rows = rows
+                .into_iter()
+                .map(|mut row| {
+                    row.values.truncate(schema.columns.len());
+                    row
+                })
+                .collect();
+            return Ok((schema.clone(), rows));
+        };
 
-        let filtered: Vec<Row> = all_rows
+        let columns = projected.iter().map(|(_, column)| column.clone()).collect();
+        let outputs: Vec<CompiledExpr> = projected
+            .iter()
+            .map(|(projection, _)| match projection {
+                Projection::Column(i) => Ok(CompiledExpr::Column(*i)),
+                Projection::Computed(expr) => self.compile(expr, schema),
+            })
+            .collect::<DbResult<_>>()?;
+        let rows = rows
             .into_iter()
-            .filter(|row| self.evaluate_expr(expr, row, schema))
+            .map(|row| {
+                let values = outputs.iter().map(|output| output.value(&row)).collect();
+                Row::new(values)
+            })
             .collect();
+        Ok((TableSchema::new(schema.name.clone(), columns), rows))
+    }
+
+    /// How many matching rows a query with a LIMIT needs from its table.
+    /// Without ORDER BY, DISTINCT or grouping, the first `row_limit` rows
+    /// that match are the result, so the table needn't be read any further.
+    /// Returns `None` when every matching row is needed.
+    fn scan_limit(
+        &self,
+        select: &Select,
+        order_by: &[OrderByExpr],
+        schema: &TableSchema,
+        row_limit: Option<usize>,
+    ) -> DbResult<Option<usize>> {
+        let Some(row_limit) = row_limit else {
+            return Ok(None);
+        };
+        if !order_by.is_empty() || select.distinct.is_some() || select.having.is_some() {
+            return Ok(None);
+        }
+
+        let group_by = self.resolve_group_by(&select.group_by, schema)?;
+        if !group_by.is_empty()
+            || self
+                .resolve_grouped_projection(&select.projection, &group_by, schema)?
+                .is_some()
+        {
+            return Ok(None);
+        }
 
-        Ok(filtered)
+        Ok(Some(row_limit))
     }
 
-    fn evaluate_expr(&self, expr: &Expr, row: &Row, schema: &TableSchema) -> bool {
-        match expr {
-            Expr::BinaryOp { left, op, right } => {
-                let left_val = self.eval_expr_to_value(left, row, schema);
-                let right_val = self.eval_expr_to_value(right, row, schema);
+    /// Answer a query whose SELECT list is only `COUNT(*)`, with no WHERE
+    /// or GROUP BY, from the table's row count. Returns `None` for any other query.
+    fn count_all_rows(
+        &self,
+        select: &Select,
+        table_name: &str,
+    ) -> DbResult<Option<(TableSchema, Row)>> {
+        if select.selection.is_some() {
+            return Ok(None);
+        }
 
-                match op {
-                    sqlparser::ast::BinaryOperator::Eq => left_val == right_val,
-                    sqlparser::ast::BinaryOperator::Gt => match (&left_val, &right_val) {
-                        (Value::Integer(l), Value::Integer(r)) => l > r,
-                        (Value::Float(l), Value::Float(r)) => l > r,
-                        _ => false,
-                    },
-                    sqlparser::ast::BinaryOperator::Lt => match (&left_val, &right_val) {
-                        (Value::Integer(l), Value::Integer(r)) => l < r,
-                        (Value::Float(l), Value::Float(r)) => l < r,
-                        _ => false,
-                    },
-                    _ => false,
-                }
+        let Some(schema) = self.storage.get_schema(table_name) else {
+            return Ok(None);
+        };
+
+        let group_by = self.resolve_group_by(&select.group_by, schema)?;
+        if !group_by.is_empty() {
+            return Ok(None);
+        }
+
+        let Some(columns) = self.resolve_grouped_projection(&select.projection, &group_by, schema)?
+        else {
+            return Ok(None);
+        };
+        if !columns
+            .iter()
+            .all(|column| matches!(column, GroupedColumn::Aggregate(Aggregate::CountStar)))
+        {
+            return Ok(None);
+        }
+
+        let Some(row_count) = self.storage.row_count(table_name) else {
+            return Ok(None);
+        };
+        let values = columns.iter().map(|_| Value::Integer(row_count as i64)).collect();
+        let result_schema = grouped_result_schema(&select.projection, &columns, &group_by, schema);
+        Ok(Some((result_schema, Row::new(values))))
+    }
+
+    /// Answer a query that selects plain columns of every row, with no WHERE,
+    /// ORDER BY or grouping, by keeping only those columns as the table is
+    /// scanned. Returns `None` for any other query.
+    fn projected_scan(
+        &self,
+        select: &Select,
+        table_name: &str,
+        order_by: &[OrderByExpr],
+        scan_limit: Option<usize>,
+    ) -> DbResult<Option<(TableSchema, Vec<Row>)>> {
+        if select.selection.is_some() || select.having.is_some() || !order_by.is_empty() {
+            return Ok(None);
+        }
+
+        let Some(schema) = self.storage.get_schema(table_name) else {
+            return Ok(None);
+        };
+
+        let group_by = self.resolve_group_by(&select.group_by, schema)?;
+        if !group_by.is_empty()
+            || self
+                .resolve_grouped_projection(&select.projection, &group_by, schema)?
+                .is_some()
+        {
+            return Ok(None);
+        }
+
+        let Some(projected) = self.resolve_projection(&select.projection, schema)? else {
+            return Ok(None);
+        };
+        let mut col_indices = Vec::with_capacity(projected.len());
+        for (projection, _) in &projected {
+            match projection {
+                Projection::Column(i) if *i < schema.columns.len() => col_indices.push(*i),
+                // Computed columns and the rowid aren't stored columns
+                _ => return Ok(None),
             }
-            _ => true,
         }
+
+        let columns = projected.into_iter().map(|(_, column)| column).collect();
+        let result_schema = TableSchema::new(schema.name.clone(), columns);
+        let rows = self.storage.scan_columns_iter(table_name, &col_indices)?;
+        let rows = self
+            .guard_scan(rows)
+            .take(scan_limit.unwrap_or(usize::MAX))
+            .map(|entry| entry.map(|(_, row)| row))
+            .collect::<DbResult<_>>()?;
+        Ok(Some((result_schema, rows)))
     }
 
-    fn eval_expr_to_value(&self, expr: &Expr, row: &Row, schema: &TableSchema) -> Value {
-        match expr {
-            Expr::Identifier(ident) => {
-                let col_name = &ident.value;
-                if let Some(col_idx) = schema.get_column_index(col_name) {
-                    row.get(col_idx).cloned().unwrap_or(Value::Null)
-                } else {
-                    Value::Null
+    /// Resolve ORDER BY keys to (position, ascending) pairs. Positions index
+    /// into the schema's columns, or into `group_by` for grouped queries.
+    fn resolve_sort_keys(
+        &self,
+        order_by: &[OrderByExpr],
+        schema: &TableSchema,
+        group_by: Option<&[usize]>,
+    ) -> DbResult<Vec<(usize, bool)>> {
+        let mut sort_keys = Vec::new();
+
+        for order_expr in order_by {
+            let col_name = match column_ref(&order_expr.expr) {
+                Some(col_name) => col_name,
+                None => {
+                    let other = &order_expr.expr;
+                    return Err(DbError::InvalidInput(format!(
+                        "Unsupported ORDER BY expression: {}",
+                        other
+                    )));
+                }
+            };
+
+            let col_idx = self.resolve_column(&col_name, schema)?;
+
+            // Grouped rows can only be ordered by their GROUP BY columns
+            let position = match group_by {
+                Some(group_by) => group_by.iter().position(|&i| i == col_idx).ok_or_else(|| {
+                    DbError::InvalidInput(format!(
+                        "ORDER BY column '{}' must appear in GROUP BY",
+                        col_name
+                    ))
+                })?,
+                None => col_idx,
+            };
+
+            sort_keys.push((position, order_expr.asc.unwrap_or(true)));
+        }
+
+        Ok(sort_keys)
+    }
+
+    /// Resolve the GROUP BY clause to schema column indices
+    fn resolve_group_by(
+        &self,
+        group_by: &GroupByExpr,
+        schema: &TableSchema,
+    ) -> DbResult<Vec<usize>> {
+        let exprs = match group_by {
+            GroupByExpr::Expressions(exprs, _) => exprs,
+            GroupByExpr::All(_) => {
+                return Err(DbError::InvalidInput(
+                    "GROUP BY ALL is not supported".to_string(),
+                ));
+            }
+        };
+
+        exprs
+            .iter()
+            .map(|expr| match column_ref(expr) {
+                Some(col_name) => self.resolve_column(&col_name, schema),
+                None => Err(DbError::InvalidInput(format!(
+                    "Unsupported GROUP BY expression: {}",
+                    expr
+                ))),
+            })
+            .collect()
+    }
+
+    /// Resolve the SELECT list of a grouped or aggregate query.
+    /// Returns `None` when the query has neither GROUP BY nor aggregates.
+    fn resolve_grouped_projection(
+        &self,
+        projection: &[SelectItem],
+        group_by: &[usize],
+        schema: &TableSchema,
+    ) -> DbResult<Option<Vec<GroupedColumn>>> {
+        let mut columns = Vec::new();
+        let mut has_aggregate = false;
+
+        for item in projection {
+            let Some((expr, _)) = select_item_expr(item) else {
+                columns.push(Err(item));
+                continue;
+            };
+
+            if let Some(aggregate) = Aggregate::from_expr(expr, schema)? {
+                has_aggregate = true;
+                columns.push(Ok(GroupedColumn::Aggregate(aggregate)));
+                continue;
+            }
+
+            // Plain columns must be one of the GROUP BY columns
+            let key_position = match column_ref(expr) {
+                Some(col_name) => {
+                    let col_idx = self.resolve_column(&col_name, schema)?;
+                    group_by.iter().position(|&i| i == col_idx)
                 }
+                None => None,
+            };
+
+            match key_position {
+                Some(position) => columns.push(Ok(GroupedColumn::Key(position))),
+                None => columns.push(Err(item)),
             }
-            Expr::Value(sql_val) => self.sql_value_to_value(sql_val).unwrap_or(Value::Null),
-            _ => Value::Null,
         }
+
+        if group_by.is_empty() && !has_aggregate {
+            return Ok(None);
+        }
+
+        columns
+            .into_iter()
+            .map(|column| {
+                column.map_err(|item| {
+                    DbError::InvalidInput(format!(
+                        "'{}' must appear in GROUP BY or be used in an aggregate",
+                        item
+                    ))
+                })
+            })
+            .collect::<DbResult<Vec<GroupedColumn>>>()
+            .map(Some)
+    }
+
+    /// Get the position of a column within a table's schema
+    fn resolve_column(&self, col_name: &str, schema: &TableSchema) -> DbResult<usize> {
+        find_column(schema, col_name).ok_or_else(|| unresolved_column(schema, col_name))
     }
 
-    fn expr_to_value(&self, expr: &Expr) -> io::Result<Value> {
+    /// Ensure every column an expression refers to exists, since evaluating a
+    /// missing one quietly gives NULL and a typo would match no rows
+    fn check_columns(&self, expr: &Expr, schema: &TableSchema) -> DbResult<()> {
         match expr {
-            Expr::Value(sql_val) => self.sql_value_to_value(sql_val),
-            _ => Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "Expected literal value",
-            )),
+            Expr::Identifier(_) | Expr::CompoundIdentifier(_) => {
+                let col_name = column_ref(expr).unwrap_or_default();
+                self.resolve_column(&col_name, schema).map(|_| ())
+            }
+            Expr::Nested(inner)
+            | Expr::UnaryOp { expr: inner, .. }
+            | Expr::IsNull(inner)
+            | Expr::IsNotNull(inner) => self.check_columns(inner, schema),
+            Expr::BinaryOp { left, right, .. } => {
+                self.check_columns(left, schema)?;
+                self.check_columns(right, schema)
+            }
+            Expr::Like { expr: inner, pattern, .. } => {
+                self.check_columns(inner, schema)?;
+                self.check_columns(pattern, schema)
+            }
+            Expr::InList { expr: inner, list, .. } => {
+                self.check_columns(inner, schema)?;
+                list.iter().try_for_each(|item| self.check_columns(item, schema))
+            }
+            Expr::Between { expr: inner, low, high, .. } => {
+                self.check_columns(inner, schema)?;
+                self.check_columns(low, schema)?;
+                self.check_columns(high, schema)
+            }
+            Expr::Function(function) => match scalar::ScalarFunction::from_call(function) {
+                Ok((_, arg)) => self.check_columns(arg, schema),
+                Err(_) => Ok(()),
+            },
+            _ => Ok(()),
         }
     }
 
-    fn sql_value_to_value(&self, sql_val: &SqlValue) -> io::Result<Value> {
-        match sql_val {
-            SqlValue::Number(n, _) => {
-                if n.contains('.') {
-                    Ok(Value::Float(n.parse().map_err(|_| {
-                        io::Error::new(io::ErrorKind::InvalidInput, "Invalid float")
-                    })?))
-                } else {
-                    Ok(Value::Integer(n.parse().map_err(|_| {
-                        io::Error::new(io::ErrorKind::InvalidInput, "Invalid integer")
-                    })?))
-                }
+    /// Resolve a SELECT list to how each output column is produced, and a
+    /// description of the column named by its alias if it has one.
+    /// Returns `None` for `SELECT *`, which keeps rows unchanged.
+    fn resolve_projection<'a>(
+        &self,
+        projection: &'a [SelectItem],
+        schema: &TableSchema,
+    ) -> DbResult<Option<Vec<(Projection<'a>, Column)>>> {
+        if let [SelectItem::Wildcard(_)] = projection {
+            return Ok(None);
+        }
+
+        let mut projected = Vec::new();
+
+        for item in projection {
+            if let SelectItem::Wildcard(_) = item {
+                projected.extend(
+                    schema.columns.iter().enumerate().map(|(i, column)| {
+                        (Projection::Column(i), column.clone())
+                    }),
+                );
+                continue;
             }
-            SqlValue::SingleQuotedString(s) | SqlValue::DoubleQuotedString(s) => {
-                Ok(Value::Text(s.clone()))
+
+            match select_item_expr(item) {
+                Some((expr, alias)) => {
+                    let (projection, mut column) = match column_ref(expr) {
+                        Some(col_name) => {
+                            let col_idx = self.resolve_column(&col_name, schema)?;
+                            (Projection::Column(col_idx), column_at(schema, col_idx).clone())
+                        }
+                        None => {
+                            let column_type = self.expr_type(expr, schema)?;
+                            let column = Column {
+                                name: expr.to_string(),
+                                column_type: column_type.unwrap_or(ColumnType::Text),
+                                int_width: IntWidth::Big,
+                                nullable: true,
+                                unique: false,
+                                primary_key: false,
+                                auto_increment: false,
+                                default: None,
+                            };
+                            (Projection::Computed(expr), column)
+                        }
+                    };
+                    if let Some(alias) = alias {
+                        column.name = alias.value.clone();
+                    }
+                    projected.push((projection, column));
+                }
+                None => {
+                    return Err(DbError::InvalidInput(format!(
+                        "Unsupported select item: {}",
+                        item
+                    )));
+                }
             }
-            SqlValue::Null => Ok(Value::Null),
-            _ => Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                format!("Unsupported value type: {:?}", sql_val),
-            )),
         }
+
+        Ok(Some(projected))
     }
-}
 
-#[derive(Debug)]
-pub enum ExecutionResult {
-    Created,
-    Inserted(usize),
-    Selected(Vec<Row>),
-}
+    fn execute_delete(&mut self, delete: &sqlparser::ast::Delete) -> DbResult<ExecutionResult> {
+        let from = match &delete.from {
+            FromTable::WithFromKeyword(from) | FromTable::WithoutKeyword(from) => from,
+        };
 
-#[derive(Debug)]
+        if from.is_empty() {
+            return Err(DbError::InvalidInput("No table specified".to_string()));
+        }
+
+        let table_name = from[0].relation.to_string();
+
+        // Find the rows to delete, along with their offsets
+        let (access_path, rows) = if let Some(ref where_clause) = delete.selection {
+            // An index lookup answers some conditions without compiling them,
+            // so make sure the whole predicate compiles before deleting anything
+            let schema = self
+                .storage
+                .get_schema(&table_name)
+                .ok_or_else(|| DbError::TableNotFound(table_name.to_string()))?;
+            self.compile_condition(where_clause, schema)?;
+            self.execute_where(&table_name, where_clause, None)?
+        } else {
+            // No WHERE clause - delete everything
+            (AccessPath::FullScan, self.scan_table(&table_name, None)?)
+        };
+        self.access_path = Some(access_path);
+
+        let deleted_count = rows.len();
+
+        for (offset, _) in rows {
+            self.storage.delete(&table_name, offset)?;
+        }
+
+        Ok(ExecutionResult::Deleted(deleted_count))
+    }
+
+    /// Find the rows matching a WHERE clause, paired with their file offsets,
+    /// along with how they were found
+    fn execute_where(
+        &self,
+        table_name: &str,
+        expr: &Expr,
+        limit: Option<usize>,
+    ) -> DbResult<(AccessPath, Vec<(u64, Row)>)> {
+        let (access_path, rows) = self.find_where(table_name, expr, limit)?;
+        match access_path {
+            AccessPath::FullScan => println!("  [No usable index, using full scan]"),
+            ref access_path => println!("  [Using {}]", access_path),
+        }
+        Ok((access_path, rows))
+    }
+
+    /// Find the rows matching a WHERE clause like `execute_where`, without
+    /// reporting how. With a `limit`, at most that many rows are returned
+    /// and a full scan stops once it has found them. Each row carries its
+    /// rowid after its columns, so the conditions can use it.
+    fn find_where(
+        &self,
+        table_name: &str,
+        expr: &Expr,
+        limit: Option<usize>,
+    ) -> DbResult<(AccessPath, Vec<(u64, Row)>)> {
+        let limit = limit.unwrap_or(usize::MAX);
+        let schema = self
+            .storage
+            .get_schema(table_name)
+            .ok_or_else(|| DbError::TableNotFound(table_name.to_string()))?;
+        self.check_columns(expr, schema)?;
+
+        // Use an index for one or more AND-ed conditions, and filter the
+        // remaining conditions in memory
+        let mut conjuncts = split_conjuncts(expr);
+        let mut candidates = None;
+
+        // Equality on the primary key matches at most one row, so it beats any other index
+        for i in 0..conjuncts.len() {
+            if let Some(scan) = self.primary_key_scan(table_name, conjuncts[i])? {
+                conjuncts.remove(i);
+                candidates = Some(scan);
+                break;
+            }
+        }
+        if candidates.is_none() {
+            // Otherwise try the usable indexes from the fewest expected rows
+            // up. A composite index wins ties, since it answers more conditions.
+            let composite = self.composite_plan(table_name, &conjuncts);
+            let mut plans: Vec<(f64, Option<usize>)> = conjuncts
+                .iter()
+                .enumerate()
+                .filter_map(|(i, conjunct)| {
+                    Some((self.estimate_rows(table_name, conjunct)?, Some(i)))
+                })
+                .collect();
+            if let Some(plan) = &composite {
+                plans.push((plan.estimated_rows, None));
+            }
+            plans.sort_by(|(a, a_single), (b, b_single)| {
+                a.total_cmp(b).then(a_single.is_some().cmp(&b_single.is_some()))
+            });
+
+            for (_, plan) in plans {
+                match (plan, &composite) {
+                    (None, Some(plan)) => {
+                        candidates = Some(self.composite_scan(table_name, plan)?);
+                        let mut used = plan.used.clone();
+                        used.sort_unstable();
+                        for i in used.into_iter().rev() {
+                            conjuncts.remove(i);
+                        }
+                        break;
+                    }
+                    (Some(i), _) => {
+                        if let Some(scan) = self.index_scan(table_name, conjuncts[i])? {
+                            conjuncts.remove(i);
+                            candidates = Some(scan);
+                            break;
+                        }
+                    }
+                    (None, None) => {}
+                }
+            }
+        }
+
+        // Resolve the remaining conditions' columns once, not for every row
+        let conditions: Vec<CompiledExpr> = conjuncts
+            .iter()
+            .map(|conjunct| self.compile_condition(conjunct, schema))
+            .collect::<DbResult<_>>()?;
+        let matches = |row: &Row| conditions.iter().all(|condition| condition.matches(row));
+
+        let filtered: Vec<(u64, Row)> = match candidates {
+            Some((access_path, rows)) => {
+                let filtered = rows
+                    .into_iter()
+                    .map(|(offset, mut row)| {
+                        push_rowid(&mut row, offset);
+                        (offset, row)
+                    })
+                    .filter(|(_, row)| matches(row))
+                    .take(limit)
+                    .collect();
+                return Ok((access_path, filtered));
+            }
+            None => {
+                // No index available - do full scan with filter, keeping
+                // only the matching rows in memory
+                let mut filtered = Vec::new();
+                let mut rows = self.guard_scan(self.storage.scan_iter(table_name)?);
+                while filtered.len() < limit {
+                    let Some(result) = rows.next() else {
+                        break;
+                    };
+                    let (offset, mut row) = result?;
+                    push_rowid(&mut row, offset);
+                    if matches(&row) {
+                        filtered.push((offset, row));
+                    }
+                }
+                filtered
+            }
+        };
+
+        Ok((AccessPath::FullScan, filtered))
+    }
+
+    /// Read the rows of a table, paired with their offsets, within the
+    /// statement's limits. Reading stops after `limit` rows, if given.
+    fn scan_table(&self, table_name: &str, limit: Option<usize>) -> DbResult<Vec<(u64, Row)>> {
+        self.guard_scan(self.storage.scan_iter(table_name)?)
+            .take(limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+
+    /// Count the rows of a full scan against the statement's row limit and
+    /// deadline, ending the scan with `DbError::Timeout` once either is passed
+    fn guard_scan<'a>(
+        &'a self,
+        rows: S::Scan<'a>,
+    ) -> impl Iterator<Item = DbResult<(u64, Row)>> + 'a {
+        rows.map(move |entry| {
+            let scanned = self.rows_scanned.get() + 1;
+            self.rows_scanned.set(scanned);
+
+            if let Some(max_rows) = self.max_rows_scanned
+                && scanned > max_rows
+            {
+                return Err(DbError::Timeout(format!(
+                    "Query read more than {} rows",
+                    max_rows
+                )));
+            }
+            if scanned.is_multiple_of(DEADLINE_CHECK_INTERVAL)
+                && let (Some(deadline), Some(timeout)) = (self.deadline, self.timeout)
+                && Instant::now() > deadline
+            {
+                return Err(DbError::Timeout(format!(
+                    "Query ran longer than {:?}",
+                    timeout
+                )));
+            }
+
+            entry
+        })
+    }
+
+    /// Answer `primary_key = literal` by reading the one row with that key.
+    /// Returns `None` for any other condition.
+    fn primary_key_scan(&self, table_name: &str, expr: &Expr) -> DbResult<Option<IndexScan>> {
+        let Expr::BinaryOp {
+            left,
+            op: sqlparser::ast::BinaryOperator::Eq,
+            right,
+        } = expr
+        else {
+            return Ok(None);
+        };
+        let Expr::Identifier(col_ident) = left.as_ref() else {
+            return Ok(None);
+        };
+        let is_primary_key = self
+            .storage
+            .get_schema(table_name)
+            .and_then(|schema| schema.get_column(&col_ident.value))
+            .is_some_and(|column| column.primary_key);
+        if !is_primary_key {
+            return Ok(None);
+        }
+        let Ok(value) = self.expr_to_value(right) else {
+            return Ok(None);
+        };
+        let value = self.coerce_to_column(table_name, &col_ident.value, value);
+        if !self.is_comparable_with_column(table_name, &col_ident.value, &value) {
+            return Ok(None);
+        }
+
+        let row = self.storage.get_by_primary_key_with_offset(table_name, &value)?;
+        let access_path = AccessPath::PrimaryKey(col_ident.value.clone());
+        Ok(Some((access_path, row.into_iter().collect())))
+    }
+
+    /// Find the composite index that AND-ed `column = literal` conditions
+    /// cover every column of, preferring the most selective one. Returns
+    /// `None` when no composite index is covered.
+    fn composite_plan(&self, table_name: &str, conjuncts: &[&Expr]) -> Option<CompositePlan> {
+        // The first equality on each column, with its position. Equality
+        // with NULL never matches, so it is left to the in-memory filter.
+        let mut equalities: BTreeMap<&str, (usize, Value)> = BTreeMap::new();
+        for (i, conjunct) in conjuncts.iter().enumerate() {
+            let Expr::BinaryOp {
+                left,
+                op: sqlparser::ast::BinaryOperator::Eq,
+                right,
+            } = conjunct
+            else {
+                continue;
+            };
+            let Expr::Identifier(col_ident) = left.as_ref() else {
+                continue;
+            };
+            let Ok(value) = self.expr_to_value(right) else {
+                continue;
+            };
+            if value == Value::Null {
+                continue;
+            }
+            let value = self.coerce_to_column(table_name, &col_ident.value, value);
+            equalities.entry(col_ident.value.as_str()).or_insert((i, value));
+        }
+
+        self.storage
+            .composite_index_columns(table_name)
+            .into_iter()
+            .filter(|col_names| col_names.iter().all(|c| equalities.contains_key(c.as_str())))
+            .filter_map(|col_names| {
+                let stats = self.storage.composite_index_stats(table_name, col_names)?;
+                let (used, values) = col_names
+                    .iter()
+                    .map(|col_name| equalities[col_name.as_str()].clone())
+                    .unzip();
+                Some(CompositePlan {
+                    col_names: col_names.to_vec(),
+                    used,
+                    values,
+                    estimated_rows: stats.avg_rows_per_key,
+                })
+            })
+            .min_by(|a, b| a.estimated_rows.total_cmp(&b.estimated_rows))
+    }
+
+    /// Fetch the rows a composite plan describes from its index
+    fn composite_scan(&self, table_name: &str, plan: &CompositePlan) -> DbResult<IndexScan> {
+        let rows = self.storage.composite_lookup_with_offsets(
+            table_name,
+            &plan.col_names,
+            &plan.values,
+        )?;
+        Ok((AccessPath::CompositeIndex(plan.col_names.clone()), rows))
+    }
+
+    /// Estimate how many rows `index_scan` would return for a condition,
+    /// from the statistics of the index it would use. Equality finds a key's
+    /// average number of rows; a range is guessed to keep a third of the table.
+    /// Returns `None` when no index applies.
+    fn estimate_rows(&self, table_name: &str, expr: &Expr) -> Option<f64> {
+        let equality = |col_name: &str| {
+            self.storage
+                .index_stats(table_name, col_name)
+                .or_else(|| self.storage.range_index_stats(table_name, col_name))
+                .map(|stats| stats.avg_rows_per_key)
+        };
+        let range = |col_name: &str| {
+            self.storage.range_index_stats(table_name, col_name)?;
+            let row_count = self.storage.row_count(table_name)? as f64;
+            Some(row_count * RANGE_SELECTIVITY)
+        };
+
+        let disjuncts = split_disjuncts(expr);
+        if disjuncts.len() > 1 {
+            return disjuncts
+                .into_iter()
+                .map(|disjunct| self.estimate_rows(table_name, disjunct))
+                .sum();
+        }
+
+        match expr {
+            Expr::InList {
+                expr: inner,
+                list,
+                negated: false,
+            } => match inner.as_ref() {
+                Expr::Identifier(col_ident) => {
+                    equality(&col_ident.value).map(|rows| rows * list.len() as f64)
+                }
+                _ => None,
+            },
+            Expr::Between {
+                expr: inner,
+                negated: false,
+                ..
+            }
+            | Expr::IsNotNull(inner) => match inner.as_ref() {
+                Expr::Identifier(col_ident) => range(&col_ident.value),
+                _ => None,
+            },
+            // NULL is a single key of the range index
+            Expr::IsNull(inner) => match inner.as_ref() {
+                Expr::Identifier(col_ident) => self
+                    .storage
+                    .range_index_stats(table_name, &col_ident.value)
+                    .map(|stats| stats.avg_rows_per_key),
+                _ => None,
+            },
+            Expr::BinaryOp { left, op, .. } => {
+                let Expr::Identifier(col_ident) = left.as_ref() else {
+                    return None;
+                };
+                match op {
+                    sqlparser::ast::BinaryOperator::Eq => equality(&col_ident.value),
+                    sqlparser::ast::BinaryOperator::Gt
+                    | sqlparser::ast::BinaryOperator::GtEq
+                    | sqlparser::ast::BinaryOperator::Lt
+                    | sqlparser::ast::BinaryOperator::LtEq => range(&col_ident.value),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Try to answer a single `column <op> literal` condition from an index.
+    /// Returns `None` when no index applies.
+    fn index_scan(&self, table_name: &str, expr: &Expr) -> DbResult<Option<IndexScan>> {
+        let disjuncts = split_disjuncts(expr);
+        if disjuncts.len() > 1 {
+            return self.index_scan_union(table_name, &disjuncts);
+        }
+
+        if let Expr::InList {
+            expr: inner,
+            list,
+            negated: false,
+        } = expr
+        {
+            return self.index_scan_in_list(table_name, inner, list);
+        }
+
+        if let Expr::Between {
+            expr: inner,
+            negated: false,
+            low,
+            high,
+        } = expr
+        {
+            return self.index_scan_between(table_name, inner, low, high);
+        }
+
+        // NULLs sort first in a range index, so both NULL checks are ranges
+        if let Expr::IsNull(inner) | Expr::IsNotNull(inner) = expr {
+            let Expr::Identifier(col_ident) = inner.as_ref() else {
+                return Ok(None);
+            };
+            let (lower, upper) = if matches!(expr, Expr::IsNull(_)) {
+                (Bound::Included(Value::Null), Bound::Included(Value::Null))
+            } else {
+                (Bound::Excluded(Value::Null), Bound::Unbounded)
+            };
+            return self.range_scan(table_name, &col_ident.value, lower, upper);
+        }
+
+        let Expr::BinaryOp { left, op, right } = expr else {
+            return Ok(None);
+        };
+
+        // Check if left is a column and right is a value. A column on the
+        // right, as in `a < b`, isn't a literal, so no index applies and the
+        // comparison is left to the filter, which reads both from each row.
+        let Expr::Identifier(col_ident) = left.as_ref() else {
+            return Ok(None);
+        };
+        let Ok(value) = self.expr_to_value(right) else {
+            return Ok(None);
+        };
+        let col_name = col_ident.value.as_str();
+        let value = self.coerce_to_column(table_name, col_name, value);
+
+        // A comparison with NULL or a value of another type never matches,
+        // but an index would find rows for it: NULL and the text 'Null'
+        // share a hash key, and a range follows the cross-type order.
+        // Leave those to the in-memory filter.
+        if !self.is_comparable_with_column(table_name, col_name, &value) {
+            return Ok(None);
+        }
+
+        // Equality can use a hash index
+        if matches!(op, sqlparser::ast::BinaryOperator::Eq)
+            && self.storage.has_hash_index(table_name, col_name)
+        {
+            let rows = self.hash_lookup(table_name, col_name, &value)?;
+            return Ok(Some((AccessPath::Index(col_name.to_string()), rows)));
+        }
+
+        // NULL never satisfies a comparison, so exclude it from open lower bounds
+        let (lower, upper) = match op {
+            sqlparser::ast::BinaryOperator::Eq => {
+                (Bound::Included(value.clone()), Bound::Included(value))
+            }
+            sqlparser::ast::BinaryOperator::Gt => (Bound::Excluded(value), Bound::Unbounded),
+            sqlparser::ast::BinaryOperator::GtEq => (Bound::Included(value), Bound::Unbounded),
+            sqlparser::ast::BinaryOperator::Lt => {
+                (Bound::Excluded(Value::Null), Bound::Excluded(value))
+            }
+            sqlparser::ast::BinaryOperator::LtEq => {
+                (Bound::Excluded(Value::Null), Bound::Included(value))
+            }
+            _ => return Ok(None),
+        };
+
+        // Ordered comparisons (and equality without a hash index) can use a range index
+        self.range_scan(table_name, col_name, lower, upper)
+    }
+
+    /// Answer OR-ed conditions by looking each one up in an index and taking
+    /// the union of the rows. Returns `None` unless every condition can use
+    /// an index, since one that can't needs a full scan anyway.
+    fn index_scan_union(
+        &self,
+        table_name: &str,
+        disjuncts: &[&Expr],
+    ) -> DbResult<Option<IndexScan>> {
+        let mut access_paths: Vec<AccessPath> = Vec::new();
+        // Key by offset so a row matching several conditions appears once
+        let mut rows = BTreeMap::new();
+        for disjunct in disjuncts {
+            let Some((access_path, matches)) = self.index_scan(table_name, disjunct)? else {
+                return Ok(None);
+            };
+            if !access_paths.contains(&access_path) {
+                access_paths.push(access_path);
+            }
+            rows.extend(matches);
+        }
+
+        let access_path = match <[AccessPath; 1]>::try_from(access_paths) {
+            Ok([access_path]) => access_path,
+            Err(access_paths) => AccessPath::IndexUnion(access_paths),
+        };
+        Ok(Some((access_path, rows.into_iter().collect())))
+    }
+
+    /// Fetch the rows equal to a value from a hash index. Its keys are the
+    /// values' string forms, so the text 'Null' shares NULL's key; rows
+    /// holding NULL are dropped, since NULL never equals anything.
+    fn hash_lookup(
+        &self,
+        table_name: &str,
+        col_name: &str,
+        value: &Value,
+    ) -> DbResult<Vec<(u64, Row)>> {
+        let mut rows = self
+            .storage
+            .index_lookup_with_offsets(table_name, col_name, value)?;
+        if let Some(col_idx) = self
+            .storage
+            .get_schema(table_name)
+            .and_then(|schema| schema.get_column_index(col_name))
+        {
+            rows.retain(|(_, row)| row.get(col_idx).is_some_and(|v| *v != Value::Null));
+        }
+        Ok(rows)
+    }
+
+    /// Fetch the rows within the bounds from a range index.
+    /// Returns `None` when the column has no range index.
+    fn range_scan(
+        &self,
+        table_name: &str,
+        col_name: &str,
+        lower: Bound<Value>,
+        upper: Bound<Value>,
+    ) -> DbResult<Option<IndexScan>> {
+        match self.storage.range_lookup_with_offsets(table_name, col_name, lower, upper) {
+            Ok(rows) => Ok(Some((AccessPath::RangeIndex(col_name.to_string()), rows))),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Answer `column IN (literal, ...)` with one index lookup per value,
+    /// unioning the matching rows. Returns `None` when no index applies.
+    fn index_scan_in_list(
+        &self,
+        table_name: &str,
+        expr: &Expr,
+        list: &[Expr],
+    ) -> DbResult<Option<IndexScan>> {
+        let Expr::Identifier(col_ident) = expr else {
+            return Ok(None);
+        };
+        let col_name = col_ident.value.as_str();
+        let Ok(values) = list
+            .iter()
+            .map(|item| {
+                let value = self.expr_to_value(item)?;
+                Ok(self.coerce_to_column(table_name, col_name, value))
+            })
+            .collect::<DbResult<Vec<Value>>>()
+        else {
+            return Ok(None);
+        };
+
+        // Prefer a hash index, falling back to a range index
+        let use_hash = self.storage.has_hash_index(table_name, col_name);
+        let has_range = self.storage.has_range_index(table_name, col_name);
+        if !use_hash && !has_range {
+            return Ok(None);
+        }
+
+        // Key by offset so values listed twice don't duplicate rows
+        let mut rows = BTreeMap::new();
+        for value in values {
+            // NULL is never IN anything, and neither is a value of another type
+            if !self.is_comparable_with_column(table_name, col_name, &value) {
+                continue;
+            }
+
+            let matches = if use_hash {
+                self.hash_lookup(table_name, col_name, &value)?
+            } else {
+                let bound = Bound::Included(value);
+                self.storage.range_lookup_with_offsets(table_name, col_name, bound.clone(), bound)?
+            };
+            rows.extend(matches);
+        }
+
+        let access_path = if use_hash {
+            AccessPath::Index(col_name.to_string())
+        } else {
+            AccessPath::RangeIndex(col_name.to_string())
+        };
+        Ok(Some((access_path, rows.into_iter().collect())))
+    }
+
+    /// Answer `column BETWEEN low AND high` from a range index.
+    /// Returns `None` when no index applies.
+    fn index_scan_between(
+        &self,
+        table_name: &str,
+        expr: &Expr,
+        low: &Expr,
+        high: &Expr,
+    ) -> DbResult<Option<IndexScan>> {
+        let Expr::Identifier(col_ident) = expr else {
+            return Ok(None);
+        };
+        let (Ok(low), Ok(high)) = (self.expr_to_value(low), self.expr_to_value(high)) else {
+            return Ok(None);
+        };
+        let low = self.coerce_to_column(table_name, &col_ident.value, low);
+        let high = self.coerce_to_column(table_name, &col_ident.value, high);
+
+        // A NULL bound, or one of another type, never matches; leave it to
+        // the in-memory filter
+        if !self.is_comparable_with_column(table_name, &col_ident.value, &low)
+            || !self.is_comparable_with_column(table_name, &col_ident.value, &high)
+        {
+            return Ok(None);
+        }
+
+        self.range_scan(
+            table_name,
+            &col_ident.value,
+            Bound::Included(low),
+            Bound::Included(high),
+        )
+    }
+
+    /// Convert a literal to the type of the column it is compared against.
+    /// Numbers are left alone for a Decimal column: they compare exactly
+    /// with its values as they are, and rounding them to its scale would
+    /// let `price = 19.999` find a stored 20.00.
+    fn coerce_to_column(&self, table_name: &str, col_name: &str, value: Value) -> Value {
+        match self
+            .storage
+            .get_schema(table_name)
+            .and_then(|schema| schema.get_column(col_name))
+        {
+            Some(column) if !matches!(column.column_type, ColumnType::Decimal { .. }) => {
+                value.coerce_to(&column.column_type)
+            }
+            _ => value,
+        }
+    }
+
+    /// Whether a literal can be compared with the values of a column, so an
+    /// index lookup for it finds exactly the rows the comparison matches
+    fn is_comparable_with_column(&self, table_name: &str, col_name: &str, value: &Value) -> bool {
+        self.storage
+            .get_schema(table_name)
+            .and_then(|schema| schema.get_column(col_name))
+            .is_some_and(|column| value.is_comparable_with_type(&column.column_type))
+    }
+
+    /// Evaluate a literal to store in a column of the given type. A number
+    /// for a Decimal column is read straight from its digits, so 0.1 is
+    /// exact rather than the nearest float, before rounding to the scale.
+    fn literal_for_column(&self, expr: &Expr, column_type: &ColumnType) -> DbResult<Value> {
+        if let ColumnType::Decimal { .. } = column_type
+            && let Some(decimal) = decimal_literal(expr)
+        {
+            return Ok(Value::Decimal(decimal).coerce_to(column_type));
+        }
+        Ok(self.expr_to_value(expr)?.coerce_to(column_type))
+    }
+
+    fn expr_to_value(&self, expr: &Expr) -> DbResult<Value> {
+        match expr {
+            Expr::Value(sql_val) => self.sql_value_to_value(sql_val),
+            Expr::UnaryOp {
+                op: op @ (UnaryOperator::Minus | UnaryOperator::Plus),
+                expr: inner,
+            } => {
+                // Parse the sign with the digits so the smallest i64 is in range
+                if let (UnaryOperator::Minus, Expr::Value(SqlValue::Number(n, long))) =
+                    (op, inner.as_ref())
+                {
+                    return self.sql_value_to_value(&SqlValue::Number(format!("-{}", n), *long));
+                }
+
+                match self.expr_to_value(inner)? {
+                    value @ (Value::Integer(_)
+                    | Value::Float(_)
+                    | Value::Decimal(_)
+                    | Value::Null) => {
+                        Ok(scalar::sign(op, value))
+                    }
+                    value => Err(DbError::TypeMismatch(format!(
+                        "Cannot apply '{}' to {:?}",
+                        op, value
+                    ))),
+                }
+            }
+            // DATE '2024-01-15' and TIMESTAMP '2024-01-15 10:30:00'
+            Expr::TypedString {
+                data_type:
+                    sqlparser::ast::DataType::Date
+                    | sqlparser::ast::DataType::Timestamp(_, _)
+                    | sqlparser::ast::DataType::Datetime(_),
+                value,
+            } => match Value::Text(value.clone()).coerce_to(&ColumnType::Timestamp) {
+                Value::Timestamp(t) => Ok(Value::Timestamp(t)),
+                _ => Err(DbError::InvalidInput(format!(
+                    "Invalid timestamp: '{}'",
+                    value
+                ))),
+            },
+            _ => Err(DbError::InvalidInput("Expected literal value".to_string())),
+        }
+    }
+
+    fn sql_value_to_value(&self, sql_val: &SqlValue) -> DbResult<Value> {
+        match sql_val {
+            SqlValue::Placeholder(placeholder) => self.placeholder_value(placeholder),
+            _ => Value::from_sql(sql_val).map_err(DbError::InvalidInput),
+        }
+    }
+}
+
+/// How one output column of a non-grouped SELECT is produced
+enum Projection<'a> {
+    /// Copied from a column of the source rows
+    Column(usize),
+    /// Computed from each source row
+    Computed(&'a Expr),
+}
+
+/// The expression of a SELECT list item and its `AS` alias, if any.
+/// Returns `None` for wildcards.
+fn select_item_expr(item: &SelectItem) -> Option<(&Expr, Option<&Ident>)> {
+    match item {
+        SelectItem::UnnamedExpr(expr) => Some((expr, None)),
+        SelectItem::ExprWithAlias { expr, alias } => Some((expr, Some(alias))),
+        _ => None,
+    }
+}
+
+/// The type of a `DECIMAL(precision, scale)` column, also written NUMERIC
+/// or DEC. Precision defaults to 10 and scale to 0, as in MySQL.
+fn decimal_type(col_name: &str, info: ExactNumberInfo) -> DbResult<ColumnType> {
+    let (precision, scale) = match info {
+        ExactNumberInfo::None => (10, 0),
+        ExactNumberInfo::Precision(precision) => (precision, 0),
+        ExactNumberInfo::PrecisionAndScale(precision, scale) => (precision, scale),
+    };
+    if !(1..=MAX_PRECISION as u64).contains(&precision) || scale > precision {
+        return Err(DbError::InvalidInput(format!(
+            "Invalid DECIMAL({},{}) for column {}: precision must be 1 to {} \
+             and scale at most the precision",
+            precision, scale, col_name, MAX_PRECISION
+        )));
+    }
+    Ok(ColumnType::Decimal {
+        precision: precision as u8,
+        scale: scale as u8,
+    })
+}
+
+/// The exact value of a number literal, optionally signed, such as `-12.50`.
+/// Returns `None` for anything else, including numbers in exponent notation.
+fn decimal_literal(expr: &Expr) -> Option<Decimal> {
+    match expr {
+        Expr::Value(SqlValue::Number(n, _)) => Decimal::parse(n),
+        Expr::UnaryOp {
+            op: UnaryOperator::Minus,
+            expr: inner,
+        } => decimal_literal(inner)?.checked_neg(),
+        Expr::UnaryOp {
+            op: UnaryOperator::Plus,
+            expr: inner,
+        } => decimal_literal(inner),
+        _ => None,
+    }
+}
+
+/// Width of a `SMALLSERIAL`, `SERIAL` or `BIGSERIAL` column; `None` for other types
+fn serial_width(data_type: &sqlparser::ast::DataType) -> Option<IntWidth> {
+    let sqlparser::ast::DataType::Custom(name, modifiers) = data_type else {
+        return None;
+    };
+    if !modifiers.is_empty() {
+        return None;
+    }
+    match name.to_string().to_uppercase().as_str() {
+        "SMALLSERIAL" => Some(IntWidth::Small),
+        "SERIAL" => Some(IntWidth::Int),
+        "BIGSERIAL" => Some(IntWidth::Big),
+        _ => None,
+    }
+}
+
+/// The column name an expression refers to: `name` for a plain identifier,
+/// or `table.name` for a qualified one
+fn column_ref(expr: &Expr) -> Option<String> {
+    match expr {
+        Expr::Identifier(ident) => Some(ident.value.clone()),
+        Expr::CompoundIdentifier(idents) => Some(
+            idents
+                .iter()
+                .map(|ident| ident.value.as_str())
+                .collect::<Vec<_>>()
+                .join("."),
+        ),
+        _ => None,
+    }
+}
+
+/// Find a column by name. Joined rows name their columns `table.column`,
+/// so an unqualified name also matches a single column qualified with it.
+/// A name qualified with the table's own name, as in `SELECT users.name
+/// FROM users`, matches the plain column. `rowid` is found just past the
+/// table's columns unless the table has a column of that name.
+fn find_column(schema: &TableSchema, col_name: &str) -> Option<usize> {
+    if let Some(col_idx) = schema.get_column_index(col_name) {
+        return Some(col_idx);
+    }
+    if let Some((qualifier, name)) = col_name.split_once('.')
+        && qualifier == schema.name
+        && let Some(col_idx) = schema.get_column_index(name)
+    {
+        return Some(col_idx);
+    }
+
+    let mut matches = qualified_matches(schema, col_name);
+    match (matches.next(), matches.next()) {
+        (Some(col_idx), None) => Some(col_idx),
+        (Some(_), Some(_)) => None,
+        (None, _) => rowid_position(schema, col_name),
+    }
+}
+
+/// Position of the `rowid` pseudo-column if `col_name` names it. Joined
+/// rows, whose columns are named `table.column`, have no rowid.
+fn rowid_position(schema: &TableSchema, col_name: &str) -> Option<usize> {
+    let name = match col_name.split_once('.') {
+        Some((qualifier, name)) if qualifier == schema.name => name,
+        Some(_) => return None,
+        None => col_name,
+    };
+    let joined = schema.columns.iter().any(|column| column.name.contains('.'));
+    (name.eq_ignore_ascii_case(ROWID) && !joined).then_some(schema.columns.len())
+}
+
+/// The column at a position `find_column` returned, which may be the rowid
+fn column_at(schema: &TableSchema, col_idx: usize) -> &Column {
+    schema.columns.get(col_idx).unwrap_or(&ROWID_COLUMN)
+}
+
+/// Append a row's rowid, its offset in the data log, after its columns
+fn push_rowid(row: &mut Row, offset: u64) {
+    row.values.push(Value::Integer(offset as i64));
+}
+
+/// Whether `qualifier` names the table of `schema`, or one of the tables
+/// of a joined schema
+fn is_qualifier(schema: &TableSchema, qualifier: &str) -> bool {
+    qualifier == schema.name
+        || schema.columns.iter().any(|column| {
+            column
+                .name
+                .rsplit_once('.')
+                .is_some_and(|(table, _)| table == qualifier)
+        })
+}
+
+/// The error for a column name `find_column` could not resolve: a
+/// qualifier outside the FROM clause, a name more than one joined table
+/// has, or a column that does not exist
+fn unresolved_column(schema: &TableSchema, col_name: &str) -> DbError {
+    if let Some((qualifier, _)) = col_name.split_once('.')
+        && !is_qualifier(schema, qualifier)
+    {
+        DbError::InvalidInput(format!(
+            "Table '{}' in '{}' is not in the FROM clause",
+            qualifier, col_name
+        ))
+    } else if qualified_matches(schema, col_name).count() > 1 {
+        DbError::InvalidInput(format!("Column reference '{}' is ambiguous", col_name))
+    } else {
+        DbError::ColumnNotFound {
+            table: schema.name.to_string(),
+            column: col_name.to_string(),
+        }
+    }
+}
+
+/// Positions of the columns named `<table>.<col_name>`
+fn qualified_matches<'a>(
+    schema: &'a TableSchema,
+    col_name: &'a str,
+) -> impl Iterator<Item = usize> + 'a {
+    schema.columns.iter().enumerate().filter_map(move |(i, column)| {
+        let (_, name) = column.name.rsplit_once('.')?;
+        (name == col_name).then_some(i)
+    })
+}
+
+/// Drop repeated result rows for SELECT DISTINCT, keeping the first of each
+/// so any ORDER BY still holds
+fn apply_distinct(select: &Select, rows: Vec<Row>) -> DbResult<Vec<Row>> {
+    match &select.distinct {
+        None => Ok(rows),
+        Some(Distinct::Distinct) => {
+            let mut seen = HashSet::new();
+            Ok(rows
+                .into_iter()
+                .filter(|row| seen.insert(row.values.clone()))
+                .collect())
+        }
+        Some(Distinct::On(_)) => Err(DbError::InvalidInput(
+            "DISTINCT ON is not supported".to_string(),
+        )),
+    }
+}
+
+/// Parse SQL that is run directly, which can't contain placeholders
+fn parse_script(sql: &str) -> DbResult<Vec<Statement>> {
+    let (ast, param_count) = prepared::parse_with_placeholders(sql)?;
+    if param_count > 0 {
+        return Err(DbError::InvalidInput(
+            "Statements with placeholders must be run with prepare and execute_prepared"
+                .to_string(),
+        ));
+    }
+    Ok(ast)
+}
+
+/// Describe the output columns of a grouped or aggregate query
+fn grouped_result_schema(
+    projection: &[SelectItem],
+    columns: &[GroupedColumn],
+    group_by: &[usize],
+    schema: &TableSchema,
+) -> TableSchema {
+    let columns = projection
+        .iter()
+        .zip(columns)
+        .map(|(item, column)| {
+            let alias = select_item_expr(item).and_then(|(_, alias)| alias);
+            let mut column = match column {
+                GroupedColumn::Key(i) => column_at(schema, group_by[*i]).clone(),
+                GroupedColumn::Aggregate(aggregate) => Column {
+                    name: item.to_string(),
+                    column_type: aggregate.output_type(schema),
+                    int_width: IntWidth::Big,
+                    nullable: true,
+                    unique: false,
+                    primary_key: false,
+                    auto_increment: false,
+                    default: None,
+                },
+            };
+            if let Some(alias) = alias {
+                column.name = alias.value.clone();
+            }
+            column
+        })
+        .collect();
+
+    TableSchema::new(schema.name.clone(), columns)
+}
+
+/// Whether a VALUES entry is the bare `DEFAULT` keyword
+fn is_default_keyword(expr: &Expr) -> bool {
+    matches!(expr, Expr::Identifier(ident)
+        if ident.quote_style.is_none() && ident.value.eq_ignore_ascii_case("default"))
+}
+
+/// Compare two rows by (position, ascending) sort keys, breaking ties left to right
+fn compare_rows(a: &Row, b: &Row, sort_keys: &[(usize, bool)]) -> Ordering {
+    for &(position, ascending) in sort_keys {
+        let ordering = a.get(position).cmp(&b.get(position));
+        let ordering = if ascending { ordering } else { ordering.reverse() };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+/// Match text against a SQL LIKE pattern, where `%` matches any sequence of
+/// characters, `_` matches exactly one, and `escape` makes the next character literal
+fn like_match(text: &str, pattern: &str, escape: char) -> bool {
+    enum Token {
+        AnySequence,
+        AnyChar,
+        Literal(char),
+    }
+
+    // Tokenize the pattern so escaped wildcards become literals
+    let mut tokens = Vec::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        tokens.push(match c {
+            '%' => Token::AnySequence,
+            '_' => Token::AnyChar,
+            c if c == escape => Token::Literal(chars.next().unwrap_or(escape)),
+            c => Token::Literal(c),
+        });
+    }
+
+    let text: Vec<char> = text.chars().collect();
+    let (mut t, mut p) = (0, 0);
+    // Position after the last `%` seen, and the text position it resumes from
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match tokens.get(p) {
+            Some(Token::AnySequence) => {
+                p += 1;
+                backtrack = Some((p, t));
+            }
+            Some(Token::AnyChar) => {
+                p += 1;
+                t += 1;
+            }
+            Some(Token::Literal(c)) if *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                // Let the last `%` swallow one more character and retry
+                Some((resume_p, resume_t)) => {
+                    p = resume_p;
+                    t = resume_t + 1;
+                    backtrack = Some((resume_p, resume_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    // Trailing `%`s can match the empty string
+    tokens[p..].iter().all(|token| matches!(token, Token::AnySequence))
+}
+
+/// Split an expression into its top-level OR-ed conditions
+fn split_disjuncts(expr: &Expr) -> Vec<&Expr> {
+    match expr {
+        Expr::Nested(inner) => split_disjuncts(inner),
+        Expr::BinaryOp {
+            left,
+            op: sqlparser::ast::BinaryOperator::Or,
+            right,
+        } => {
+            let mut disjuncts = split_disjuncts(left);
+            disjuncts.extend(split_disjuncts(right));
+            disjuncts
+        }
+        _ => vec![expr],
+    }
+}
+
+/// Split an expression into its top-level AND-ed conditions
+fn split_conjuncts(expr: &Expr) -> Vec<&Expr> {
+    match expr {
+        Expr::Nested(inner) => split_conjuncts(inner),
+        Expr::BinaryOp {
+            left,
+            op: sqlparser::ast::BinaryOperator::And,
+            right,
+        } => {
+            let mut conjuncts = split_conjuncts(left);
+            conjuncts.extend(split_conjuncts(right));
+            conjuncts
+        }
+        _ => vec![expr],
+    }
+}
+
+#[derive(Debug)]
+pub enum ExecutionResult {
+    Created,
+    IndexCreated,
+    Inserted(usize),
+    Selected(Vec<Row>),
+    Deleted(usize),
+    Dropped,
+    IndexDropped,
+    Truncated,
+    TransactionStarted,
+    Committed,
+    RolledBack,
+}
+
+/// How a statement found the rows it read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AccessPath {
+    /// Every row of the table was read
+    FullScan,
+    /// The row count was taken from the table's stored count, without reading rows
+    RowCount,
+    /// The one row with a given primary key was read directly
+    PrimaryKey(String),
+    /// Rows were found through the hash index on the column
+    Index(String),
+    /// Rows were found through the range index on the column
+    RangeIndex(String),
+    /// Rows were found through the composite index on the columns
+    CompositeIndex(Vec<String>),
+    /// Rows were found through several indexes, one per OR-ed condition
+    IndexUnion(Vec<AccessPath>),
+}
+
+impl AccessPath {
+    /// Whether rows were found through an index rather than a scan
+    pub fn uses_index(&self) -> bool {
+        matches!(
+            self,
+            AccessPath::PrimaryKey(_)
+                | AccessPath::Index(_)
+                | AccessPath::RangeIndex(_)
+                | AccessPath::CompositeIndex(_)
+                | AccessPath::IndexUnion(_)
+        )
+    }
+}
+
+impl fmt::Display for AccessPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccessPath::FullScan => write!(f, "full scan"),
+            AccessPath::RowCount => write!(f, "stored row count"),
+            AccessPath::PrimaryKey(column) => write!(f, "primary key lookup on {}", column),
+            AccessPath::Index(column) => write!(f, "index on {}", column),
+            AccessPath::RangeIndex(column) => write!(f, "range index on {}", column),
+            AccessPath::CompositeIndex(columns) => {
+                write!(f, "composite index on ({})", columns.join(", "))
+            }
+            AccessPath::IndexUnion(access_paths) => {
+                let access_paths: Vec<String> =
+                    access_paths.iter().map(|path| path.to_string()).collect();
+                write!(f, "union of {}", access_paths.join(" and "))
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
 pub struct QueryResult {
     pub result: ExecutionResult,
     pub duration: std::time::Duration,
+    /// Whether the statement's rows were found through an index
     pub used_index: bool,
-}
\ No newline at end of file
+    /// How the statement found the rows it read; `None` if it read none
+    pub access_path: Option<AccessPath>,
+    /// Columns of the `Selected` rows; `None` for other statements
+    pub schema: Option<TableSchema>,
+    /// For an INSERT into a table with an AUTO_INCREMENT column, that
+    /// column's value in each inserted row, in order; empty otherwise
+    pub inserted_ids: Vec<i64>,
+    /// Rows the statement read through full table scans
+    pub rows_scanned: u64,
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An in-memory database with `users (id, name, age)` holding three rows
+    fn users() -> QueryExecutor {
+        let mut executor = QueryExecutor::new(BitcaskStorage::in_memory());
+        executor
+            .execute_script(
+                "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, age INTEGER);
+                 INSERT INTO users VALUES (1, 'alice', 30), (2, 'bob', 25), (3, 'carol', 41);",
+            )
+            .unwrap();
+        executor
+    }
+
+    fn selected(executor: &mut QueryExecutor, sql: &str) -> Vec<Row> {
+        match executor.execute(sql).unwrap().result {
+            ExecutionResult::Selected(rows) => rows,
+            other => panic!("expected rows from {}, got {:?}", sql, other),
+        }
+    }
+
+    fn count_users(executor: &mut QueryExecutor) -> usize {
+        selected(executor, "SELECT * FROM users").len()
+    }
+
+    /// The names of the rows a query returns, in order
+    fn names(executor: &mut QueryExecutor, sql: &str) -> Vec<String> {
+        selected(executor, sql)
+            .iter()
+            .map(|row| row.get(1).unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn comparisons_with_null_match_no_rows() {
+        let mut executor = users();
+        executor
+            .execute("INSERT INTO users VALUES (4, 'dave', NULL)")
+            .unwrap();
+
+        for sql in [
+            "SELECT * FROM users WHERE age != 30",
+            "SELECT * FROM users WHERE age <> 30",
+            "SELECT * FROM users WHERE NOT age = 30",
+        ] {
+            assert_eq!(names(&mut executor, sql), ["bob", "carol"], "{}", sql);
+        }
+        assert_eq!(
+            names(
+                &mut executor,
+                "SELECT * FROM users WHERE age = 30 OR age != 30"
+            ),
+            ["alice", "bob", "carol"]
+        );
+        assert_eq!(
+            names(&mut executor, "SELECT * FROM users WHERE age IS NULL"),
+            ["dave"]
+        );
+        assert!(names(&mut executor, "SELECT * FROM users WHERE age = NULL").is_empty());
+    }
+
+    #[test]
+    fn unsupported_conditions_are_errors() {
+        let mut executor = users();
+        for sql in [
+            "SELECT * FROM users WHERE age",
+            "SELECT * FROM users WHERE age + 1",
+            "SELECT * FROM users WHERE (age > 1) IS TRUE",
+            "SELECT * FROM users WHERE name ILIKE 'A%'",
+            "SELECT * FROM users WHERE FOO(name) = 'x'",
+            "SELECT * FROM users WHERE UPPER(age) = 'x'",
+            "SELECT * FROM users WHERE nickname = 'x'",
+        ] {
+            assert!(executor.execute(sql).is_err(), "{} should fail", sql);
+        }
+    }
+
+    #[test]
+    fn having_is_refused() {
+        let mut executor = users();
+        let result =
+            executor.execute("SELECT age, COUNT(*) FROM users GROUP BY age HAVING COUNT(*) > 1");
+        assert!(matches!(result, Err(DbError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn exhausted_auto_increment_is_an_error() {
+        let mut executor = QueryExecutor::new(BitcaskStorage::in_memory());
+        executor
+            .execute_script(
+                "CREATE TABLE t (id BIGSERIAL PRIMARY KEY, name TEXT);
+                 INSERT INTO t VALUES (9223372036854775807, 'last');",
+            )
+            .unwrap();
+        let result = executor.execute("INSERT INTO t (name) VALUES ('next')");
+        assert!(matches!(
+            result,
+            Err(DbError::Row { source, .. }) if matches!(*source, DbError::SchemaViolation(_))
+        ));
+    }
+
+    /// An in-memory database with `big (n)` holding 0 to 999
+    fn big() -> QueryExecutor {
+        let mut executor = QueryExecutor::new(BitcaskStorage::in_memory());
+        executor.execute("CREATE TABLE big (n INTEGER)").unwrap();
+        let rows = (0..1000)
+            .map(|n| Row::new(vec![Value::Integer(n)]))
+            .collect();
+        executor.storage.insert_batch("big", rows).unwrap();
+        executor
+    }
+
+    /// How many rows a query returns, and how many it read from the table
+    fn rows_and_scanned(executor: &mut QueryExecutor, sql: &str) -> (usize, u64) {
+        let result = executor.execute(sql).unwrap();
+        let ExecutionResult::Selected(rows) = result.result else {
+            panic!("expected rows from {}", sql);
+        };
+        (rows.len(), result.rows_scanned)
+    }
+
+    #[test]
+    fn limit_stops_scan_early() {
+        let mut executor = big();
+        assert_eq!(
+            rows_and_scanned(&mut executor, "SELECT * FROM big LIMIT 5"),
+            (5, 5)
+        );
+        assert_eq!(
+            rows_and_scanned(&mut executor, "SELECT * FROM big LIMIT 5 OFFSET 10"),
+            (5, 15)
+        );
+        // Every fourth row matches, so 20 are read to find 5
+        assert_eq!(
+            rows_and_scanned(&mut executor, "SELECT * FROM big WHERE n % 4 = 3 LIMIT 5"),
+            (5, 20)
+        );
+    }
+
+    #[test]
+    fn limit_reads_whole_table_when_needed() {
+        let mut executor = big();
+        for sql in [
+            "SELECT * FROM big ORDER BY n DESC LIMIT 5",
+            "SELECT DISTINCT n FROM big LIMIT 5",
+            "SELECT n, COUNT(*) FROM big GROUP BY n LIMIT 5",
+        ] {
+            assert_eq!(rows_and_scanned(&mut executor, sql), (5, 1000), "{}", sql);
+        }
+        assert_eq!(
+            rows_and_scanned(&mut executor, "SELECT * FROM big"),
+            (1000, 1000)
+        );
+    }
+
+    #[test]
+    fn compares_two_columns_of_a_row_without_an_index() {
+        let mut executor = QueryExecutor::new(BitcaskStorage::in_memory());
+        executor
+            .execute_script(
+                "CREATE TABLE spans (id INTEGER PRIMARY KEY, starts INTEGER, ends INTEGER);
+                 CREATE INDEX idx_starts ON spans (starts);
+                 CREATE INDEX idx_ends ON spans USING BTREE (ends);
+                 INSERT INTO spans VALUES (1, 10, 20), (2, 30, 25), (3, 5, 5), (4, NULL, 8);",
+            )
+            .unwrap();
+
+        let result = executor
+            .execute("SELECT id FROM spans WHERE starts < ends")
+            .unwrap();
+        assert_eq!(result.access_path, Some(AccessPath::FullScan));
+        assert!(!result.used_index);
+        let ExecutionResult::Selected(rows) = result.result else {
+            panic!("expected rows");
+        };
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].values, [Value::Integer(1)]);
+
+        let ids = |executor: &mut QueryExecutor, sql| -> Vec<Value> {
+            selected(executor, sql)
+                .into_iter()
+                .map(|row| row.values[0].clone())
+                .collect()
+        };
+        assert_eq!(
+            ids(&mut executor, "SELECT id FROM spans WHERE ends <= starts"),
+            [Value::Integer(2), Value::Integer(3)]
+        );
+        assert_eq!(
+            ids(&mut executor, "SELECT id FROM spans WHERE starts = ends"),
+            [Value::Integer(3)]
+        );
+    }
+
+    #[test]
+    fn explicit_null_round_trips() {
+        let mut executor = QueryExecutor::new(BitcaskStorage::in_memory());
+        executor
+            .execute_script(
+                "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT DEFAULT 'anon', age INTEGER);
+                 INSERT INTO users VALUES (1, NULL, 30);",
+            )
+            .unwrap();
+
+        // Rows are read back from the log, so the NULL has been serialized
+        // and deserialized on the way
+        let result = executor.execute("SELECT * FROM users").unwrap();
+        let schema = result.schema.unwrap();
+        let ExecutionResult::Selected(rows) = result.result else {
+            panic!("expected rows");
+        };
+        assert_eq!(
+            rows[0].values,
+            [Value::Integer(1), Value::Null, Value::Integer(30)]
+        );
+        assert_eq!(
+            names(&mut executor, "SELECT * FROM users WHERE name IS NULL").len(),
+            1
+        );
+
+        let table = display::format_rows(&schema, &rows);
+        assert!(table.contains("|  1 | NULL |  30 |"), "{}", table);
+        let json = display::rows_to_json(&schema, &rows);
+        assert_eq!(
+            json,
+            serde_json::json!([{ "id": 1, "name": null, "age": 30 }])
+        );
+    }
+
+    #[test]
+    fn explicit_null_into_not_null_column_fails() {
+        let mut executor = QueryExecutor::new(BitcaskStorage::in_memory());
+        executor
+            .execute(
+                "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL DEFAULT 'anon')",
+            )
+            .unwrap();
+        assert!(
+            executor
+                .execute("INSERT INTO users VALUES (1, NULL)")
+                .is_err()
+        );
+        assert_eq!(selected(&mut executor, "SELECT * FROM users").len(), 0);
+    }
+
+    #[test]
+    fn delete_with_unsupported_predicate_deletes_nothing() {
+        let mut executor = users();
+        for sql in [
+            "DELETE FROM users WHERE name ILIKE 'zzz%'",
+            "DELETE FROM users WHERE id IN (SELECT id FROM users WHERE age > 100)",
+            "DELETE FROM users WHERE EXISTS (SELECT id FROM users WHERE age > 100)",
+            // The primary key lookup answers `id = 1` on its own
+            "DELETE FROM users WHERE id = 1 AND name ILIKE 'zzz%'",
+        ] {
+            assert!(executor.execute(sql).is_err(), "{} should fail", sql);
+            assert_eq!(count_users(&mut executor), 3, "{} deleted rows", sql);
+        }
+    }
+
+    #[test]
+    fn delete_with_supported_predicate_deletes_matching_rows() {
+        let mut executor = users();
+        let result = executor.execute("DELETE FROM users WHERE name LIKE 'zzz%'").unwrap();
+        assert!(matches!(result.result, ExecutionResult::Deleted(0)));
+        let result = executor.execute("DELETE FROM users WHERE age > 28").unwrap();
+        assert!(matches!(result.result, ExecutionResult::Deleted(2)));
+        assert_eq!(count_users(&mut executor), 1);
+    }
+
+    #[test]
+    fn unqualified_column_in_self_join_is_ambiguous() {
+        let mut executor = users();
+        for sql in [
+            "SELECT id FROM users a JOIN users b ON a.id = b.id",
+            "SELECT a.id FROM users a JOIN users b ON id = b.id",
+            "SELECT a.id FROM users a JOIN users b ON a.id = b.id WHERE id = 1",
+            "SELECT a.id FROM users a JOIN users b ON a.id = b.id ORDER BY id",
+            "SELECT COUNT(id) FROM users a JOIN users b ON a.id = b.id",
+            "SELECT age, COUNT(*) FROM users a JOIN users b ON a.id = b.id GROUP BY age",
+        ] {
+            match executor.execute(sql) {
+                Err(DbError::InvalidInput(message)) => {
+                    assert!(message.contains("is ambiguous"), "{}: {}", sql, message)
+                }
+                other => panic!(
+                    "{} should be ambiguous, got {:?}",
+                    sql,
+                    other.map(|r| r.result)
+                ),
+            }
+        }
+
+        let rows = selected(
+            &mut executor,
+            "SELECT a.id, b.name FROM users a JOIN users b ON a.id = b.id WHERE a.id = 2",
+        );
+        assert_eq!(rows.len(), 1);
+        assert_eq!(
+            rows[0].values,
+            vec![Value::Integer(2), Value::Text("bob".to_string())]
+        );
+    }
+
+    /// The first column of each row a query returns, in order
+    fn ids(executor: &mut QueryExecutor, sql: &str) -> Vec<Value> {
+        selected(executor, sql)
+            .iter()
+            .map(|row| row.get(0).unwrap().clone())
+            .collect()
+    }
+
+    #[test]
+    fn hash_index_lookups_match_the_filter() {
+        let mut executor = QueryExecutor::new(BitcaskStorage::in_memory());
+        executor
+            .execute_script(
+                "CREATE TABLE t (id INTEGER PRIMARY KEY, name TEXT, age INTEGER);
+                 INSERT INTO t VALUES (1, NULL, NULL), (2, 'Null', 5), (3, '5', 7);",
+            )
+            .unwrap();
+        let queries = [
+            "SELECT id FROM t WHERE name = NULL",
+            "SELECT id FROM t WHERE name = 5",
+            "SELECT id FROM t WHERE name = '5'",
+            "SELECT id FROM t WHERE name IN (NULL, 5, 'Null')",
+            "SELECT id FROM t WHERE id = '3'",
+            "SELECT id FROM t WHERE id = NULL",
+        ];
+        let unindexed: Vec<_> = queries.iter().map(|sql| ids(&mut executor, sql)).collect();
+        assert_eq!(unindexed[2], vec![Value::Integer(3)]);
+        assert_eq!(unindexed[3], vec![Value::Integer(2)]);
+
+        executor.execute("CREATE INDEX ON t (name)").unwrap();
+        for (sql, expected) in queries.iter().zip(&unindexed) {
+            assert_eq!(&ids(&mut executor, sql), expected, "{}", sql);
+        }
+    }
+
+    #[test]
+    fn range_index_lookups_match_the_filter() {
+        let mut executor = QueryExecutor::new(BitcaskStorage::in_memory());
+        executor
+            .execute_script(
+                "CREATE TABLE t (id INTEGER PRIMARY KEY, name TEXT, age INTEGER);
+                 INSERT INTO t VALUES (1, NULL, NULL), (2, 'Null', 5), (3, '5', 7);",
+            )
+            .unwrap();
+        let queries = [
+            "SELECT id FROM t WHERE age = NULL",
+            "SELECT id FROM t WHERE age >= NULL",
+            "SELECT id FROM t WHERE age < NULL",
+            "SELECT id FROM t WHERE age = '5'",
+            "SELECT id FROM t WHERE age < 'a'",
+            "SELECT id FROM t WHERE age > 6",
+            "SELECT id FROM t WHERE age BETWEEN 0 AND 'z'",
+            "SELECT id FROM t WHERE age IN (NULL, 'x', 7)",
+            "SELECT id FROM t WHERE name = NULL",
+            "SELECT id FROM t WHERE name > 5",
+            "SELECT id FROM t WHERE name <= 6",
+            "SELECT id FROM t WHERE name >= 'N'",
+        ];
+        let unindexed: Vec<_> = queries.iter().map(|sql| ids(&mut executor, sql)).collect();
+        assert_eq!(unindexed[5], vec![Value::Integer(3)]);
+        assert_eq!(unindexed[7], vec![Value::Integer(3)]);
+        assert_eq!(unindexed[11], vec![Value::Integer(2)]);
+
+        executor
+            .execute_script(
+                "CREATE INDEX ON t USING BTREE (age);
+                 CREATE INDEX ON t USING BTREE (name);",
+            )
+            .unwrap();
+        for (sql, expected) in queries.iter().zip(&unindexed) {
+            assert_eq!(&ids(&mut executor, sql), expected, "{}", sql);
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use super::{QueryExecutor, QueryResult};
+use crate::error::{DbError, DbResult};
+use crate::storage::{Storage, Value};
+use sqlparser::ast::Statement;
+use sqlparser::dialect::GenericDialect;
+use sqlparser::parser::Parser;
+use sqlparser::tokenizer::{Token, Tokenizer};
+use std::time::Instant;
+
+/// A statement parsed once by `QueryExecutor::prepare` and run any number
+/// of times with `QueryExecutor::execute_prepared`
+#[derive(Debug, Clone)]
+pub struct PreparedStatement {
+    statement: Statement,
+    param_count: usize,
+}
+
+impl PreparedStatement {
+    /// Number of values `execute_prepared` expects
+    pub fn param_count(&self) -> usize {
+        self.param_count
+    }
+}
+
+impl<S: Storage> QueryExecutor<S> {
+    /// Parse a statement for repeated execution. Placeholders are bound to
+    /// parameters by position: each `?` takes the next parameter, while
+    /// `?N` and `$N` take the Nth (counting from 1).
+    pub fn prepare(&self, sql: &str) -> DbResult<PreparedStatement> {
+        let (mut statements, param_count) = parse_with_placeholders(sql)?;
+
+        if statements.len() != 1 {
+            return Err(DbError::InvalidInput(
+                "A prepared statement must contain exactly one SQL statement".to_string(),
+            ));
+        }
+
+        Ok(PreparedStatement {
+            statement: statements.remove(0),
+            param_count,
+        })
+    }
+
+    /// Execute a prepared statement with `params` bound to its placeholders
+    pub fn execute_prepared(
+        &mut self,
+        stmt: &PreparedStatement,
+        params: &[Value],
+    ) -> DbResult<QueryResult> {
+        let start = Instant::now();
+
+        if params.len() != stmt.param_count {
+            return Err(DbError::InvalidInput(format!(
+                "Statement expects {} parameters but {} were given",
+                stmt.param_count,
+                params.len()
+            )));
+        }
+
+        self.params = params.to_vec();
+        let result = self.execute_statement(&stmt.statement, start);
+        self.params.clear();
+        result
+    }
+
+    /// Look up the value bound to a placeholder
+    pub(super) fn placeholder_value(&self, placeholder: &str) -> DbResult<Value> {
+        let position = placeholder_position(placeholder)?;
+        self.params.get(position - 1).cloned().ok_or_else(|| {
+            DbError::InvalidInput(format!("No value bound to placeholder {}", placeholder))
+        })
+    }
+}
+
+/// Parse SQL, numbering bare `?` placeholders so their position survives
+/// parsing. Returns the statements and the number of parameters they take.
+pub(super) fn parse_with_placeholders(sql: &str) -> DbResult<(Vec<Statement>, usize)> {
+    let dialect = GenericDialect {};
+
+    let mut tokens = Tokenizer::new(&dialect, sql).tokenize_with_location()?;
+
+    let mut next_position = 0;
+    let mut param_count = 0;
+    for token in &mut tokens {
+        let Token::Placeholder(placeholder) = &mut token.token else {
+            continue;
+        };
+        if placeholder == "?" {
+            next_position += 1;
+            *placeholder = format!("${}", next_position);
+        }
+        param_count = param_count.max(placeholder_position(placeholder)?);
+    }
+
+    let statements = Parser::new(&dialect)
+        .with_tokens_with_locations(tokens)
+        .parse_statements()?;
+
+    Ok((statements, param_count))
+}
+
+/// The 1-based parameter position of a numbered placeholder like `$2` or `?2`
+fn placeholder_position(placeholder: &str) -> DbResult<usize> {
+    placeholder[1..]
+        .parse()
+        .ok()
+        .filter(|&position| position > 0)
+        .ok_or_else(|| DbError::InvalidInput(format!("Unsupported placeholder '{}'", placeholder)))
+}
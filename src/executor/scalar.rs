@@ -0,0 +1,291 @@
+use super::{column_at, column_ref, QueryExecutor};
+use crate::error::{DbError, DbResult};
+use crate::storage::decimal::{Decimal, MAX_PRECISION};
+use crate::storage::{ColumnType, Storage, TableSchema, Value};
+use sqlparser::ast::{
+    BinaryOperator, Expr, Function, FunctionArg, FunctionArgExpr, FunctionArguments,
+    UnaryOperator,
+};
+
+impl<S: Storage> QueryExecutor<S> {
+    /// Work out the type of the values an expression produces, checking that
+    /// each operator is given operands it can use. Returns `None` for an
+    /// expression that is always NULL.
+    pub(super) fn expr_type(
+        &self,
+        expr: &Expr,
+        schema: &TableSchema,
+    ) -> DbResult<Option<ColumnType>> {
+        match expr {
+            Expr::Identifier(_) | Expr::CompoundIdentifier(_) => {
+                let col_name = column_ref(expr).unwrap_or_default();
+                let col_idx = self.resolve_column(&col_name, schema)?;
+                Ok(Some(column_at(schema, col_idx).column_type.clone()))
+            }
+            Expr::Nested(inner) => self.expr_type(inner, schema),
+            Expr::BinaryOp { left, op, right } if is_arithmetic(op) => {
+                let left_type = self.expr_type(left, schema)?;
+                let right_type = self.expr_type(right, schema)?;
+                match (left_type, right_type) {
+                    (None, _) | (_, None) => Ok(None),
+                    (Some(ColumnType::Integer), Some(ColumnType::Integer)) => {
+                        Ok(Some(ColumnType::Integer))
+                    }
+                    (
+                        Some(left_type @ (ColumnType::Integer | ColumnType::Decimal { .. })),
+                        Some(right_type @ (ColumnType::Integer | ColumnType::Decimal { .. })),
+                    ) => Ok(Some(decimal_result_type(op, &left_type, &right_type))),
+                    (
+                        Some(ColumnType::Integer | ColumnType::Float | ColumnType::Decimal { .. }),
+                        Some(ColumnType::Integer | ColumnType::Float | ColumnType::Decimal { .. }),
+                    ) => Ok(Some(ColumnType::Float)),
+                    (Some(left_type), Some(right_type)) => Err(DbError::TypeMismatch(format!(
+                        "Cannot apply '{}' to {:?} and {:?} in {}",
+                        op, left_type, right_type, expr
+                    ))),
+                }
+            }
+            Expr::UnaryOp {
+                op: op @ (UnaryOperator::Minus | UnaryOperator::Plus),
+                expr: inner,
+            } => match self.expr_type(inner, schema)? {
+                operand_type @ (None
+                | Some(
+                    ColumnType::Integer | ColumnType::Float | ColumnType::Decimal { .. },
+                )) => Ok(operand_type),
+                Some(operand_type) => Err(DbError::TypeMismatch(format!(
+                    "Cannot apply '{}' to {:?} in {}",
+                    op, operand_type, expr
+                ))),
+            },
+            Expr::BinaryOp {
+                left,
+                op: BinaryOperator::StringConcat,
+                right,
+            } => {
+                for operand in [left, right] {
+                    self.expect_text(operand, schema, "'||'")?;
+                }
+                Ok(Some(ColumnType::Text))
+            }
+            Expr::Function(function) => {
+                let (scalar_function, arg) = ScalarFunction::from_call(function)?;
+                self.expect_text(arg, schema, &function.name.to_string().to_uppercase())?;
+                Ok(Some(scalar_function.output_type()))
+            }
+            Expr::Value(_) | Expr::TypedString { .. } => Ok(value_type(&self.expr_to_value(expr)?)),
+            _ => Err(DbError::InvalidInput(format!(
+                "Unsupported expression: {}",
+                expr
+            ))),
+        }
+    }
+
+    /// Check that an operand of a text operator is Text (or always NULL)
+    fn expect_text(&self, operand: &Expr, schema: &TableSchema, operator: &str) -> DbResult<()> {
+        match self.expr_type(operand, schema)? {
+            None | Some(ColumnType::Text) => Ok(()),
+            Some(other) => Err(DbError::TypeMismatch(format!(
+                "{} expects Text but '{}' is {:?}",
+                operator, operand, other
+            ))),
+        }
+    }
+}
+
+/// A built-in function that maps one value to another
+#[derive(Debug, Clone, Copy)]
+pub(super) enum ScalarFunction {
+    Upper,
+    Lower,
+    Length,
+}
+
+impl ScalarFunction {
+    /// Recognise a call to a scalar function, returning it with its argument
+    pub(super) fn from_call(function: &Function) -> DbResult<(ScalarFunction, &Expr)> {
+        let name = function.name.to_string();
+        let scalar_function = match name.to_lowercase().as_str() {
+            "upper" => ScalarFunction::Upper,
+            "lower" => ScalarFunction::Lower,
+            "length" => ScalarFunction::Length,
+            _ => {
+                return Err(DbError::InvalidInput(format!(
+                    "Unknown function '{}'",
+                    name
+                )));
+            }
+        };
+
+        if let FunctionArguments::List(list) = &function.args
+            && let [FunctionArg::Unnamed(FunctionArgExpr::Expr(arg))] = list.args.as_slice()
+        {
+            return Ok((scalar_function, arg));
+        }
+
+        Err(DbError::InvalidInput(format!(
+            "{} takes exactly one argument",
+            name.to_uppercase()
+        )))
+    }
+
+    fn output_type(self) -> ColumnType {
+        match self {
+            ScalarFunction::Upper | ScalarFunction::Lower => ColumnType::Text,
+            ScalarFunction::Length => ColumnType::Integer,
+        }
+    }
+
+    /// Apply the function. NULL and non-Text arguments give NULL.
+    pub(super) fn apply(self, value: Value) -> Value {
+        let Value::Text(text) = value else {
+            return Value::Null;
+        };
+
+        match self {
+            ScalarFunction::Upper => Value::Text(text.to_uppercase()),
+            ScalarFunction::Lower => Value::Text(text.to_lowercase()),
+            ScalarFunction::Length => Value::Integer(text.chars().count() as i64),
+        }
+    }
+}
+
+/// Concatenate two Text values; anything else gives NULL
+pub(super) fn concat(left: Value, right: Value) -> Value {
+    match (left, right) {
+        (Value::Text(mut left), Value::Text(right)) => {
+            left.push_str(&right);
+            Value::Text(left)
+        }
+        _ => Value::Null,
+    }
+}
+
+/// Apply a unary `-` or `+`. Integer overflow and non-numeric operands give NULL.
+pub(super) fn sign(op: &UnaryOperator, value: Value) -> Value {
+    match (op, value) {
+        (UnaryOperator::Minus, Value::Integer(i)) => {
+            i.checked_neg().map(Value::Integer).unwrap_or(Value::Null)
+        }
+        (UnaryOperator::Minus, Value::Float(x)) => Value::Float(-x),
+        (UnaryOperator::Minus, Value::Decimal(d)) => {
+            d.checked_neg().map(Value::Decimal).unwrap_or(Value::Null)
+        }
+        (
+            UnaryOperator::Plus,
+            value @ (Value::Integer(_) | Value::Float(_) | Value::Decimal(_)),
+        ) => value,
+        _ => Value::Null,
+    }
+}
+
+/// Whether an operator is one of `+ - * / %`
+pub(super) fn is_arithmetic(op: &BinaryOperator) -> bool {
+    matches!(
+        op,
+        BinaryOperator::Plus
+            | BinaryOperator::Minus
+            | BinaryOperator::Multiply
+            | BinaryOperator::Divide
+            | BinaryOperator::Modulo
+    )
+}
+
+/// Apply an arithmetic operator. Integers stay Integers (division truncates)
+/// unless a Float is involved. Decimals added, subtracted or multiplied with
+/// Decimals or Integers give exact Decimals; dividing them, or mixing them
+/// with Floats, gives a Float. NULL or non-numeric operands, division by zero
+/// and integer or decimal overflow give NULL.
+pub(super) fn arithmetic(op: &BinaryOperator, left: Value, right: Value) -> Value {
+    if let (Value::Integer(a), Value::Integer(b)) = (&left, &right) {
+        let result = match op {
+            BinaryOperator::Plus => a.checked_add(*b),
+            BinaryOperator::Minus => a.checked_sub(*b),
+            BinaryOperator::Multiply => a.checked_mul(*b),
+            BinaryOperator::Divide => a.checked_div(*b),
+            BinaryOperator::Modulo => a.checked_rem(*b),
+            _ => None,
+        };
+        return result.map(Value::Integer).unwrap_or(Value::Null);
+    }
+
+    if matches!((&left, &right), (Value::Decimal(_), _) | (_, Value::Decimal(_)))
+        && let (Some(a), Some(b)) = (as_decimal(&left), as_decimal(&right))
+        && !matches!(op, BinaryOperator::Divide | BinaryOperator::Modulo)
+    {
+        let result = match op {
+            BinaryOperator::Plus => a.checked_add(b),
+            BinaryOperator::Minus => a.checked_sub(b),
+            BinaryOperator::Multiply => a.checked_mul(b),
+            _ => None,
+        };
+        return result.map(Value::Decimal).unwrap_or(Value::Null);
+    }
+
+    let (Some(a), Some(b)) = (as_f64(&left), as_f64(&right)) else {
+        return Value::Null;
+    };
+    let result = match op {
+        BinaryOperator::Plus => a + b,
+        BinaryOperator::Minus => a - b,
+        BinaryOperator::Multiply => a * b,
+        BinaryOperator::Divide | BinaryOperator::Modulo if b == 0.0 => return Value::Null,
+        BinaryOperator::Divide => a / b,
+        BinaryOperator::Modulo => a % b,
+        _ => return Value::Null,
+    };
+    Value::Float(result)
+}
+
+fn as_f64(value: &Value) -> Option<f64> {
+    match value {
+        Value::Integer(i) => Some(*i as f64),
+        Value::Float(x) => Some(*x),
+        Value::Decimal(d) => Some(d.to_f64()),
+        _ => None,
+    }
+}
+
+/// An Integer or Decimal as an exact Decimal
+fn as_decimal(value: &Value) -> Option<Decimal> {
+    match value {
+        Value::Integer(i) => Some(Decimal::from_i64(*i)),
+        Value::Decimal(d) => Some(*d),
+        _ => None,
+    }
+}
+
+/// The type of `+ - * / %` on Decimals and Integers, matching `arithmetic`.
+/// Sums and differences keep the larger scale and products add the scales;
+/// precision isn't tracked, so results may use every digit a Decimal has.
+fn decimal_result_type(op: &BinaryOperator, left: &ColumnType, right: &ColumnType) -> ColumnType {
+    let scale_of = |column_type: &ColumnType| match column_type {
+        ColumnType::Decimal { scale, .. } => *scale,
+        _ => 0,
+    };
+    let (left_scale, right_scale) = (scale_of(left), scale_of(right));
+    let scale = match op {
+        BinaryOperator::Plus | BinaryOperator::Minus => left_scale.max(right_scale),
+        BinaryOperator::Multiply => (left_scale + right_scale).min(MAX_PRECISION),
+        _ => return ColumnType::Float,
+    };
+    ColumnType::Decimal {
+        precision: MAX_PRECISION,
+        scale,
+    }
+}
+
+/// The column type a literal value belongs to, or `None` for NULL
+fn value_type(value: &Value) -> Option<ColumnType> {
+    match value {
+        Value::Integer(_) => Some(ColumnType::Integer),
+        Value::Float(_) => Some(ColumnType::Float),
+        Value::Text(_) => Some(ColumnType::Text),
+        Value::Timestamp(_) => Some(ColumnType::Timestamp),
+        Value::Decimal(d) => Some(ColumnType::Decimal {
+            precision: MAX_PRECISION,
+            scale: d.scale(),
+        }),
+        Value::Null => None,
+    }
+}
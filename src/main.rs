@@ -1,45 +1,162 @@
-mod executor;
-mod storage;
+use selfhealdb::error::DbResult;
+use selfhealdb::executor::display::format_rows;
+use selfhealdb::executor::{ExecutionResult, QueryExecutor, QueryResult};
+use selfhealdb::storage::BitcaskStorage;
+use std::fs::File;
+use std::io::{self, BufRead, Write};
 
-use executor::QueryExecutor;
-use storage::BitcaskStorage;
+/// Database file used when no path is given on the command line
+const DEFAULT_DB_PATH: &str = "selfheal.db";
 
-fn main() -> std::io::Result<()> {
-    println!("=== SelfHealDB - SQL Executor Test ===\n");
+fn main() -> DbResult<()> {
+    // `selfhealdb --recover DAMAGED NEW` salvages a damaged database
+    let args: Vec<String> = std::env::args().collect();
+    if let [_, flag, path, output_path] = args.as_slice()
+        && flag == "--recover"
+    {
+        return recover(path, output_path);
+    }
+
+    // `selfhealdb --read-only PATH` opens an existing database without writing to it
+    let read_only = args.get(1).is_some_and(|arg| arg == "--read-only");
+    let path = args
+        .get(if read_only { 2 } else { 1 })
+        .cloned()
+        .unwrap_or_else(|| DEFAULT_DB_PATH.to_string());
 
-    let storage = BitcaskStorage::new("sqltest.db")?;
+    let storage = if read_only {
+        BitcaskStorage::open_read_only(&path)?
+    } else {
+        BitcaskStorage::new(&path)?
+    };
     let mut executor = QueryExecutor::new(storage);
 
-    // Create table
-    println!("--- Creating Table ---");
-    executor.execute("CREATE TABLE users (id INTEGER, name TEXT, age INTEGER)")?;
-
-    // Insert data
-    println!("\n--- Inserting Data ---");
-    for i in 1..=1000 {
-        let sql = format!(
-            "INSERT INTO users VALUES ({}, 'User{}', {})",
-            i,
-            i,
-            20 + (i % 30)
-        );
-        executor.execute(&sql)?;
+    println!("=== SelfHealDB ===");
+    println!("Connected to '{}'{}", path, if read_only { " (read-only)" } else { "" });
+    println!("End statements with ';'. Type .help for commands.\n");
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    let mut statement = String::new();
+
+    loop {
+        // Show a continuation prompt while a statement is still open
+        print!("{}", if statement.is_empty() { "sql> " } else { " ..> " });
+        io::stdout().flush()?;
+
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => break,
+        };
+        let trimmed = line.trim();
+
+        if statement.is_empty() {
+            if trimmed.is_empty() {
+                continue;
+            }
+            if trimmed == ".exit" {
+                break;
+            }
+            if trimmed.starts_with('.') {
+                if let Err(e) = run_command(&mut executor, trimmed) {
+                    println!("Error: {}", e);
+                }
+                continue;
+            }
+        }
+
+        statement.push_str(&line);
+        statement.push('\n');
+
+        if !trimmed.ends_with(';') {
+            continue;
+        }
+
+        // A line may hold several statements
+        match executor.execute_script(&statement) {
+            Ok(results) => results.iter().for_each(print_result),
+            Err(e) => println!("Error: {}", e),
+        }
+        statement.clear();
     }
-    println!("Inserted 1000 rows");
 
-    // Query without index
-    println!("\n--- Query WITHOUT Index ---");
-    let result = executor.execute("SELECT * FROM users WHERE id = 500")?;
-    println!("Query took: {:?}", result.duration);
+    println!();
+    Ok(())
+}
 
-    // Create index
-    println!("\n--- Creating Index ---");
-    executor.storage.create_index("users", "id")?;
+/// Copy what can still be read from a damaged database to a new one
+fn recover(path: &str, output_path: &str) -> DbResult<()> {
+    let report = BitcaskStorage::recover(path, output_path)?;
+    println!("Recovered '{}' into '{}'", path, output_path);
+    println!("  {} record(s) read", report.records_read);
+    println!("  {} row(s) recovered", report.rows_recovered);
+    println!("  {} row(s) rejected", report.rows_rejected);
+    println!(
+        "  {} damaged region(s), {} byte(s) skipped",
+        report.damaged_regions, report.bytes_skipped
+    );
+    Ok(())
+}
 
-    // Query with index
-    println!("\n--- Query WITH Index ---");
-    let result = executor.execute("SELECT * FROM users WHERE id = 500")?;
-    println!("Query took: {:?}", result.duration);
+/// Run a shell command such as `.export users users.csv`
+fn run_command(executor: &mut QueryExecutor, command: &str) -> DbResult<()> {
+    let args: Vec<&str> = command.split_whitespace().collect();
+
+    match args.as_slice() {
+        [".help"] => {
+            println!(".export TABLE FILE   Write a table to a CSV file");
+            println!(".import TABLE FILE   Load rows from a CSV file into a table");
+            println!(".read FILE           Run the SQL statements in a file");
+            println!(".sync                Force all writes so far to disk");
+            println!(".exit                Quit the shell");
+        }
+        [".export", table, file] => {
+            executor.export_csv(table, File::create(file)?)?;
+            println!("Exported '{}' to '{}'", table, file);
+        }
+        [".import", table, file] => {
+            let count = executor.import_csv(table, File::open(file)?)?;
+            println!("Imported {} row(s) into '{}'", count, table);
+        }
+        [".read", file] => {
+            let sql = std::fs::read_to_string(file)?;
+            executor.execute_script(&sql)?.iter().for_each(print_result);
+        }
+        [".sync"] => {
+            executor.storage.sync()?;
+            println!("Synced to disk");
+        }
+        _ => println!("Unknown command '{}'. Type .help for commands.", command),
+    }
 
     Ok(())
 }
+
+/// Print a statement's outcome followed by how long it took
+fn print_result(result: &QueryResult) {
+    match &result.result {
+        ExecutionResult::Created => println!("Table created"),
+        ExecutionResult::IndexCreated => println!("Index created"),
+        ExecutionResult::Inserted(count) if result.inserted_ids.is_empty() => {
+            println!("{} row(s) inserted", count)
+        }
+        ExecutionResult::Inserted(count) => {
+            let ids: Vec<String> = result.inserted_ids.iter().map(i64::to_string).collect();
+            println!("{} row(s) inserted (id {})", count, ids.join(", "))
+        }
+        ExecutionResult::Selected(rows) => {
+            if let Some(schema) = &result.schema {
+                print!("{}", format_rows(schema, rows));
+            }
+            println!("({} row(s))", rows.len());
+        }
+        ExecutionResult::Deleted(count) => println!("{} row(s) deleted", count),
+        ExecutionResult::Dropped => println!("Table dropped"),
+        ExecutionResult::IndexDropped => println!("Index dropped"),
+        ExecutionResult::Truncated => println!("Table truncated"),
+        ExecutionResult::TransactionStarted => println!("Transaction started"),
+        ExecutionResult::Committed => println!("Transaction committed"),
+        ExecutionResult::RolledBack => println!("Transaction rolled back"),
+    }
+    println!("Time: {:?}\n", result.duration);
+}
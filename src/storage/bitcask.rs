@@ -1,410 +1,3687 @@
-use super::types::{Index, Row, TableSchema, Value};
-use std::collections::HashMap;
-use std::fs::{File, OpenOptions};
+use super::checksum::{Crc32, crc32};
+use super::log_file::{LogFile, MemoryFile};
+use super::types::{
+    Column, ColumnType, CompositeIndex, Index, IndexStats, IntWidth, RangeIndex, Row, TableSchema,
+    Value,
+};
+use crate::error::{DbError, DbResult};
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
 use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::ops::Bound;
 use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Marker byte for a versioned table schema record
+const SCHEMA_MARKER: u8 = 0xFA;
+/// Marker bytes for the unversioned schema records written by earlier
+/// releases; these are still read, but never written
+const SCHEMA_V0_MARKER: u8 = 0xFF;
+const SCHEMA_V1_MARKER: u8 = 0xFB;
+/// Layout version of newly written schema records
+const SCHEMA_VERSION: u32 = 6;
+/// Marker byte for a row record
+const ROW_MARKER: u8 = 0xAA;
+/// Marker byte for a tombstone record (a deleted row)
+const TOMBSTONE_MARKER: u8 = 0xBB;
+/// Marker byte for a dropped table record
+const DROP_TABLE_MARKER: u8 = 0xFD;
+/// Marker byte for an index definition record
+const INDEX_MARKER: u8 = 0xFE;
+/// Marker byte for a range index definition record
+const RANGE_INDEX_MARKER: u8 = 0xFC;
+/// Marker byte for a dropped index record
+const DROP_INDEX_MARKER: u8 = 0xF9;
+/// Marker byte for a multi-column index definition record
+const COMPOSITE_INDEX_MARKER: u8 = 0xF8;
+/// Marker byte for a truncated table record
+const TRUNCATE_MARKER: u8 = 0xF7;
+/// Marker byte for a case-insensitive index definition record
+const CASE_INSENSITIVE_INDEX_MARKER: u8 = 0xF6;
+/// Marker bytes for the records that open, commit and roll back a transaction
+const BEGIN_MARKER: u8 = 0xCA;
+const COMMIT_MARKER: u8 = 0xCB;
+const ROLLBACK_MARKER: u8 = 0xCC;
+
+/// Size at which the active data log segment is closed and a new one started
+pub const DEFAULT_SEGMENT_SIZE: u64 = 128 * 1024 * 1024;
+
+/// Number of writes after which a checkpoint is taken automatically
+pub const DEFAULT_CHECKPOINT_INTERVAL: u64 = 10_000;
+
+/// Largest row or schema body a record may hold
+pub const DEFAULT_MAX_RECORD_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Rows `load_rows` validates and writes at a time, bounding how many it
+/// holds in memory
+pub const LOAD_CHUNK_ROWS: usize = 10_000;
+
+/// Bounds on the length a record may claim, checked before anything is
+/// allocated for it, so a corrupt length can't exhaust memory
+#[derive(Clone, Copy)]
+struct RecordBounds {
+    /// Offset where the readable log ends
+    end: u64,
+    /// Largest body a record may hold
+    max_record_size: u64,
+}
+
+/// Whether opening a database may create it, or must find an existing one
+#[derive(Clone, Copy)]
+enum OpenMode {
+    OpenOrCreate,
+    Open,
+    Create,
+    /// Open an existing database without write access to its files
+    ReadOnly,
+}
+
+/// How eagerly written records are forced to disk.
+///
+/// In every mode, records still in the write buffer are lost if the process
+/// crashes before `flush`, which the executor calls after each statement.
+/// `BitcaskStorage::sync` forces everything written so far to disk in any mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DurabilityMode {
+    /// Leave syncing to the OS. Flushed records survive a process crash, but
+    /// an OS crash or power loss loses whatever the OS has not yet written
+    /// back, typically up to 30 seconds of writes.
+    #[default]
+    None,
+    /// Flush and sync after every write, so a write survives any crash once
+    /// it returns. This is the slowest mode.
+    OnCommit,
+    /// Sync from a background thread once a second, so an OS crash or power
+    /// loss loses at most about a second of flushed records.
+    EverySecond,
+}
+
+/// What `BitcaskStorage::recover` salvaged from a damaged database
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecoveryReport {
+    /// Records of the data log that could still be read
+    pub records_read: u64,
+    /// Live rows written to the recovered database
+    pub rows_recovered: u64,
+    /// Readable rows that couldn't be kept, such as a row whose delete was
+    /// lost in a damaged stretch and that now repeats a UNIQUE value
+    pub rows_rejected: u64,
+    /// Damaged stretches of the data log that were skipped
+    pub damaged_regions: u64,
+    /// Bytes of the data log skipped over in those stretches
+    pub bytes_skipped: u64,
+}
+
+/// Size and liveness figures from `BitcaskStorage::stats`, for deciding
+/// when a `compact` is worth running
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StorageStats {
+    /// Bytes in the data log segments and the catalog, including records
+    /// still in the write buffer
+    pub file_size: u64,
+    /// Number of tables
+    pub tables: usize,
+    /// Rows that are not deleted, across every table
+    pub live_rows: u64,
+    /// Records in the data log
+    pub total_records: u64,
+    /// Records that compaction would drop: deleted, rolled-back and
+    /// truncated rows, and the tombstones and markers recording that
+    pub stale_records: u64,
+    /// Approximate bytes held by the in-memory indexes
+    pub index_memory: usize,
+}
+
+/// What `BitcaskStorage::load_rows` loaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadReport {
+    /// Rows written
+    pub rows: u64,
+    /// Time the whole load took, including the final sync
+    pub elapsed: Duration,
+}
+
+/// The kinds of index the engine can persist and rebuild
+#[derive(Clone, Copy)]
+enum IndexKind {
+    /// Equality lookups (`Index`)
+    Hash,
+    /// Ordered range lookups (`RangeIndex`)
+    Range,
+    /// Equality lookups on several columns together (`CompositeIndex`)
+    Composite,
+    /// Equality lookups ignoring the case of text (`Index` with `case_insensitive`)
+    CaseInsensitive,
+}
+
+/// A live index: its table, its columns in order and its kind
+type IndexDefinition = (String, Vec<String>, IndexKind);
+
+/// Table schema in an older layout, generic over that layout's column type
+#[derive(Deserialize)]
+struct LegacyTableSchema<C> {
+    name: String,
+    columns: Vec<C>,
+}
+
+/// Column layout of version 0: no constraints
+#[derive(Deserialize)]
+struct ColumnV0 {
+    name: String,
+    column_type: ColumnType,
+}
+
+/// Column layout of version 1: adds NOT NULL
+#[derive(Deserialize)]
+struct ColumnV1 {
+    name: String,
+    column_type: ColumnType,
+    nullable: bool,
+}
+
+/// Column layout of version 2: adds UNIQUE
+#[derive(Deserialize)]
+struct ColumnV2 {
+    name: String,
+    column_type: ColumnType,
+    nullable: bool,
+    unique: bool,
+}
+
+/// Column layout of version 3: adds DEFAULT
+#[derive(Deserialize)]
+struct ColumnV3 {
+    name: String,
+    column_type: ColumnType,
+    nullable: bool,
+    unique: bool,
+    default: Option<Value>,
+}
+
+/// Column layout of version 4: adds the integer width
+#[derive(Deserialize)]
+struct ColumnV4 {
+    name: String,
+    column_type: ColumnType,
+    int_width: IntWidth,
+    nullable: bool,
+    unique: bool,
+    default: Option<Value>,
+}
+
+/// Column layout of version 5: adds PRIMARY KEY
+#[derive(Deserialize)]
+struct ColumnV5 {
+    name: String,
+    column_type: ColumnType,
+    int_width: IntWidth,
+    nullable: bool,
+    unique: bool,
+    primary_key: bool,
+    default: Option<Value>,
+}
+
+impl From<ColumnV0> for Column {
+    fn from(column: ColumnV0) -> Self {
+        Column {
+            name: column.name,
+            column_type: column.column_type,
+            int_width: IntWidth::Big,
+            nullable: true,
+            unique: false,
+            primary_key: false,
+            auto_increment: false,
+            default: None,
+        }
+    }
+}
+
+impl From<ColumnV1> for Column {
+    fn from(column: ColumnV1) -> Self {
+        Column {
+            name: column.name,
+            column_type: column.column_type,
+            int_width: IntWidth::Big,
+            nullable: column.nullable,
+            unique: false,
+            primary_key: false,
+            auto_increment: false,
+            default: None,
+        }
+    }
+}
+
+impl From<ColumnV2> for Column {
+    fn from(column: ColumnV2) -> Self {
+        Column {
+            name: column.name,
+            column_type: column.column_type,
+            int_width: IntWidth::Big,
+            nullable: column.nullable,
+            unique: column.unique,
+            primary_key: false,
+            auto_increment: false,
+            default: None,
+        }
+    }
+}
+
+impl From<ColumnV3> for Column {
+    fn from(column: ColumnV3) -> Self {
+        Column {
+            name: column.name,
+            column_type: column.column_type,
+            int_width: IntWidth::Big,
+            nullable: column.nullable,
+            unique: column.unique,
+            primary_key: false,
+            auto_increment: false,
+            default: column.default,
+        }
+    }
+}
+
+impl From<ColumnV4> for Column {
+    fn from(column: ColumnV4) -> Self {
+        Column {
+            name: column.name,
+            column_type: column.column_type,
+            int_width: column.int_width,
+            nullable: column.nullable,
+            unique: column.unique,
+            primary_key: false,
+            auto_increment: false,
+            default: column.default,
+        }
+    }
+}
+
+impl From<ColumnV5> for Column {
+    fn from(column: ColumnV5) -> Self {
+        Column {
+            name: column.name,
+            column_type: column.column_type,
+            int_width: column.int_width,
+            nullable: column.nullable,
+            unique: column.unique,
+            primary_key: column.primary_key,
+            auto_increment: false,
+            default: column.default,
+        }
+    }
+}
+
+impl<C: Into<Column>> From<LegacyTableSchema<C>> for TableSchema {
+    fn from(legacy: LegacyTableSchema<C>) -> Self {
+        let columns = legacy.columns.into_iter().map(Into::into).collect();
+        TableSchema::new(legacy.name, columns)
+    }
+}
+
+/// Decode schema bytes written in any known layout version, upgrading older
+/// layouts to the current one. A layout newer than this build knows is
+/// refused rather than guessed at, so the database fails to open.
+fn decode_schema(version: u32, bytes: &[u8]) -> io::Result<TableSchema> {
+    match version {
+        0 => Ok(deserialize::<LegacyTableSchema<ColumnV0>>(bytes)?.into()),
+        1 => Ok(deserialize::<LegacyTableSchema<ColumnV1>>(bytes)?.into()),
+        2 => Ok(deserialize::<LegacyTableSchema<ColumnV2>>(bytes)?.into()),
+        3 => Ok(deserialize::<LegacyTableSchema<ColumnV3>>(bytes)?.into()),
+        4 => Ok(deserialize::<LegacyTableSchema<ColumnV4>>(bytes)?.into()),
+        5 => Ok(deserialize::<LegacyTableSchema<ColumnV5>>(bytes)?.into()),
+        SCHEMA_VERSION => deserialize(bytes),
+        _ if version > SCHEMA_VERSION => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Schema version {} was written by a newer release; this one reads up to version {}",
+                version, SCHEMA_VERSION
+            ),
+        )),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Unknown schema version {}", version),
+        )),
+    }
+}
+
+/// A single record decoded from the data log or the catalog
+enum Record {
+    /// Table schema
+    Schema(TableSchema),
+    /// Serialized row belonging to `table`
+    Row { table: String, bytes: Vec<u8> },
+    /// Marks the row written at `row_offset` as deleted
+    Tombstone { table: String, row_offset: u64 },
+    /// Marks `table` and every row written before it as dropped
+    DropTable { table: String },
+    /// Marks every row of `table` written before it as deleted
+    Truncate { table: String },
+    /// Declares an index on `table`.`columns`; only a composite index has more than one
+    Index { table: String, columns: Vec<String>, kind: IndexKind },
+    /// Removes every index on `table`.`column`, including composite ones
+    DropIndex { table: String, column: String },
+    /// Opens a transaction; the records up to its commit only count if it commits
+    Begin,
+    /// Commits the open transaction
+    Commit,
+    /// Discards the records written since the open transaction began
+    Rollback,
+}
+
+/// State rebuilt from the data log up to `data_offset`, saved so that opening
+/// the database only has to replay the records after it
+#[derive(Serialize, Deserialize)]
+struct Checkpoint {
+    data_offset: u64,
+    /// Maps table_name -> (row_count, offset where the table's rows start)
+    tables: HashMap<String, (u64, u64)>,
+    deleted_rows: Vec<u64>,
+}
+
+/// A change made inside a transaction, kept so a rollback can undo it
+enum TransactionChange {
+    /// A row was written at `offset`
+    Inserted { table: String, offset: u64 },
+    /// The row at `offset` was deleted
+    Deleted { table: String, offset: u64 },
+}
 
 /// The main storage engine using the Bitcask model
-/// - Append-only log file for durability
+/// - Append-only data log of rows and tombstones for durability, split into
+///   read-only segments plus one active segment that takes new writes
+/// - Append-only catalog of schemas and index definitions, kept apart from
+///   the rows so compaction only has to rewrite the data log
 /// - In-memory index for fast lookups
+/// - Reads take `&self` and never move the file cursor, so the engine can be
+///   shared as `Arc<RwLock<BitcaskStorage>>` with many concurrent readers
 pub struct BitcaskStorage {
-    data_file: File,
-    data_file_path: String,
+    /// Buffered appender for the active segment. Records reach the file when
+    /// the buffer fills or on `flush`; until then reads find them in the buffer.
+    writer: BufWriter<Box<dyn LogFile>>,
+    /// Path of the first data log segment; `None` for an in-memory database
+    data_file_path: Option<String>,
+    /// Closed, read-only segments of the data log, oldest first
+    segments: Vec<Segment>,
+    /// Log offset of the first byte of the active segment
+    active_base: u64,
+    /// Size at which the active segment is closed
+    max_segment_size: u64,
+    /// Schema, index and drop records; written through immediately
+    catalog_file: Box<dyn LogFile>,
     /// Maps table_name -> (schema, row_count)
     pub tables: HashMap<String, (TableSchema, u64)>,
     /// Maps table_name -> column_name -> Index
     pub indexes: HashMap<String, HashMap<String, Index>>,
-    /// Current file offset (where next write will go)
+    /// Maps table_name -> column_name -> RangeIndex
+    pub range_indexes: HashMap<String, HashMap<String, RangeIndex>>,
+    /// Maps table_name -> indexes on several columns
+    pub composite_indexes: HashMap<String, Vec<CompositeIndex>>,
+    /// Current log offset (where next write will go). Offsets run on across
+    /// segments, so segment `n` starts where segment `n - 1` ends.
     current_offset: u64,
+    /// Data log offset where each table's rows start; rows before it belong
+    /// to an earlier, dropped table of the same name
+    table_offsets: HashMap<String, u64>,
+    /// Offsets of rows that have been deleted by a tombstone
+    deleted_rows: HashSet<u64>,
+    /// Maps table_name -> next value of its AUTO_INCREMENT column. Filled in
+    /// from the largest stored value the first time a table needs one.
+    /// `None` once the sequence has passed i64::MAX.
+    next_ids: HashMap<String, Option<i64>>,
+    /// Changes made by the open transaction, oldest first; `None` outside one
+    transaction: Option<Vec<TransactionChange>>,
+    /// Writes since the last checkpoint
+    writes_since_checkpoint: u64,
+    /// Writes after which a checkpoint is taken; 0 turns automatic checkpoints off
+    checkpoint_interval: u64,
+    /// Largest row or schema body a record may hold
+    max_record_size: u64,
+    durability: DurabilityMode,
+    /// Whether the files were opened read-only, so every write is refused
+    read_only: bool,
+    /// Syncs the files once a second in `EverySecond` mode. Declared after
+    /// the writer, which is flushed first when the engine is dropped.
+    background_sync: Option<BackgroundSync>,
+}
+
+/// The thread that syncs the active segment and the catalog once a second
+struct BackgroundSync {
+    files: Arc<Mutex<Vec<Box<dyn LogFile>>>>,
+    /// Dropping the sender stops the thread after a final sync
+    _stop: mpsc::Sender<()>,
+}
+
+impl BackgroundSync {
+    fn start(files: Vec<Box<dyn LogFile>>) -> Self {
+        let files = Arc::new(Mutex::new(files));
+        let (stop, stopped) = mpsc::channel::<()>();
+
+        let thread_files = Arc::clone(&files);
+        thread::spawn(move || loop {
+            let waited = stopped.recv_timeout(Duration::from_secs(1));
+
+            if let Ok(files) = thread_files.lock() {
+                for file in files.iter() {
+                    // There is no caller to report a failure to; the next
+                    // sync tries again
+                    let _ = file.sync();
+                }
+            }
+
+            if !matches!(waited, Err(RecvTimeoutError::Timeout)) {
+                break;
+            }
+        });
+
+        BackgroundSync { files, _stop: stop }
+    }
+}
+
+/// A closed segment of the data log
+struct Segment {
+    file: Box<dyn LogFile>,
+    /// Log offset of the segment's first byte
+    base: u64,
+}
+
+/// Reads the log sequentially from a starting offset using positioned reads,
+/// so readers sharing one file handle never move each other's cursor.
+/// Reads move from one segment to the next, and bytes past the end of the
+/// active segment are read from the writer's pending buffer.
+struct PositionedReader<'a> {
+    segments: &'a [Segment],
+    active: &'a dyn LogFile,
+    active_base: u64,
+    /// Records written but not yet flushed to the active segment
+    pending: &'a [u8],
+    /// Log offset of the first pending byte
+    pending_start: u64,
+    offset: u64,
+}
+
+impl Read for PositionedReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = if self.offset >= self.pending_start {
+            let start = (self.offset - self.pending_start) as usize;
+            let pending = self.pending.get(start..).unwrap_or_default();
+            let read = pending.len().min(buf.len());
+            buf[..read].copy_from_slice(&pending[..read]);
+            read
+        } else {
+            // Find the segment holding the offset and where it ends
+            let (file, base, end) = if self.offset >= self.active_base {
+                (self.active, self.active_base, self.pending_start)
+            } else {
+                let i = self.segments.partition_point(|segment| segment.base <= self.offset) - 1;
+                let end = self.segments.get(i + 1).map_or(self.active_base, |next| next.base);
+                (&*self.segments[i].file, self.segments[i].base, end)
+            };
+
+            // Stop at the end of the segment; the rest comes from the next one
+            let in_segment = (end - self.offset).min(buf.len() as u64) as usize;
+            file.read_at(&mut buf[..in_segment], self.offset - base)?
+        };
+
+        self.offset += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for PositionedReader<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let end = self.pending_start + self.pending.len() as u64;
+        let offset = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => self.offset.checked_add_signed(delta),
+            SeekFrom::End(delta) => end.checked_add_signed(delta),
+        };
+        self.offset = offset.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Seek before the start of the log")
+        })?;
+        Ok(self.offset)
+    }
+}
+
+/// Lazily reads the live rows of one table from the data file,
+/// yielding each row with its file offset
+pub struct ScanIter<'a> {
+    reader: BufReader<PositionedReader<'a>>,
+    table_name: String,
+    deleted_rows: &'a HashSet<u64>,
+    /// Offset of the next record to read
+    offset: u64,
+    /// End of the log when the scan started, and the largest record accepted
+    bounds: RecordBounds,
+    /// Positions of the columns to keep in each row, or `None` for all of them
+    columns: Option<Vec<usize>>,
+}
+
+impl Iterator for ScanIter<'_> {
+    type Item = DbResult<(u64, Row)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.offset < self.bounds.end {
+            let record_offset = self.offset;
+            let record = read_record(&mut self.reader, record_offset, self.bounds);
+            let (record, record_len) = match record {
+                Ok(Some(record)) => record,
+                Ok(None) => return None,
+                Err(e) => {
+                    // Stop after an error rather than reading garbage
+                    self.offset = self.bounds.end;
+                    return Some(Err(e.into()));
+                }
+            };
+            self.offset += record_len;
+
+            if let Record::Row { table, bytes } = record
+                && table == self.table_name
+                && !self.deleted_rows.contains(&record_offset)
+            {
+                let row = deserialize(&bytes).map_err(DbError::from);
+                let row = match &self.columns {
+                    Some(columns) => row.map(|row| project_row(&row, columns)),
+                    None => row,
+                };
+                return Some(row.map(|row| (record_offset, row)));
+            }
+        }
+
+        None
+    }
 }
 
 impl BitcaskStorage {
-    /// Create a new storage engine with the given file path
-    pub fn new(path: &str) -> io::Result<Self> {
+    /// Open the database at the given file path, creating it if it doesn't exist.
+    /// Rows are stored in the data log at `path` and table definitions in
+    /// a catalog file at `path.catalog`.
+    pub fn new(path: &str) -> DbResult<Self> {
+        Self::open_with(
+            path,
+            DEFAULT_SEGMENT_SIZE,
+            DurabilityMode::default(),
+            OpenMode::OpenOrCreate,
+        )
+    }
+
+    /// Open an existing database, failing with `NotFound` if there is none at `path`
+    pub fn open(path: &str) -> DbResult<Self> {
+        Self::open_with(path, DEFAULT_SEGMENT_SIZE, DurabilityMode::default(), OpenMode::Open)
+    }
+
+    /// Create a fresh database, failing with `AlreadyExists` if there is one at `path`
+    pub fn create(path: &str) -> DbResult<Self> {
+        Self::open_with(path, DEFAULT_SEGMENT_SIZE, DurabilityMode::default(), OpenMode::Create)
+    }
+
+    /// Open an existing database for reading only. Its files are opened
+    /// without write access and are never modified: scans, lookups and the
+    /// in-memory indexes work as usual, but every write fails. Several
+    /// processes may read a database this way while another one writes it.
+    pub fn open_read_only(path: &str) -> DbResult<Self> {
+        Self::open_with(path, DEFAULT_SEGMENT_SIZE, DurabilityMode::default(), OpenMode::ReadOnly)
+    }
+
+    /// Open or create a database whose data log moves on to a new segment
+    /// file once the active one reaches `max_segment_size` bytes.
+    /// The first segment is `path`, and later ones are `path.1`, `path.2`, ...
+    pub fn with_segment_size(path: &str, max_segment_size: u64) -> DbResult<Self> {
+        Self::open_with(path, max_segment_size, DurabilityMode::default(), OpenMode::OpenOrCreate)
+    }
+
+    /// Open or create a database that syncs writes to disk as `mode` describes
+    pub fn with_durability(path: &str, mode: DurabilityMode) -> DbResult<Self> {
+        Self::open_with(path, DEFAULT_SEGMENT_SIZE, mode, OpenMode::OpenOrCreate)
+    }
+
+    /// Create a storage engine that keeps its log and catalog in memory.
+    /// Nothing touches the filesystem, and everything is lost on drop.
+    pub fn in_memory() -> Self {
+        BitcaskStorage {
+            writer: BufWriter::new(Box::new(MemoryFile::default())),
+            data_file_path: None,
+            segments: Vec::new(),
+            active_base: 0,
+            max_segment_size: DEFAULT_SEGMENT_SIZE,
+            catalog_file: Box::new(MemoryFile::default()),
+            tables: HashMap::new(),
+            indexes: HashMap::new(),
+            range_indexes: HashMap::new(),
+            composite_indexes: HashMap::new(),
+            current_offset: 0,
+            table_offsets: HashMap::new(),
+            deleted_rows: HashSet::new(),
+            next_ids: HashMap::new(),
+            transaction: None,
+            writes_since_checkpoint: 0,
+            // Nothing to checkpoint to
+            checkpoint_interval: 0,
+            max_record_size: DEFAULT_MAX_RECORD_SIZE,
+            durability: DurabilityMode::None,
+            read_only: false,
+            background_sync: None,
+        }
+    }
+
+    fn open_with(
+        path: &str,
+        max_segment_size: u64,
+        durability: DurabilityMode,
+        open_mode: OpenMode,
+    ) -> DbResult<Self> {
+        let read_only = matches!(open_mode, OpenMode::ReadOnly);
+        let catalog_path = format!("{}.catalog", path);
+        match open_mode {
+            OpenMode::Open | OpenMode::ReadOnly if !Path::new(path).exists() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("Database '{}' not found", path),
+                )
+                .into());
+            }
+            OpenMode::Create if Path::new(path).exists() || Path::new(&catalog_path).exists() => {
+                return Err(DbError::AlreadyExists(format!(
+                    "Database '{}' already exists",
+                    path
+                )));
+            }
+            // Finishing an interrupted compaction removes and renames files
+            OpenMode::ReadOnly if Path::new(&format!("{}.compacted", path)).exists() => {
+                return Err(DbError::InvalidInput(format!(
+                    "Database '{}' has an unfinished compaction; open it for writing first",
+                    path
+                )));
+            }
+            _ => {}
+        }
+        if !read_only {
+            finish_compaction(path)?;
+        }
+
+        // Every segment but the last is closed
+        let mut segments = Vec::new();
+        let mut active_base = 0u64;
+        while Path::new(&segment_path(path, segments.len() + 1)).exists() {
+            let file = File::open(segment_path(path, segments.len()))?;
+            let len = file.metadata()?.len();
+            segments.push(Segment { file: Box::new(file), base: active_base });
+            active_base += len;
+        }
+
+        // Append mode keeps every write at the end of the log, even after
+        // reads have moved the file cursor
         let file = OpenOptions::new()
             .read(true)
-            .write(true)
-            .create(true)
-            .open(path)?;
+            .append(!read_only)
+            .create(!read_only)
+            .open(segment_path(path, segments.len()))?;
 
-        // Get current file size (for appending)
+        // Get current log size (for appending)
         let metadata = file.metadata()?;
-        let current_offset = metadata.len();
+        let current_offset = active_base + metadata.len();
+
+        // A read-only open can't create a missing catalog, so it reads a
+        // legacy database's schemas from the data log without migrating them
+        let catalog_file: Box<dyn LogFile> = if read_only && !Path::new(&catalog_path).exists() {
+            Box::new(MemoryFile::default())
+        } else {
+            Box::new(
+                OpenOptions::new()
+                    .read(true)
+                    .append(!read_only)
+                    .create(!read_only)
+                    .open(&catalog_path)?,
+            )
+        };
+
+        // Databases written before the catalog existed keep their schemas in
+        // the data log, and have rows but an empty catalog
+        let legacy_layout = current_offset > 0 && catalog_file.size()? == 0;
+
+        let mut storage = BitcaskStorage {
+            writer: BufWriter::new(Box::new(file)),
+            data_file_path: Some(path.to_string()),
+            segments,
+            active_base,
+            max_segment_size,
+            catalog_file,
+            tables: HashMap::new(),
+            indexes: HashMap::new(),
+            range_indexes: HashMap::new(),
+            composite_indexes: HashMap::new(),
+            current_offset,
+            table_offsets: HashMap::new(),
+            deleted_rows: HashSet::new(),
+            next_ids: HashMap::new(),
+            transaction: None,
+            writes_since_checkpoint: 0,
+            checkpoint_interval: if read_only { 0 } else { DEFAULT_CHECKPOINT_INTERVAL },
+            max_record_size: DEFAULT_MAX_RECORD_SIZE,
+            durability,
+            read_only,
+            background_sync: None,
+        };
+
+        // Recover tables written by previous sessions
+        let catalog = if legacy_layout {
+            storage.writer.get_ref().try_clone()?
+        } else {
+            storage.catalog_file.try_clone()?
+        };
+        let index_definitions = storage.replay_catalog(&*catalog)?;
+        if legacy_layout && !read_only {
+            storage.migrate_catalog(&catalog_path, &index_definitions)?;
+        }
+        let replay_from = storage.load_checkpoint(path)?;
+        storage.replay_data(replay_from)?;
+
+        // Rebuild the in-memory indexes from the recovered rows
+        for (table_name, column_names, kind) in index_definitions {
+            match kind {
+                IndexKind::Hash | IndexKind::CaseInsensitive => {
+                    let case_insensitive = matches!(kind, IndexKind::CaseInsensitive);
+                    let index =
+                        storage.build_index(&table_name, &column_names[0], case_insensitive)?;
+                    storage
+                        .indexes
+                        .entry(table_name)
+                        .or_default()
+                        .insert(column_names[0].clone(), index);
+                }
+                IndexKind::Range => {
+                    let index = storage.build_range_index(&table_name, &column_names[0])?;
+                    storage
+                        .range_indexes
+                        .entry(table_name)
+                        .or_default()
+                        .insert(column_names[0].clone(), index);
+                }
+                IndexKind::Composite => {
+                    let index = storage.build_composite_index(&table_name, &column_names)?;
+                    storage.composite_indexes.entry(table_name).or_default().push(index);
+                }
+            }
+        }
+
+        if durability == DurabilityMode::EverySecond && !read_only {
+            storage.background_sync = Some(BackgroundSync::start(storage.files_to_sync()?));
+        }
+
+        Ok(storage)
+    }
+
+    /// Replay a catalog to rebuild the table schemas.
+    /// Returns the definitions of indexes that are still live.
+    fn replay_catalog(&mut self, file: &dyn LogFile) -> DbResult<Vec<IndexDefinition>> {
+        let file_len = file.size()?;
+        let mut reader = BufReader::new(PositionedReader {
+            segments: &[],
+            active: file,
+            active_base: 0,
+            pending: &[],
+            pending_start: file_len,
+            offset: 0,
+        });
+        let mut replayed_offset = 0u64;
+        let mut index_definitions: Vec<IndexDefinition> = Vec::new();
+        let bounds = RecordBounds {
+            end: file_len,
+            max_record_size: self.max_record_size,
+        };
+
+        while let Some((record, record_len)) = read_record(&mut reader, replayed_offset, bounds)? {
+            match record {
+                Record::Schema(schema) => {
+                    // Rows start at the beginning of the data log unless
+                    // replay_data finds an earlier table of the same name
+                    self.table_offsets.insert(schema.name.clone(), 0);
+                    self.tables.insert(schema.name.clone(), (schema, 0));
+                }
+                Record::DropTable { table } => {
+                    self.tables.remove(&table);
+                    self.table_offsets.remove(&table);
+                    index_definitions.retain(|(index_table, _, _)| *index_table != table);
+                }
+                Record::Index { table, columns, kind } => {
+                    index_definitions.push((table, columns, kind));
+                }
+                Record::DropIndex { table, column } => {
+                    index_definitions.retain(|(index_table, index_columns, _)| {
+                        *index_table != table || !index_columns.contains(&column)
+                    });
+                }
+                // Only found when reading the data log of the legacy layout
+                Record::Row { .. }
+                | Record::Tombstone { .. }
+                | Record::Truncate { .. }
+                | Record::Begin
+                | Record::Commit
+                | Record::Rollback => {}
+            }
+
+            replayed_offset += record_len;
+        }
+
+        // Every byte of the file should belong to a complete record
+        if replayed_offset != file_len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Catalog replay ended at offset {} but file is {} bytes",
+                    replayed_offset, file_len
+                ),
+            )
+            .into());
+        }
+
+        Ok(index_definitions)
+    }
+
+    /// Replay the data log to rebuild the row counts, deleted rows and the
+    /// offset where each table's rows start.
+    ///
+    /// The changes of a transaction that was rolled back, or that never
+    /// committed because the process stopped mid-way, are discarded.
+    ///
+    /// Replay starts at `start`, which is 0 or the offset of a checkpoint
+    /// that has already restored the state before it.
+    fn replay_data(&mut self, start: u64) -> DbResult<()> {
+        // Built from the fields rather than with reader_at so the tables can
+        // be updated while reading; nothing is buffered yet
+        let mut reader = BufReader::new(PositionedReader {
+            segments: &self.segments,
+            active: &**self.writer.get_ref(),
+            active_base: self.active_base,
+            pending: &[],
+            pending_start: self.current_offset,
+            offset: start,
+        });
+        let mut replayed_offset = start;
+        let mut transaction: Option<Vec<TransactionChange>> = None;
+        let bounds = self.record_bounds();
+        let mut torn = false;
+
+        loop {
+            let (record, record_len) = match read_record(&mut reader, replayed_offset, bounds) {
+                Ok(Some(record)) => record,
+                Ok(None) => break,
+                // Only the last record can run past the end of the log
+                Err(e) if is_torn(&e) => {
+                    torn = true;
+                    break;
+                }
+                Err(e) => return Err(e.into()),
+            };
+            match record {
+                Record::Row { table, .. } => {
+                    if let Some((_, row_count)) = self.tables.get_mut(&table) {
+                        *row_count += 1;
+                    }
+                    if let Some(changes) = &mut transaction {
+                        let offset = replayed_offset;
+                        changes.push(TransactionChange::Inserted { table, offset });
+                    }
+                }
+                Record::Tombstone { table, row_offset } => {
+                    self.deleted_rows.insert(row_offset);
+                    if let Some((_, row_count)) = self.tables.get_mut(&table) {
+                        *row_count = row_count.saturating_sub(1);
+                    }
+                    if let Some(changes) = &mut transaction {
+                        changes.push(TransactionChange::Deleted { table, offset: row_offset });
+                    }
+                }
+                // A transaction left open by an earlier crash ends where the next begins
+                Record::Begin => {
+                    if let Some(changes) = transaction.replace(Vec::new()) {
+                        discard_changes(changes, &mut self.tables, &mut self.deleted_rows);
+                    }
+                }
+                Record::Commit => transaction = None,
+                Record::Rollback => {
+                    if let Some(changes) = transaction.take() {
+                        discard_changes(changes, &mut self.tables, &mut self.deleted_rows);
+                    }
+                }
+                // A dropped or truncated table's rows end at its drop or truncate record
+                Record::DropTable { table } | Record::Truncate { table } => {
+                    if let Some((_, row_count)) = self.tables.get_mut(&table) {
+                        *row_count = 0;
+                        self.table_offsets.insert(table, replayed_offset + record_len);
+                    }
+                }
+                // In the legacy layout a table's rows start at its schema
+                Record::Schema(schema) => {
+                    if let Some((_, row_count)) = self.tables.get_mut(&schema.name) {
+                        *row_count = 0;
+                        self.table_offsets.insert(schema.name, replayed_offset);
+                    }
+                }
+                // Legacy index records; the catalog already has them
+                Record::Index { .. } | Record::DropIndex { .. } => {}
+            }
+
+            replayed_offset += record_len;
+        }
+        drop(reader);
+
+        if torn {
+            self.truncate_torn_record(replayed_offset)?;
+        }
+
+        // Every byte of the file should belong to a complete record
+        if replayed_offset != self.current_offset {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Log replay ended at offset {} but file is {} bytes",
+                    replayed_offset, self.current_offset
+                ),
+            )
+            .into());
+        }
+
+        // The log ends inside a transaction that never committed. Close it
+        // so rows written from now on aren't taken to be part of it; a
+        // read-only open writes nothing, and may see another process's
+        // transaction still in progress.
+        if let Some(changes) = transaction {
+            discard_changes(changes, &mut self.tables, &mut self.deleted_rows);
+            if !self.read_only {
+                self.write_transaction_marker(ROLLBACK_MARKER)?;
+                self.flush()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Cut the data log back to `offset`, where a record a crash left half
+    /// written begins. Every record before it is complete, and the torn
+    /// record itself was never committed, so nothing committed is lost; a
+    /// transaction it belonged to is rolled back by `replay_data`. A
+    /// read-only open leaves the file alone and just stops reading there.
+    fn truncate_torn_record(&mut self, offset: u64) -> DbResult<()> {
+        if !self.read_only {
+            let Some(path) = &self.data_file_path else {
+                return Err(torn_record(offset).into());
+            };
+            // Closed segments end with a complete record
+            let len = offset
+                .checked_sub(self.active_base)
+                .ok_or_else(|| torn_record(offset))?;
+            OpenOptions::new()
+                .write(true)
+                .open(segment_path(path, self.segments.len()))?
+                .set_len(len)?;
+            println!("✓ Removed a torn record at offset {} from the end of the log", offset);
+        }
+        self.current_offset = offset;
+        Ok(())
+    }
+
+    /// Restore the state saved by the last checkpoint, returning the data log
+    /// offset to replay from. A missing, damaged or outdated checkpoint is
+    /// ignored, and the whole log is replayed.
+    fn load_checkpoint(&mut self, path: &str) -> DbResult<u64> {
+        let bytes = match fs::read(format!("{}.checkpoint", path)) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+        let Some(checkpoint) = decode_checkpoint(&bytes) else {
+            return Ok(0);
+        };
+        if checkpoint.data_offset > self.current_offset {
+            return Ok(0);
+        }
+
+        // A table missing from the checkpoint was created after it, so none
+        // of its rows come before the checkpoint
+        for (table_name, (_, row_count)) in self.tables.iter_mut() {
+            let (saved_count, table_offset) = checkpoint
+                .tables
+                .get(table_name)
+                .copied()
+                .unwrap_or((0, checkpoint.data_offset));
+            *row_count = saved_count;
+            self.table_offsets.insert(table_name.clone(), table_offset);
+        }
+        self.deleted_rows = checkpoint.deleted_rows.into_iter().collect();
+
+        Ok(checkpoint.data_offset)
+    }
+
+    /// Write the recovered schemas and index definitions of a legacy
+    /// single-file database to a new catalog.
+    ///
+    /// The catalog is written to a temporary file and synced before it is
+    /// renamed into place, so a crash leaves the database in the legacy layout.
+    fn migrate_catalog(
+        &mut self,
+        catalog_path: &str,
+        index_definitions: &[IndexDefinition],
+    ) -> DbResult<()> {
+        let migrate_path = format!("{}.migrate", catalog_path);
+
+        let mut table_names: Vec<&String> = self.tables.keys().collect();
+        table_names.sort();
+
+        let migrate_file = File::create(&migrate_path)?;
+        let mut writer = BufWriter::new(&migrate_file);
+        for table_name in table_names {
+            let (schema, _) = &self.tables[table_name];
+            write_schema_record(&mut writer, schema)?;
+        }
+        for (table_name, column_names, kind) in index_definitions {
+            write_index_record(&mut writer, table_name, column_names, *kind)?;
+        }
+        writer.flush()?;
+        drop(writer);
+        migrate_file.sync_all()?;
+
+        fs::rename(&migrate_path, catalog_path)?;
+
+        let catalog_file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(catalog_path)?;
+        self.catalog_file = Box::new(catalog_file);
+
+        println!("✓ Moved table definitions to '{}'", catalog_path);
+        Ok(())
+    }
+
+    /// Create a new table
+    pub fn create_table(&mut self, schema: TableSchema) -> DbResult<()> {
+        self.check_writable()?;
+        self.check_no_transaction("create a table")?;
+        let table_name = schema.name.clone();
+
+        if self.tables.contains_key(&table_name) {
+            return Err(DbError::AlreadyExists(format!(
+                "Table '{}' already exists",
+                table_name
+            )));
+        }
+
+        // Store schema in memory
+        self.tables.insert(table_name.clone(), (schema.clone(), 0));
+        self.table_offsets.insert(table_name.clone(), self.current_offset);
+
+        // Persist the schema to disk
+        self.write_schema(&schema)?;
+
+        println!("✓ Created table '{}'", table_name);
+
+        // UNIQUE columns are backed by an index for the duplicate check
+        for column in schema.columns.iter().filter(|column| column.unique) {
+            self.create_index(&table_name, &[column.name.as_str()])?;
+        }
+
+        Ok(())
+    }
+
+    /// Drop a table along with its rows and indexes
+    pub fn drop_table(&mut self, table_name: &str) -> DbResult<()> {
+        self.check_writable()?;
+        self.check_no_transaction("drop a table")?;
+        if !self.tables.contains_key(table_name) {
+            return Err(DbError::TableNotFound(table_name.to_string()));
+        }
+
+        // Persist the drop first so it survives a restart
+        self.write_drop_table(table_name)?;
+
+        // Forget the schema and any indexes
+        self.tables.remove(table_name);
+        self.table_offsets.remove(table_name);
+        self.indexes.remove(table_name);
+        self.range_indexes.remove(table_name);
+        self.composite_indexes.remove(table_name);
+        self.next_ids.remove(table_name);
+
+        println!("✓ Dropped table '{}'", table_name);
+        self.after_write(1)
+    }
+
+    /// Delete every row of a table, keeping its schema and indexes
+    pub fn truncate(&mut self, table_name: &str) -> DbResult<()> {
+        self.check_writable()?;
+        self.check_no_transaction("truncate a table")?;
+        if !self.tables.contains_key(table_name) {
+            return Err(DbError::TableNotFound(table_name.to_string()));
+        }
+
+        // Mark the end of the table's rows in the data log; the catalog is unchanged
+        self.rotate_if_full()?;
+        let record_len = write_truncate_record(&mut self.writer, table_name)?;
+        self.current_offset += record_len;
+        self.finish_write()?;
+
+        // The table's rows now start after the truncate record
+        self.table_offsets.insert(table_name.to_string(), self.current_offset);
+        if let Some((_, row_count)) = self.tables.get_mut(table_name) {
+            *row_count = 0;
+        }
+        // Numbering starts over, as the table is empty
+        self.next_ids.remove(table_name);
+
+        // Empty the indexes, keeping their definitions
+        if let Some(table_indexes) = self.indexes.get_mut(table_name) {
+            for index in table_indexes.values_mut() {
+                index.index_map.clear();
+            }
+        }
+        if let Some(table_indexes) = self.range_indexes.get_mut(table_name) {
+            for index in table_indexes.values_mut() {
+                index.index_map.clear();
+            }
+        }
+        if let Some(table_indexes) = self.composite_indexes.get_mut(table_name) {
+            for index in table_indexes {
+                index.index_map.clear();
+            }
+        }
+
+        println!("✓ Truncated table '{}'", table_name);
+        self.after_write(1)
+    }
+
+    /// Write a schema to the catalog
+    fn write_schema(&mut self, schema: &TableSchema) -> DbResult<()> {
+        self.append_catalog(|writer| write_schema_record(writer, schema))
+    }
+
+    /// Insert a row into a table
+    pub fn insert(&mut self, table_name: &str, mut row: Row) -> DbResult<u64> {
+        self.check_writable()?;
+        self.assign_ids(table_name, std::slice::from_mut(&mut row))?;
+
+        // Get schema and validate
+        let (schema, _) = self
+            .tables
+            .get(table_name)
+            .ok_or_else(|| DbError::TableNotFound(table_name.to_string()))?;
+
+        // Validate row matches schema
+        schema
+            .validate_row(&row)
+            .map_err(DbError::SchemaViolation)?;
+
+        self.check_record_size(&row)?;
+        self.check_unique(table_name, &row, None)?;
+
+        // Remember offset before writing
+        let row_offset = self.current_offset;
+
+        // Write row to disk
+        self.write_row(table_name, &row)?;
+
+        // Update indexes if they exist
+        self.index_row(table_name, &row, row_offset);
+
+        // Increment row count
+        if let Some((_, row_count)) = self.tables.get_mut(table_name) {
+            *row_count += 1;
+        }
+
+        self.record_change(TransactionChange::Inserted {
+            table: table_name.to_string(),
+            offset: row_offset,
+        });
+        self.after_write(1)?;
+
+        Ok(row_offset)
+    }
+
+    /// Insert many rows into a table.
+    /// Every row is validated first, so either all rows are written or none are.
+    /// Returns the offsets of the new rows, in order.
+    pub fn insert_batch(&mut self, table_name: &str, mut rows: Vec<Row>) -> DbResult<Vec<u64>> {
+        self.check_writable()?;
+        self.assign_ids(table_name, &mut rows)?;
+
+        // Get schema and validate
+        let (schema, _) = self
+            .tables
+            .get(table_name)
+            .ok_or_else(|| DbError::TableNotFound(table_name.to_string()))?;
+
+        for (i, row) in rows.iter().enumerate() {
+            schema
+                .validate_row(row)
+                .map_err(|e| DbError::SchemaViolation(e).in_row(i))?;
+        }
+        for (i, row) in rows.iter().enumerate() {
+            self.check_record_size(row).map_err(|e| e.in_row(i))?;
+        }
+
+        // Check unique columns against the table and against the rest of the batch
+        let unique_columns = unique_columns(schema);
+        for (i, row) in rows.iter().enumerate() {
+            self.check_unique_columns(table_name, row, None, &unique_columns)
+                .map_err(|e| e.in_row(i))?;
+        }
+        for (col_idx, col_name) in unique_columns {
+            let mut seen = BTreeSet::new();
+            for (i, row) in rows.iter().enumerate() {
+                let Some(value) = row.get(col_idx) else {
+                    continue;
+                };
+                if *value != Value::Null && !seen.insert(value) {
+                    return Err(DbError::SchemaViolation(format!(
+                        "Duplicate value {} for unique column {}.{}",
+                        value, table_name, col_name
+                    ))
+                    .in_row(i));
+                }
+            }
+        }
+
+        // Write all rows to the log, encoding each into the same buffer
+        let mut offsets = Vec::with_capacity(rows.len());
+        let mut row_bytes = Vec::new();
+        for row in &rows {
+            self.rotate_if_full()?;
+            offsets.push(self.current_offset);
+            self.current_offset +=
+                write_row_record_with(&mut self.writer, table_name, row, &mut row_bytes)?;
+        }
+        self.finish_write()?;
+
+        // Update indexes and row count
+        for (row, &row_offset) in rows.iter().zip(&offsets) {
+            self.index_row(table_name, row, row_offset);
+        }
+        if let Some((_, row_count)) = self.tables.get_mut(table_name) {
+            *row_count += rows.len() as u64;
+        }
+
+        for &offset in &offsets {
+            self.record_change(TransactionChange::Inserted {
+                table: table_name.to_string(),
+                offset,
+            });
+        }
+        self.after_write(offsets.len() as u64)?;
+
+        Ok(offsets)
+    }
+
+    /// Bulk-load rows into a table, the fast way to fill one. The rows are
+    /// written `LOAD_CHUNK_ROWS` at a time as by `insert_batch`, with each
+    /// chunk's index updates applied together, all inside one transaction:
+    /// the data log is synced and checkpoints are taken only once, when the
+    /// load commits, even in `OnCommit` mode. The load is all or nothing, so
+    /// a row that fails validation rolls back every row loaded before it;
+    /// errors number rows from the start of the load. Inside an open
+    /// transaction the rows join it instead.
+    pub fn load_rows(
+        &mut self,
+        table_name: &str,
+        rows: impl IntoIterator<Item = Row>,
+    ) -> DbResult<LoadReport> {
+        let start = Instant::now();
+        self.check_writable()?;
+
+        let own_transaction = self.transaction.is_none();
+        if own_transaction {
+            self.begin_transaction()?;
+        }
+
+        match self.load_chunks(table_name, rows.into_iter()) {
+            Ok(loaded) => {
+                if own_transaction {
+                    self.commit()?;
+                }
+                Ok(LoadReport {
+                    rows: loaded,
+                    elapsed: start.elapsed(),
+                })
+            }
+            Err(e) => {
+                if own_transaction {
+                    self.rollback()?;
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Insert rows chunk by chunk for `load_rows`, returning how many were written
+    fn load_chunks(
+        &mut self,
+        table_name: &str,
+        mut rows: impl Iterator<Item = Row>,
+    ) -> DbResult<u64> {
+        let mut loaded = 0;
+        loop {
+            let chunk: Vec<Row> = rows.by_ref().take(LOAD_CHUNK_ROWS).collect();
+            if chunk.is_empty() {
+                return Ok(loaded);
+            }
+
+            let count = chunk.len() as u64;
+            self.insert_batch(table_name, chunk).map_err(|e| match e {
+                DbError::Row { index, source } => DbError::Row {
+                    index: index + loaded as usize,
+                    source,
+                },
+                e => e,
+            })?;
+            loaded += count;
+        }
+    }
+
+    /// Give each row with a NULL in the table's AUTO_INCREMENT column the
+    /// next number in sequence, returning the column's value in every row.
+    /// Rows that already hold a value keep it, and numbering continues past
+    /// it. Numbers are used up even if the rows are then not written, so two
+    /// inserts never get the same one. Returns nothing for a table without
+    /// an AUTO_INCREMENT column.
+    pub fn assign_ids(&mut self, table_name: &str, rows: &mut [Row]) -> DbResult<Vec<i64>> {
+        self.check_writable()?;
+        let (schema, _) = self
+            .tables
+            .get(table_name)
+            .ok_or_else(|| DbError::TableNotFound(table_name.to_string()))?;
+        let Some(col_idx) = schema.auto_increment_column() else {
+            return Ok(Vec::new());
+        };
+
+        let mut next_id = match self.next_ids.get(table_name) {
+            Some(&next_id) => next_id,
+            None => match self.max_id(table_name, col_idx)? {
+                Some(max_id) => max_id.checked_add(1),
+                None => Some(1),
+            },
+        };
+
+        let mut ids = Vec::with_capacity(rows.len());
+        for (i, row) in rows.iter_mut().enumerate() {
+            let Some(value) = row.values.get_mut(col_idx) else {
+                continue;
+            };
+            match value {
+                Value::Null => {
+                    let id = next_id.ok_or_else(|| {
+                        DbError::SchemaViolation(format!(
+                            "AUTO_INCREMENT column '{}' has run out of values",
+                            schema.columns[col_idx].name
+                        ))
+                        .in_row(i)
+                    })?;
+                    *value = Value::Integer(id);
+                    ids.push(id);
+                    next_id = id.checked_add(1);
+                }
+                Value::Integer(id) => {
+                    ids.push(*id);
+                    if next_id.is_some_and(|next_id| next_id <= *id) {
+                        next_id = id.checked_add(1);
+                    }
+                }
+                // Left for validation to reject
+                _ => {}
+            }
+        }
+
+        self.next_ids.insert(table_name.to_string(), next_id);
+        Ok(ids)
+    }
+
+    /// Largest Integer stored in a column, found by reading the whole table
+    fn max_id(&self, table_name: &str, col_idx: usize) -> DbResult<Option<i64>> {
+        let mut max_id = None;
+        for entry in self.scan_columns_iter(table_name, &[col_idx])? {
+            let (_, row) = entry?;
+            if let Some(Value::Integer(id)) = row.get(0) {
+                max_id = max_id.max(Some(*id));
+            }
+        }
+        Ok(max_id)
+    }
+
+    /// Add a newly written row to the table's indexes
+    fn index_row(&mut self, table_name: &str, row: &Row, row_offset: u64) {
+        if let Some(table_indexes) = self.indexes.get_mut(table_name) {
+            for index in table_indexes.values_mut() {
+                if let Some(value) = row.get(index.column_index) {
+                    index.insert(value, row_offset);
+                }
+            }
+        }
+        if let Some(table_indexes) = self.range_indexes.get_mut(table_name) {
+            for index in table_indexes.values_mut() {
+                if let Some(value) = row.get(index.column_index) {
+                    index.insert(value, row_offset);
+                }
+            }
+        }
+        if let Some(table_indexes) = self.composite_indexes.get_mut(table_name) {
+            for index in table_indexes {
+                index.insert(row, row_offset);
+            }
+        }
+    }
+
+    /// Ensure a row doesn't repeat an existing value in any UNIQUE column.
+    /// NULLs are never considered duplicates. The row at `replacing`, which
+    /// the new row is about to replace, is not counted.
+    fn check_unique(
+        &mut self,
+        table_name: &str,
+        row: &Row,
+        replacing: Option<u64>,
+    ) -> DbResult<()> {
+        let unique_columns = match self.get_schema(table_name) {
+            Some(schema) => unique_columns(schema),
+            None => return Ok(()),
+        };
+        self.check_unique_columns(table_name, row, replacing, &unique_columns)
+    }
+
+    /// `check_unique` for the given (position, name) UNIQUE columns of the
+    /// table, so a batch can look them up once rather than for every row
+    fn check_unique_columns(
+        &mut self,
+        table_name: &str,
+        row: &Row,
+        replacing: Option<u64>,
+        unique_columns: &[(usize, String)],
+    ) -> DbResult<()> {
+        for (col_idx, col_name) in unique_columns {
+            let col_idx = *col_idx;
+            let Some(value) = row.get(col_idx).filter(|value| **value != Value::Null) else {
+                continue;
+            };
+
+            // Use the backing index, falling back to a scan if it is missing.
+            // Index keys are the values' string forms, so confirm each hit.
+            let existing = match self.indexes.get(table_name).and_then(|t| t.get(col_name)) {
+                Some(index) => {
+                    let offsets = index.lookup(value).cloned().unwrap_or_default();
+                    self.read_rows_at_offsets(offsets)?
+                }
+                None => self.scan_with_offsets(table_name)?,
+            };
+            let duplicate = existing.iter().any(|(offset, existing)| {
+                Some(*offset) != replacing && existing.get(col_idx) == Some(value)
+            });
+
+            if duplicate {
+                return Err(DbError::SchemaViolation(format!(
+                    "Duplicate value {} for unique column {}.{}",
+                    value, table_name, col_name
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write a row to the data file
+    fn write_row(&mut self, table_name: &str, row: &Row) -> DbResult<()> {
+        self.rotate_if_full()?;
+        let record_len = write_row_record(&mut self.writer, table_name, row)?;
+
+        // Update offset
+        self.current_offset += record_len;
+
+        self.finish_write()
+    }
+
+    /// Delete the row stored at `offset` by appending a tombstone record
+    pub fn delete(&mut self, table_name: &str, offset: u64) -> DbResult<()> {
+        self.check_writable()?;
+        if !self.tables.contains_key(table_name) {
+            return Err(DbError::TableNotFound(table_name.to_string()));
+        }
+
+        // Read the row first so its values can be purged from the indexes
+        let row = self.read_row_at_offset(offset)?;
+
+        // Write tombstone to disk
+        self.write_tombstone(table_name, offset)?;
+        self.deleted_rows.insert(offset);
+
+        // Remove the row's offset from any indexes
+        self.unindex_row(table_name, &row, offset);
+
+        // Decrement row count
+        if let Some((_, row_count)) = self.tables.get_mut(table_name) {
+            *row_count = row_count.saturating_sub(1);
+        }
+
+        self.record_change(TransactionChange::Deleted {
+            table: table_name.to_string(),
+            offset,
+        });
+
+        self.after_write(1)
+    }
+
+    /// Replace the row stored at `offset` with `row`, returning the new row's
+    /// offset. The old row is tombstoned and the new one appended, so scans
+    /// and index lookups only ever see the newest version. The new row is
+    /// validated before anything is written.
+    pub fn update(&mut self, table_name: &str, offset: u64, row: Row) -> DbResult<u64> {
+        self.check_writable()?;
+        let (schema, _) = self
+            .tables
+            .get(table_name)
+            .ok_or_else(|| DbError::TableNotFound(table_name.to_string()))?;
+
+        schema
+            .validate_row(&row)
+            .map_err(DbError::SchemaViolation)?;
+
+        if self.deleted_rows.contains(&offset) {
+            return Err(DbError::InvalidInput(format!(
+                "Row at offset {} has been deleted",
+                offset
+            )));
+        }
+
+        self.check_record_size(&row)?;
+        self.check_unique(table_name, &row, Some(offset))?;
+
+        self.delete(table_name, offset)?;
+        self.insert(table_name, row)
+    }
+
+    /// Remove the offsets of deleted rows from every index. `delete` already
+    /// unindexes the rows it deletes, so this only sweeps up any left over.
+    /// Returns how many offsets were removed.
+    pub fn vacuum_indexes(&mut self) -> usize {
+        let deleted_rows = &self.deleted_rows;
+        let mut removed = 0;
+        for index in self.indexes.values_mut().flat_map(|t| t.values_mut()) {
+            removed += index.remove_offsets(deleted_rows);
+        }
+        for index in self.range_indexes.values_mut().flat_map(|t| t.values_mut()) {
+            removed += index.remove_offsets(deleted_rows);
+        }
+        for index in self.composite_indexes.values_mut().flatten() {
+            removed += index.remove_offsets(deleted_rows);
+        }
+
+        if removed > 0 {
+            println!("✓ Removed {} deleted row(s) from indexes", removed);
+        }
+        removed
+    }
+
+    /// Remove a deleted row from the table's indexes
+    fn unindex_row(&mut self, table_name: &str, row: &Row, row_offset: u64) {
+        if let Some(table_indexes) = self.indexes.get_mut(table_name) {
+            for index in table_indexes.values_mut() {
+                if let Some(value) = row.get(index.column_index) {
+                    index.remove(value, row_offset);
+                }
+            }
+        }
+        if let Some(table_indexes) = self.range_indexes.get_mut(table_name) {
+            for index in table_indexes.values_mut() {
+                if let Some(value) = row.get(index.column_index) {
+                    index.remove(value, row_offset);
+                }
+            }
+        }
+        if let Some(table_indexes) = self.composite_indexes.get_mut(table_name) {
+            for index in table_indexes {
+                index.remove(row, row_offset);
+            }
+        }
+    }
+
+    /// Write a tombstone for the row at `row_offset` to the data file
+    fn write_tombstone(&mut self, table_name: &str, row_offset: u64) -> DbResult<()> {
+        self.rotate_if_full()?;
+        let record_len = write_tombstone_record(&mut self.writer, table_name, row_offset)?;
+
+        // Update offset
+        self.current_offset += record_len;
+
+        self.finish_write()
+    }
+
+    /// Write a drop-table record to the data log and the catalog
+    fn write_drop_table(&mut self, table_name: &str) -> DbResult<()> {
+        // Mark the end of the table's rows in the data log, and make sure it
+        // reaches the file before the catalog forgets the table; otherwise a
+        // new table of the same name could inherit the old rows
+        self.rotate_if_full()?;
+        let record_len = write_drop_table_record(&mut self.writer, table_name)?;
+        self.current_offset += record_len;
+        self.flush()?;
+        self.finish_write()?;
+
+        // Remove the table from the catalog
+        self.append_catalog(|writer| write_drop_table_record(writer, table_name))
+    }
+
+    /// Drop every index (hash, range and composite) on a column
+    pub fn drop_index(&mut self, table_name: &str, column_name: &str) -> DbResult<()> {
+        self.check_writable()?;
+        self.check_no_transaction("drop an index")?;
+        let has_hash = self
+            .indexes
+            .get(table_name)
+            .is_some_and(|table_indexes| table_indexes.contains_key(column_name));
+        let has_range = self
+            .range_indexes
+            .get(table_name)
+            .is_some_and(|table_indexes| table_indexes.contains_key(column_name));
+        let has_composite = self.composite_indexes.get(table_name).is_some_and(|table_indexes| {
+            table_indexes
+                .iter()
+                .any(|index| index.column_names.iter().any(|c| c == column_name))
+        });
+
+        if !has_hash && !has_range && !has_composite {
+            return Err(DbError::IndexNotFound(format!(
+                "{}.{}",
+                table_name, column_name
+            )));
+        }
+
+        // Persist the drop first so the index isn't rebuilt on restart
+        self.write_drop_index(table_name, column_name)?;
+
+        if let Some(table_indexes) = self.indexes.get_mut(table_name) {
+            table_indexes.remove(column_name);
+        }
+        if let Some(table_indexes) = self.range_indexes.get_mut(table_name) {
+            table_indexes.remove(column_name);
+        }
+        if let Some(table_indexes) = self.composite_indexes.get_mut(table_name) {
+            table_indexes.retain(|index| index.column_names.iter().all(|c| c != column_name));
+        }
+
+        println!("✓ Dropped index on {}.{}", table_name, column_name);
+        Ok(())
+    }
+
+    /// Write a dropped index record to the catalog
+    fn write_drop_index(&mut self, table_name: &str, column_name: &str) -> DbResult<()> {
+        self.append_catalog(|writer| write_drop_index_record(writer, table_name, column_name))
+    }
+
+    /// Scan all rows in a table (slow path - no index)
+    pub fn scan(&self, table_name: &str) -> DbResult<Vec<Row>> {
+        let rows = self.scan_with_offsets(table_name)?;
+        Ok(rows.into_iter().map(|(_, row)| row).collect())
+    }
+
+    /// Scan all rows in a table, paired with the file offset of each row
+    pub fn scan_with_offsets(&self, table_name: &str) -> DbResult<Vec<(u64, Row)>> {
+        self.scan_iter(table_name)?.collect()
+    }
+
+    /// Scan all rows in a table, keeping only the columns at `col_indices`,
+    /// in that order. Each row is still read in full; only the requested
+    /// values are kept.
+    pub fn scan_columns(&self, table_name: &str, col_indices: &[usize]) -> DbResult<Vec<Row>> {
+        self.scan_columns_iter(table_name, col_indices)?
+            .map(|entry| entry.map(|(_, row)| row))
+            .collect()
+    }
+
+    /// Iterate over the rows of a table like `scan_iter`, keeping only the
+    /// columns at `col_indices`, in that order
+    pub fn scan_columns_iter(
+        &self,
+        table_name: &str,
+        col_indices: &[usize],
+    ) -> DbResult<ScanIter<'_>> {
+        let (schema, _) = self
+            .tables
+            .get(table_name)
+            .ok_or_else(|| DbError::TableNotFound(table_name.to_string()))?;
+
+        if let Some(&col_idx) = col_indices.iter().find(|&&i| i >= schema.columns.len()) {
+            return Err(DbError::InvalidInput(format!(
+                "Column index {} out of range for table '{}'",
+                col_idx, table_name
+            )));
+        }
+
+        let mut rows = self.scan_iter(table_name)?;
+        rows.columns = Some(col_indices.to_vec());
+        Ok(rows)
+    }
+
+    /// Iterate over the rows of a table without loading them all up front.
+    /// The scan starts at the table's schema record and stops at the end of
+    /// the log as it was when called.
+    pub fn scan_iter(&self, table_name: &str) -> DbResult<ScanIter<'_>> {
+        let start = *self
+            .table_offsets
+            .get(table_name)
+            .ok_or_else(|| DbError::TableNotFound(table_name.to_string()))?;
+
+        Ok(ScanIter {
+            reader: BufReader::new(self.reader_at(start)),
+            table_name: table_name.to_string(),
+            deleted_rows: &self.deleted_rows,
+            offset: start,
+            bounds: self.record_bounds(),
+            columns: None,
+        })
+    }
+
+    /// Create an index on one column, or a composite index on several that
+    /// serves lookups constraining all of them (fast path)
+    pub fn create_index(&mut self, table_name: &str, column_names: &[&str]) -> DbResult<()> {
+        self.check_writable()?;
+        let column_name = match column_names {
+            [] => {
+                return Err(DbError::InvalidInput(
+                    "An index needs at least one column".to_string(),
+                ));
+            }
+            [column_name] => *column_name,
+            _ => return self.create_composite_index(table_name, column_names),
+        };
+
+        self.create_hash_index(table_name, column_name, false)
+    }
+
+    /// Create an index on a text column whose lookups ignore case, so
+    /// `= 'Bob@X.com'` finds a stored 'bob@x.com'. This changes equality for
+    /// every query answered through the index, though not for the same
+    /// condition evaluated without it.
+    pub fn create_case_insensitive_index(
+        &mut self,
+        table_name: &str,
+        column_name: &str,
+    ) -> DbResult<()> {
+        self.check_writable()?;
+        self.create_hash_index(table_name, column_name, true)
+    }
+
+    /// Create a hash index on a column
+    fn create_hash_index(
+        &mut self,
+        table_name: &str,
+        column_name: &str,
+        case_insensitive: bool,
+    ) -> DbResult<()> {
+        self.check_no_transaction("create an index")?;
+        let exists = self
+            .indexes
+            .get(table_name)
+            .is_some_and(|table_indexes| table_indexes.contains_key(column_name));
+
+        if exists {
+            return Err(DbError::AlreadyExists(format!(
+                "Index on {}.{} already exists",
+                table_name, column_name
+            )));
+        }
+
+        let index = self.build_index(table_name, column_name, case_insensitive)?;
+
+        // Persist the index definition so it is rebuilt on restart
+        let kind = if case_insensitive {
+            IndexKind::CaseInsensitive
+        } else {
+            IndexKind::Hash
+        };
+        self.write_index(table_name, &[column_name], kind)?;
+
+        // Store the index
+        self.indexes
+            .entry(table_name.to_string())
+            .or_default()
+            .insert(column_name.to_string(), index);
+
+        if case_insensitive {
+            println!("✓ Created case-insensitive index on {}.{}", table_name, column_name);
+        } else {
+            println!("✓ Created index on {}.{}", table_name, column_name);
+        }
+        Ok(())
+    }
+
+    /// Create an ordered index on a column, usable for range queries
+    pub fn create_range_index(&mut self, table_name: &str, column_name: &str) -> DbResult<()> {
+        self.check_writable()?;
+        self.check_no_transaction("create an index")?;
+        let exists = self
+            .range_indexes
+            .get(table_name)
+            .is_some_and(|table_indexes| table_indexes.contains_key(column_name));
+
+        if exists {
+            return Err(DbError::AlreadyExists(format!(
+                "Range index on {}.{} already exists",
+                table_name, column_name
+            )));
+        }
+
+        let index = self.build_range_index(table_name, column_name)?;
+
+        // Persist the index definition so it is rebuilt on restart
+        self.write_index(table_name, &[column_name], IndexKind::Range)?;
+
+        // Store the index
+        self.range_indexes
+            .entry(table_name.to_string())
+            .or_default()
+            .insert(column_name.to_string(), index);
+
+        println!("✓ Created range index on {}.{}", table_name, column_name);
+        Ok(())
+    }
+
+    /// Create an index on several columns together
+    fn create_composite_index(&mut self, table_name: &str, column_names: &[&str]) -> DbResult<()> {
+        self.check_no_transaction("create an index")?;
+        for (i, column_name) in column_names.iter().enumerate() {
+            if column_names[..i].contains(column_name) {
+                return Err(DbError::InvalidInput(format!(
+                    "Column '{}' appears more than once in the index",
+                    column_name
+                )));
+            }
+        }
+
+        let column_list = column_names.join(", ");
+        let exists = self.composite_indexes.get(table_name).is_some_and(|table_indexes| {
+            table_indexes.iter().any(|index| index.column_names == column_names)
+        });
+        if exists {
+            return Err(DbError::AlreadyExists(format!(
+                "Index on {}({}) already exists",
+                table_name, column_list
+            )));
+        }
+
+        let column_names: Vec<String> = column_names.iter().map(|c| c.to_string()).collect();
+        let index = self.build_composite_index(table_name, &column_names)?;
+
+        // Persist the index definition so it is rebuilt on restart
+        self.write_index(table_name, &column_names, IndexKind::Composite)?;
+
+        // Store the index
+        self.composite_indexes
+            .entry(table_name.to_string())
+            .or_default()
+            .push(index);
+
+        println!("✓ Created index on {}({})", table_name, column_list);
+        Ok(())
+    }
+
+    /// Build an index on a column by scanning the live rows of the table
+    fn build_index(
+        &self,
+        table_name: &str,
+        column_name: &str,
+        case_insensitive: bool,
+    ) -> DbResult<Index> {
+        let column_index = self.resolve_column(table_name, column_name)?;
+
+        // Create the index structure
+        let mut index = Index::new(table_name.to_string(), column_name.to_string(), column_index);
+        index.case_insensitive = case_insensitive;
+
+        // Build the index from the live rows in the file
+        for (offset, row) in self.scan_with_offsets(table_name)? {
+            if let Some(value) = row.get(column_index) {
+                index.insert(value, offset);
+            }
+        }
+
+        Ok(index)
+    }
+
+    /// Build a range index on a column by scanning the live rows of the table
+    fn build_range_index(&self, table_name: &str, column_name: &str) -> DbResult<RangeIndex> {
+        let column_index = self.resolve_column(table_name, column_name)?;
+
+        // Create the index structure
+        let mut index =
+            RangeIndex::new(table_name.to_string(), column_name.to_string(), column_index);
+
+        // Build the index from the live rows in the file
+        for (offset, row) in self.scan_with_offsets(table_name)? {
+            if let Some(value) = row.get(column_index) {
+                index.insert(value, offset);
+            }
+        }
+
+        Ok(index)
+    }
+
+    /// Build an index on several columns by scanning the live rows of the table
+    fn build_composite_index(
+        &self,
+        table_name: &str,
+        column_names: &[String],
+    ) -> DbResult<CompositeIndex> {
+        let column_indexes = column_names
+            .iter()
+            .map(|column_name| self.resolve_column(table_name, column_name))
+            .collect::<DbResult<Vec<usize>>>()?;
+
+        // Create the index structure
+        let mut index =
+            CompositeIndex::new(table_name.to_string(), column_names.to_vec(), column_indexes);
+
+        // Build the index from the live rows in the file
+        for (offset, row) in self.scan_with_offsets(table_name)? {
+            index.insert(&row, offset);
+        }
+
+        Ok(index)
+    }
+
+    /// Get the position of a column within a table's schema
+    fn resolve_column(&self, table_name: &str, column_name: &str) -> DbResult<usize> {
+        // Get schema
+        let (schema, _) = self
+            .tables
+            .get(table_name)
+            .ok_or_else(|| DbError::TableNotFound(table_name.to_string()))?;
+
+        // Check column exists
+        schema
+            .get_column_index(column_name)
+            .ok_or_else(|| DbError::ColumnNotFound {
+                table: table_name.to_string(),
+                column: column_name.to_string(),
+            })
+    }
+
+    /// Write an index definition to the catalog
+    fn write_index<S: AsRef<str>>(
+        &mut self,
+        table_name: &str,
+        column_names: &[S],
+        kind: IndexKind,
+    ) -> DbResult<()> {
+        self.append_catalog(|writer| write_index_record(writer, table_name, column_names, kind))
+    }
+
+    /// Append a record to the catalog. Catalog records are written through
+    /// immediately, since table definitions change rarely.
+    fn append_catalog(
+        &mut self,
+        encode: impl FnOnce(&mut BufWriter<&mut Box<dyn LogFile>>) -> io::Result<u64>,
+    ) -> DbResult<()> {
+        let mut writer = BufWriter::new(&mut self.catalog_file);
+        encode(&mut writer)?;
+        writer.flush()?;
+        drop(writer);
+
+        if self.durability == DurabilityMode::OnCommit {
+            self.catalog_file.sync()?;
+        }
+
+        Ok(())
+    }
+
+    /// Push any buffered records to the data file
+    pub fn flush(&mut self) -> DbResult<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Flush buffered records and force the data log and the catalog to
+    /// durable storage, whatever the durability mode. Closed segments were
+    /// synced when they filled up. Records of a transaction that is still
+    /// open are synced too, but are still discarded on the next open unless
+    /// it commits.
+    pub fn sync(&mut self) -> DbResult<()> {
+        self.flush()?;
+        self.writer.get_ref().sync()?;
+        self.catalog_file.sync()?;
+        Ok(())
+    }
+
+    /// Finish a write to the data log as the durability mode requires.
+    /// Inside a transaction this waits for the commit.
+    fn finish_write(&mut self) -> DbResult<()> {
+        if self.durability == DurabilityMode::OnCommit && self.transaction.is_none() {
+            self.writer.flush()?;
+            self.writer.get_ref().sync()?;
+        }
+
+        Ok(())
+    }
+
+    /// Start a transaction. Inserts and deletes until `commit` or `rollback`
+    /// are visible to reads straight away, but only survive a restart once
+    /// committed; if the process stops first they are discarded on the next
+    /// open. Table and index definitions can't change inside a transaction.
+    pub fn begin_transaction(&mut self) -> DbResult<()> {
+        self.check_writable()?;
+        if self.transaction.is_some() {
+            return Err(DbError::InvalidInput(
+                "A transaction is already open".to_string(),
+            ));
+        }
+
+        self.write_transaction_marker(BEGIN_MARKER)?;
+        self.transaction = Some(Vec::new());
+        Ok(())
+    }
+
+    /// Commit the open transaction. In `OnCommit` mode its records are
+    /// synced here, once, rather than after each write.
+    pub fn commit(&mut self) -> DbResult<()> {
+        if self.transaction.is_none() {
+            return Err(no_transaction());
+        }
+
+        self.write_transaction_marker(COMMIT_MARKER)?;
+        self.transaction = None;
+        self.flush()?;
+        self.finish_write()?;
+        self.after_write(1)
+    }
+
+    /// Roll back the open transaction, undoing its inserts and deletes
+    pub fn rollback(&mut self) -> DbResult<()> {
+        let Some(changes) = self.transaction.take() else {
+            return Err(no_transaction());
+        };
+
+        // Persist the rollback first so a restart discards the changes too
+        self.write_transaction_marker(ROLLBACK_MARKER)?;
+        self.flush()?;
+        self.finish_write()?;
+
+        // Undo newest first, so a row inserted and then deleted ends up gone
+        for change in changes.into_iter().rev() {
+            match change {
+                TransactionChange::Inserted { table, offset } => {
+                    let row = self.read_row_at_offset(offset)?;
+                    self.unindex_row(&table, &row, offset);
+                    self.deleted_rows.insert(offset);
+                    if let Some((_, row_count)) = self.tables.get_mut(&table) {
+                        *row_count = row_count.saturating_sub(1);
+                    }
+                }
+                TransactionChange::Deleted { table, offset } => {
+                    let row = self.read_row_at_offset(offset)?;
+                    self.deleted_rows.remove(&offset);
+                    self.index_row(&table, &row, offset);
+                    if let Some((_, row_count)) = self.tables.get_mut(&table) {
+                        *row_count += 1;
+                    }
+                }
+            }
+        }
+
+        self.after_write(1)
+    }
+
+    /// Whether a transaction is open
+    pub fn in_transaction(&self) -> bool {
+        self.transaction.is_some()
+    }
+
+    /// Remember a change made inside the open transaction, if there is one
+    fn record_change(&mut self, change: TransactionChange) {
+        if let Some(changes) = &mut self.transaction {
+            changes.push(change);
+        }
+    }
+
+    /// Append a record that opens, commits or rolls back a transaction
+    fn write_transaction_marker(&mut self, marker: u8) -> DbResult<()> {
+        self.rotate_if_full()?;
+        self.current_offset += write_transaction_record(&mut self.writer, marker)?;
+        Ok(())
+    }
+
+    /// Fail if the database was opened with `open_read_only`
+    fn check_writable(&self) -> DbResult<()> {
+        if self.read_only {
+            return Err(DbError::InvalidInput(
+                "Cannot write to a database opened read-only".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Whether the database was opened with `open_read_only`
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Fail if a transaction is open; used by operations that can't be rolled back
+    fn check_no_transaction(&self, operation: &str) -> DbResult<()> {
+        if self.transaction.is_some() {
+            return Err(DbError::InvalidInput(format!(
+                "Cannot {} inside a transaction",
+                operation
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Save the row counts and deleted rows rebuilt from the data log, so the
+    /// next open only replays the records written after this point. Schemas
+    /// still come from the catalog, and indexes are still built from the rows.
+    ///
+    /// The checkpoint is written to a temporary file and synced before it
+    /// replaces the previous one, so a crash leaves one or the other.
+    pub fn checkpoint(&mut self) -> DbResult<()> {
+        self.check_writable()?;
+        self.check_no_transaction("checkpoint")?;
+        let Some(path) = &self.data_file_path else {
+            return Ok(());
+        };
+        let checkpoint_path = format!("{}.checkpoint", path);
+
+        // Everything the checkpoint covers must be on disk before it is
+        self.writer.flush()?;
+        self.writer.get_ref().sync()?;
+
+        let tables = self
+            .tables
+            .iter()
+            .map(|(table_name, (_, row_count))| {
+                let table_offset = self.table_offsets.get(table_name).copied().unwrap_or(0);
+                (table_name.clone(), (*row_count, table_offset))
+            })
+            .collect();
+        let checkpoint = Checkpoint {
+            data_offset: self.current_offset,
+            tables,
+            deleted_rows: self.deleted_rows.iter().copied().collect(),
+        };
+        let bytes = bincode::serialize(&checkpoint).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("Serialize error: {}", e))
+        })?;
+
+        // Format: [crc][checkpoint_bytes]
+        let temp_path = format!("{}.tmp", checkpoint_path);
+        let mut file = File::create(&temp_path)?;
+        file.write_all(&crc32(&bytes).to_le_bytes())?;
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, &checkpoint_path)?;
+
+        self.writes_since_checkpoint = 0;
+        Ok(())
+    }
+
+    /// Set how many writes trigger an automatic checkpoint; 0 turns them off
+    pub fn set_checkpoint_interval(&mut self, writes: u64) {
+        self.checkpoint_interval = writes;
+    }
+
+    /// Count the records of a finished write, taking a checkpoint once enough
+    /// have built up. Checkpoints wait until no transaction is open.
+    fn after_write(&mut self, records: u64) -> DbResult<()> {
+        self.writes_since_checkpoint += records;
+        if self.checkpoint_interval > 0
+            && self.writes_since_checkpoint >= self.checkpoint_interval
+            && self.transaction.is_none()
+        {
+            self.checkpoint()?;
+        }
+
+        Ok(())
+    }
+
+    /// Handles on the files that take writes: the active segment and the catalog
+    fn files_to_sync(&self) -> DbResult<Vec<Box<dyn LogFile>>> {
+        Ok(vec![self.writer.get_ref().try_clone()?, self.catalog_file.try_clone()?])
+    }
+
+    /// Point the background sync at the current files after the active
+    /// segment has been replaced
+    fn update_background_sync(&self) -> DbResult<()> {
+        if let Some(background_sync) = &self.background_sync {
+            let files = self.files_to_sync()?;
+            if let Ok(mut synced) = background_sync.files.lock() {
+                *synced = files;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Close the active segment and start a new one once it has reached the
+    /// size limit. Called before each write, so records never span segments.
+    fn rotate_if_full(&mut self) -> DbResult<()> {
+        if self.current_offset - self.active_base < self.max_segment_size {
+            return Ok(());
+        }
+
+        let file = self.create_segment(self.segments.len() + 1)?;
+
+        // The closed segment is complete on disk before anything goes to the next
+        self.flush()?;
+        let closed = std::mem::replace(&mut self.writer, BufWriter::new(file));
+        let closed = closed.into_inner().map_err(|e| e.into_error())?;
+        closed.sync()?;
+        self.segments.push(Segment { file: closed, base: self.active_base });
+        self.active_base = self.current_offset;
+
+        self.update_background_sync()
+    }
+
+    /// Create the file for data log segment `id`
+    fn create_segment(&self, id: usize) -> DbResult<Box<dyn LogFile>> {
+        match &self.data_file_path {
+            Some(path) => {
+                let file = OpenOptions::new()
+                    .read(true)
+                    .append(true)
+                    .create_new(true)
+                    .open(segment_path(path, id))?;
+                Ok(Box::new(file))
+            }
+            None => Ok(Box::new(MemoryFile::default())),
+        }
+    }
+
+    /// Read the log from `offset`, including records that are still buffered
+    fn reader_at(&self, offset: u64) -> PositionedReader<'_> {
+        let pending = self.writer.buffer();
+        PositionedReader {
+            segments: &self.segments,
+            active: &**self.writer.get_ref(),
+            active_base: self.active_base,
+            pending,
+            pending_start: self.current_offset - pending.len() as u64,
+            offset,
+        }
+    }
+
+    /// Rewrite the data log as a single segment holding only live rows.
+    /// The catalog is left as it is.
+    ///
+    /// The compacted log is written to a temporary file and synced before it
+    /// replaces the old segments. Once it is complete a crash at any point is
+    /// recovered on the next open; before that the old segments are untouched.
+    pub fn compact(&mut self) -> DbResult<()> {
+        self.check_writable()?;
+        self.check_no_transaction("compact the log")?;
+        // Nothing may still be waiting to be appended to the old log
+        self.flush()?;
+
+        // Gather the live rows of every table before touching anything
+        let mut table_names: Vec<String> = self.tables.keys().cloned().collect();
+        table_names.sort();
+
+        let mut live_tables = Vec::new();
+        for table_name in &table_names {
+            let rows = self.scan(table_name)?;
+            live_tables.push((table_name.clone(), rows));
+        }
+
+        // Fresh indexes for the new offsets
+        let mut new_indexes: HashMap<String, HashMap<String, Index>> = HashMap::new();
+        for (table_name, table_indexes) in &self.indexes {
+            for (column_name, index) in table_indexes {
+                let mut fresh =
+                    Index::new(table_name.clone(), column_name.clone(), index.column_index);
+                fresh.case_insensitive = index.case_insensitive;
+                new_indexes
+                    .entry(table_name.clone())
+                    .or_default()
+                    .insert(column_name.clone(), fresh);
+            }
+        }
+
+        let mut new_range_indexes: HashMap<String, HashMap<String, RangeIndex>> = HashMap::new();
+        for (table_name, table_indexes) in &self.range_indexes {
+            for (column_name, index) in table_indexes {
+                let fresh =
+                    RangeIndex::new(table_name.clone(), column_name.clone(), index.column_index);
+                new_range_indexes
+                    .entry(table_name.clone())
+                    .or_default()
+                    .insert(column_name.clone(), fresh);
+            }
+        }
+
+        let mut new_composite_indexes: HashMap<String, Vec<CompositeIndex>> = HashMap::new();
+        for (table_name, table_indexes) in &self.composite_indexes {
+            let fresh = table_indexes.iter().map(|index| {
+                CompositeIndex::new(
+                    table_name.clone(),
+                    index.column_names.clone(),
+                    index.column_indexes.clone(),
+                )
+            });
+            new_composite_indexes.insert(table_name.clone(), fresh.collect());
+        }
+
+        // Write the compacted log
+        let mut compact_file: Box<dyn LogFile> = match &self.data_file_path {
+            Some(path) => Box::new(File::create(format!("{}.compact", path))?),
+            None => Box::new(MemoryFile::default()),
+        };
+        let mut writer = BufWriter::new(&mut compact_file);
+        let mut new_offset = 0u64;
+
+        let mut new_table_offsets = HashMap::new();
+
+        for (table_name, rows) in &live_tables {
+            new_table_offsets.insert(table_name.clone(), new_offset);
+
+            for row in rows {
+                let row_offset = new_offset;
+                new_offset += write_row_record(&mut writer, table_name, row)?;
+
+                if let Some(table_indexes) = new_indexes.get_mut(table_name) {
+                    for index in table_indexes.values_mut() {
+                        if let Some(value) = row.get(index.column_index) {
+                            index.insert(value, row_offset);
+                        }
+                    }
+                }
+                if let Some(table_indexes) = new_range_indexes.get_mut(table_name) {
+                    for index in table_indexes.values_mut() {
+                        if let Some(value) = row.get(index.column_index) {
+                            index.insert(value, row_offset);
+                        }
+                    }
+                }
+                if let Some(table_indexes) = new_composite_indexes.get_mut(table_name) {
+                    for index in table_indexes {
+                        index.insert(row, row_offset);
+                    }
+                }
+            }
+        }
+
+        writer.flush()?;
+        drop(writer);
+        compact_file.sync()?;
+
+        self.segments.clear();
+        if let Some(path) = &self.data_file_path {
+            // Mark the compacted log complete, then swap it in for every segment
+            fs::rename(format!("{}.compact", path), format!("{}.compacted", path))?;
+            finish_compaction(path)?;
+
+            // Reopen in append mode
+            compact_file = Box::new(OpenOptions::new().read(true).append(true).open(path)?);
+        }
+        self.writer = BufWriter::new(compact_file);
+        self.active_base = 0;
+        self.current_offset = new_offset;
+        self.update_background_sync()?;
+        self.indexes = new_indexes;
+        self.range_indexes = new_range_indexes;
+        self.composite_indexes = new_composite_indexes;
+        self.table_offsets = new_table_offsets;
+        // Deleted rows were left out of the new log
+        self.deleted_rows.clear();
+
+        for (table_name, rows) in &live_tables {
+            if let Some((_, row_count)) = self.tables.get_mut(table_name) {
+                *row_count = rows.len() as u64;
+            }
+        }
+
+        match &self.data_file_path {
+            Some(path) => println!("✓ Compacted '{}'", path),
+            None => println!("✓ Compacted in-memory log"),
+        }
+        Ok(())
+    }
+
+    /// Salvage what can still be read from the damaged database at `path`
+    /// into a new database at `output_path`, leaving the damaged one as it is.
+    ///
+    /// Where a record of the data log can't be read, bytes are skipped up to
+    /// the next row record of a known table whose checksum verifies, and
+    /// reading goes on from there. Deletes, truncates and transactions are
+    /// applied as on a normal open, as far as their records survive. Table
+    /// and index definitions come from the catalog, which has to be readable.
+    pub fn recover(path: &str, output_path: &str) -> DbResult<RecoveryReport> {
+        let mut source = BitcaskStorage::in_memory();
+        let catalog_path = format!("{}.catalog", path);
+        let index_definitions = if Path::new(&catalog_path).exists() {
+            source.replay_catalog(&File::open(&catalog_path)?)?
+        } else {
+            Vec::new()
+        };
+
+        let log = read_data_log(path)?;
+        let bounds = RecordBounds {
+            end: log.len() as u64,
+            max_record_size: DEFAULT_MAX_RECORD_SIZE,
+        };
+        let mut report = RecoveryReport::default();
+
+        // Every row read, by offset; deletes are applied when writing them out
+        let mut rows: BTreeMap<u64, (String, Row)> = BTreeMap::new();
+        let mut deleted_rows = HashSet::new();
+        let mut transaction: Option<Vec<TransactionChange>> = None;
+
+        let mut offset = 0u64;
+        while offset < bounds.end {
+            let (record, record_len) = match read_record(&mut &log[offset as usize..], offset, bounds) {
+                Ok(Some(record)) => record,
+                Ok(None) => break,
+                Err(_) => {
+                    let resume = next_row_record(&log, offset + 1, bounds, &source.tables);
+                    report.damaged_regions += 1;
+                    report.bytes_skipped += resume - offset;
+                    offset = resume;
+                    continue;
+                }
+            };
+            report.records_read += 1;
+
+            match record {
+                Record::Row { table, bytes } => match deserialize::<Row>(&bytes) {
+                    Ok(row) => {
+                        if let Some(changes) = &mut transaction {
+                            let table = table.clone();
+                            changes.push(TransactionChange::Inserted { table, offset });
+                        }
+                        rows.insert(offset, (table, row));
+                    }
+                    Err(_) => report.rows_rejected += 1,
+                },
+                Record::Tombstone { table, row_offset } => {
+                    deleted_rows.insert(row_offset);
+                    if let Some(changes) = &mut transaction {
+                        changes.push(TransactionChange::Deleted { table, offset: row_offset });
+                    }
+                }
+                Record::Begin => {
+                    if let Some(changes) = transaction.replace(Vec::new()) {
+                        discard_changes(changes, &mut source.tables, &mut deleted_rows);
+                    }
+                }
+                Record::Commit => transaction = None,
+                Record::Rollback => {
+                    if let Some(changes) = transaction.take() {
+                        discard_changes(changes, &mut source.tables, &mut deleted_rows);
+                    }
+                }
+                // Rows written before these belong to an earlier table of the name
+                Record::DropTable { table } | Record::Truncate { table } => {
+                    rows.retain(|_, (row_table, _)| *row_table != table);
+                }
+                Record::Schema(schema) => {
+                    rows.retain(|_, (row_table, _)| *row_table != schema.name);
+                }
+                Record::Index { .. } | Record::DropIndex { .. } => {}
+            }
+
+            offset += record_len;
+        }
+
+        // A transaction still open at the end never committed
+        if let Some(changes) = transaction {
+            discard_changes(changes, &mut source.tables, &mut deleted_rows);
+        }
+
+        let mut output = BitcaskStorage::create(output_path)?;
+
+        let mut table_names: Vec<&String> = source.tables.keys().collect();
+        table_names.sort();
+        for table_name in table_names {
+            let (schema, _) = &source.tables[table_name];
+            output.create_table(schema.clone())?;
+        }
+        for (table_name, column_names, kind) in &index_definitions {
+            let column_names: Vec<&str> = column_names.iter().map(String::as_str).collect();
+            let created = match kind {
+                IndexKind::Hash | IndexKind::Composite => {
+                    output.create_index(table_name, &column_names)
+                }
+                IndexKind::Range => output.create_range_index(table_name, column_names[0]),
+                IndexKind::CaseInsensitive => {
+                    output.create_case_insensitive_index(table_name, column_names[0])
+                }
+            };
+            // UNIQUE columns were given their index along with the table
+            match created {
+                Ok(()) | Err(DbError::AlreadyExists(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        for (offset, (table_name, row)) in rows {
+            if deleted_rows.contains(&offset) || !output.tables.contains_key(&table_name) {
+                continue;
+            }
+            match output.insert(&table_name, row) {
+                Ok(_) => report.rows_recovered += 1,
+                Err(DbError::SchemaViolation(_)) => report.rows_rejected += 1,
+                Err(e) => return Err(e),
+            }
+        }
+        output.sync()?;
+
+        Ok(report)
+    }
+
+    /// Lookup rows using an index (fast path)
+    pub fn index_lookup(
+        &self,
+        table_name: &str,
+        column_name: &str,
+        value: &Value,
+    ) -> DbResult<Vec<Row>> {
+        let rows = self.index_lookup_with_offsets(table_name, column_name, value)?;
+        Ok(rows.into_iter().map(|(_, row)| row).collect())
+    }
+
+    /// Lookup rows using an index, paired with the file offset of each row
+    pub fn index_lookup_with_offsets(
+        &self,
+        table_name: &str,
+        column_name: &str,
+        value: &Value,
+    ) -> DbResult<Vec<(u64, Row)>> {
+        // Check if index exists
+        let index = self
+            .indexes
+            .get(table_name)
+            .and_then(|table_indexes| table_indexes.get(column_name))
+            .ok_or_else(|| DbError::IndexNotFound(format!("{}.{}", table_name, column_name)))?;
+
+        // A value missing from the index simply has no rows
+        let offsets = index.lookup(value).cloned().unwrap_or_default();
+
+        self.read_rows_at_offsets(offsets)
+    }
+
+    /// Whether a column has an index of its own, hash or range. Composite
+    /// indexes are listed by `composite_index_columns`.
+    pub fn has_index(&self, table_name: &str, column_name: &str) -> bool {
+        self.has_hash_index(table_name, column_name)
+            || self.has_range_index(table_name, column_name)
+    }
+
+    /// Whether a column has a hash index, which answers equality lookups
+    pub fn has_hash_index(&self, table_name: &str, column_name: &str) -> bool {
+        self.indexes
+            .get(table_name)
+            .is_some_and(|table_indexes| table_indexes.contains_key(column_name))
+    }
+
+    /// Whether a column has a range index
+    pub fn has_range_index(&self, table_name: &str, column_name: &str) -> bool {
+        self.range_indexes
+            .get(table_name)
+            .is_some_and(|table_indexes| table_indexes.contains_key(column_name))
+    }
+
+    /// The columns of a table that have an index of their own, in name order
+    pub fn list_indexes(&self, table_name: &str) -> Vec<&str> {
+        let hash_columns = self.indexes.get(table_name).into_iter().flat_map(HashMap::keys);
+        let range_columns = self.range_indexes.get(table_name).into_iter().flat_map(HashMap::keys);
+        let columns: BTreeSet<&str> = hash_columns.chain(range_columns).map(String::as_str).collect();
+        columns.into_iter().collect()
+    }
+
+    /// Statistics of the hash index on a column, if it has one
+    pub fn index_stats(&self, table_name: &str, column_name: &str) -> Option<IndexStats> {
+        let index = self.indexes.get(table_name)?.get(column_name)?;
+        Some(index.stats())
+    }
+
+    /// Statistics of the range index on a column, if it has one
+    pub fn range_index_stats(&self, table_name: &str, column_name: &str) -> Option<IndexStats> {
+        let index = self.range_indexes.get(table_name)?.get(column_name)?;
+        Some(index.stats())
+    }
+
+    /// Statistics of the composite index on exactly these columns, if there is one
+    pub fn composite_index_stats(
+        &self,
+        table_name: &str,
+        column_names: &[String],
+    ) -> Option<IndexStats> {
+        let table_indexes = self.composite_indexes.get(table_name)?;
+        let index = table_indexes.iter().find(|index| index.column_names == column_names)?;
+        Some(index.stats())
+    }
+
+    /// The columns of each composite index on a table, in index order
+    pub fn composite_index_columns(&self, table_name: &str) -> Vec<&[String]> {
+        self.composite_indexes
+            .get(table_name)
+            .map(|table_indexes| {
+                table_indexes.iter().map(|index| index.column_names.as_slice()).collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Lookup rows using a composite index, paired with the file offset of
+    /// each row. `values` are given in the same order as `column_names`.
+    pub fn composite_lookup_with_offsets(
+        &self,
+        table_name: &str,
+        column_names: &[String],
+        values: &[Value],
+    ) -> DbResult<Vec<(u64, Row)>> {
+        // Check if index exists
+        let index = self
+            .composite_indexes
+            .get(table_name)
+            .and_then(|table_indexes| {
+                table_indexes.iter().find(|index| index.column_names == column_names)
+            })
+            .ok_or_else(|| {
+                DbError::IndexNotFound(format!("{}({})", table_name, column_names.join(", ")))
+            })?;
+
+        // A tuple missing from the index simply has no rows
+        let offsets = index.lookup(values).cloned().unwrap_or_default();
+
+        self.read_rows_at_offsets(offsets)
+    }
+
+    /// Fetch the row whose primary key is `key`, going straight to it through
+    /// the primary key's index rather than scanning the table
+    pub fn get_by_primary_key(&self, table_name: &str, key: &Value) -> DbResult<Option<Row>> {
+        let row = self.get_by_primary_key_with_offset(table_name, key)?;
+        Ok(row.map(|(_, row)| row))
+    }
 
-        Ok(BitcaskStorage {
-            data_file: file,
-            data_file_path: path.to_string(),
-            tables: HashMap::new(),
-            indexes: HashMap::new(),
-            current_offset,
-        })
+    /// Fetch the row whose primary key is `key`, paired with its file offset
+    pub fn get_by_primary_key_with_offset(
+        &self,
+        table_name: &str,
+        key: &Value,
+    ) -> DbResult<Option<(u64, Row)>> {
+        let schema = self
+            .get_schema(table_name)
+            .ok_or_else(|| DbError::TableNotFound(table_name.to_string()))?;
+        let col_idx = schema.primary_key().ok_or_else(|| {
+            DbError::InvalidInput(format!("Table '{}' has no primary key", table_name))
+        })?;
+        let col_name = &schema.columns[col_idx].name;
+
+        // Index keys are the values' string forms, so confirm the hit.
+        // The backing index may have been dropped; then scan instead.
+        let rows = match self.index_lookup_with_offsets(table_name, col_name, key) {
+            Ok(rows) => rows,
+            Err(_) => self.scan_with_offsets(table_name)?,
+        };
+        Ok(rows.into_iter().find(|(_, row)| row.get(col_idx) == Some(key)))
     }
 
-    /// Create a new table
-    pub fn create_table(&mut self, schema: TableSchema) -> io::Result<()> {
-        let table_name = schema.name.clone();
+    /// Lookup rows whose column value falls within the bounds using a range
+    /// index, paired with the file offset of each row
+    pub fn range_lookup_with_offsets(
+        &self,
+        table_name: &str,
+        column_name: &str,
+        lower: Bound<Value>,
+        upper: Bound<Value>,
+    ) -> DbResult<Vec<(u64, Row)>> {
+        // Check if range index exists
+        let offsets = self
+            .range_indexes
+            .get(table_name)
+            .and_then(|table_indexes| table_indexes.get(column_name))
+            .map(|index| index.lookup_range(lower, upper))
+            .ok_or_else(|| DbError::IndexNotFound(format!("{}.{}", table_name, column_name)))?;
 
-        if self.tables.contains_key(&table_name) {
-            return Err(io::Error::new(
-                io::ErrorKind::AlreadyExists,
-                format!("Table '{}' already exists", table_name),
-            ));
-        }
+        let mut rows = Vec::new();
 
-        // Store schema in memory
-        self.tables.insert(table_name.clone(), (schema.clone(), 0));
+        // Read each row from disk using the offset
+        for offset in offsets {
+            let row = self.read_row_at_offset(offset)?;
+            rows.push((offset, row));
+        }
 
-        // Persist the schema to disk
-        self.write_schema(&schema)?;
+        Ok(rows)
+    }
 
-        println!("✓ Created table '{}'", table_name);
-        Ok(())
+    /// Bounds for reading records from the data log as it is now
+    fn record_bounds(&self) -> RecordBounds {
+        RecordBounds {
+            end: self.current_offset,
+            max_record_size: self.max_record_size,
+        }
     }
 
-    /// Write a schema to the data file
-    fn write_schema(&mut self, schema: &TableSchema) -> io::Result<()> {
-        // Format: [SCHEMA_MARKER][schema_bytes_length][schema_bytes]
-        const SCHEMA_MARKER: u8 = 0xFF;
+    /// Set the largest row a record may hold. Rows over it are rejected on
+    /// insert, and a record claiming more is treated as corrupt when read.
+    pub fn set_max_record_size(&mut self, bytes: u64) {
+        self.max_record_size = bytes;
+    }
 
-        let schema_bytes = bincode::serialize(schema).map_err(|e| {
+    /// Ensure a row fits in a record, so it can be read back
+    fn check_record_size(&self, row: &Row) -> DbResult<()> {
+        let size = bincode::serialized_size(row).map_err(|e| {
             io::Error::new(io::ErrorKind::InvalidData, format!("Serialize error: {}", e))
         })?;
+        if size > self.max_record_size {
+            return Err(DbError::InvalidInput(format!(
+                "Row of {} bytes is over the {} byte record limit",
+                size, self.max_record_size
+            )));
+        }
+
+        Ok(())
+    }
 
-        let mut writer = BufWriter::new(&self.data_file);
+    /// Read the rows at several file offsets, paired with their offsets.
+    /// The offsets are sorted first so the log is read front to back through
+    /// one buffer, which is reused whenever the next row is already in it.
+    fn read_rows_at_offsets(&self, mut offsets: Vec<u64>) -> DbResult<Vec<(u64, Row)>> {
+        offsets.sort_unstable();
+        let Some(&first) = offsets.first() else {
+            return Ok(Vec::new());
+        };
+
+        let mut reader = BufReader::new(self.reader_at(first));
+        let mut position = first;
+        let bounds = self.record_bounds();
+
+        let mut rows = Vec::with_capacity(offsets.len());
+        for offset in offsets {
+            reader.seek_relative(offset as i64 - position as i64)?;
+            match read_record(&mut reader, offset, bounds)? {
+                Some((Record::Row { bytes, .. }, record_len)) => {
+                    position = offset + record_len;
+                    rows.push((offset, deserialize(&bytes)?));
+                }
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Expected row marker at offset {}", offset),
+                    )
+                    .into());
+                }
+            }
+        }
 
-        // Write marker
-        writer.write_all(&[SCHEMA_MARKER])?;
+        Ok(rows)
+    }
 
-        // Write length (as u32)
-        let len = schema_bytes.len() as u32;
-        writer.write_all(&len.to_le_bytes())?;
+    /// Read the row record at a log offset, for tools that inspect the log.
+    /// The row is returned even if it has since been deleted. An offset past
+    /// the end of the log, or one that isn't the start of a row record, is
+    /// rejected with `InvalidInput`.
+    pub fn read_row_at(&self, offset: u64) -> DbResult<Row> {
+        if offset >= self.current_offset {
+            return Err(DbError::InvalidInput(format!(
+                "Offset {} is past the end of the log at {}",
+                offset, self.current_offset
+            )));
+        }
 
-        // Write schema bytes
-        writer.write_all(&schema_bytes)?;
+        let mut marker = [0u8; 1];
+        self.reader_at(offset).read_exact(&mut marker)?;
+        if marker[0] != ROW_MARKER {
+            return Err(DbError::InvalidInput(format!(
+                "Offset {} is not the start of a row record",
+                offset
+            )));
+        }
 
-        writer.flush()?;
+        self.read_row_at_offset(offset)
+    }
 
-        // Update offset
-        self.current_offset += 1 + 4 + schema_bytes.len() as u64;
+    /// Read a single row from a specific file offset
+    fn read_row_at_offset(&self, offset: u64) -> DbResult<Row> {
+        let mut reader = BufReader::new(self.reader_at(offset));
 
-        Ok(())
+        match read_record(&mut reader, offset, self.record_bounds())? {
+            Some((Record::Row { bytes, .. }, _)) => Ok(deserialize(&bytes)?),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Expected row marker at offset {}", offset),
+            )
+            .into()),
+        }
     }
 
-    /// Insert a row into a table
-    pub fn insert(&mut self, table_name: &str, row: Row) -> io::Result<u64> {
-        // Get schema and validate
-        let (schema, row_count) = self
-            .tables
-            .get_mut(table_name)
-            .ok_or_else(|| {
-                io::Error::new(
-                    io::ErrorKind::NotFound,
-                    format!("Table '{}' not found", table_name),
-                )
-            })?;
+    /// Get table schema
+    pub fn get_schema(&self, table_name: &str) -> Option<&TableSchema> {
+        self.tables.get(table_name).map(|(schema, _)| schema)
+    }
 
-        // Validate row matches schema
-        schema.validate_row(&row).map_err(|e| {
-            io::Error::new(io::ErrorKind::InvalidData, e)
-        })?;
+    /// Schemas of every table, sorted by name
+    pub fn list_tables(&self) -> Vec<&TableSchema> {
+        let mut schemas: Vec<&TableSchema> =
+            self.tables.values().map(|(schema, _)| schema).collect();
+        schemas.sort_by(|a, b| a.name.cmp(&b.name));
+        schemas
+    }
 
-        // Remember offset before writing
-        let row_offset = self.current_offset;
+    /// Get the number of live rows in a table without scanning it
+    pub fn row_count(&self, table_name: &str) -> Option<u64> {
+        self.tables.get(table_name).map(|(_, row_count)| *row_count)
+    }
 
-        // Write row to disk
-        self.write_row(table_name, &row)?;
+    /// Report how big the database is and how much of its data log is
+    /// stale. Counting the records reads the whole data log.
+    pub fn stats(&self) -> DbResult<StorageStats> {
+        let mut reader = BufReader::new(self.reader_at(0));
+        let bounds = self.record_bounds();
+        let mut offset = 0u64;
+        let mut total_records = 0u64;
+        while let Some((_, record_len)) = read_record(&mut reader, offset, bounds)? {
+            offset += record_len;
+            total_records += 1;
+        }
 
-        // Update indexes if they exist
-        if let Some(table_indexes) = self.indexes.get_mut(table_name) {
-            for (col_name, index) in table_indexes.iter_mut() {
-                if let Some(col_idx) = schema.get_column_index(col_name) {
-                    if let Some(value) = row.get(col_idx) {
-                        index.insert(value, row_offset);
-                    }
+        let live_rows: u64 = self.tables.values().map(|(_, row_count)| row_count).sum();
+        let hash_memory: usize = self
+            .indexes
+            .values()
+            .flat_map(HashMap::values)
+            .map(Index::memory_usage)
+            .sum();
+        let range_memory: usize = self
+            .range_indexes
+            .values()
+            .flat_map(HashMap::values)
+            .map(RangeIndex::memory_usage)
+            .sum();
+        let composite_memory: usize = self
+            .composite_indexes
+            .values()
+            .flatten()
+            .map(CompositeIndex::memory_usage)
+            .sum();
+
+        Ok(StorageStats {
+            file_size: self.current_offset + self.catalog_file.size()?,
+            tables: self.tables.len(),
+            live_rows,
+            total_records,
+            stale_records: total_records.saturating_sub(live_rows),
+            index_memory: hash_memory + range_memory + composite_memory,
+        })
+    }
+}
+
+/// Undo the row counts and deletions of a transaction found to have been
+/// rolled back while replaying the log, newest change first
+fn discard_changes(
+    changes: Vec<TransactionChange>,
+    tables: &mut HashMap<String, (TableSchema, u64)>,
+    deleted_rows: &mut HashSet<u64>,
+) {
+    for change in changes.into_iter().rev() {
+        match change {
+            TransactionChange::Inserted { table, offset } => {
+                deleted_rows.insert(offset);
+                if let Some((_, row_count)) = tables.get_mut(&table) {
+                    *row_count = row_count.saturating_sub(1);
+                }
+            }
+            TransactionChange::Deleted { table, offset } => {
+                deleted_rows.remove(&offset);
+                if let Some((_, row_count)) = tables.get_mut(&table) {
+                    *row_count += 1;
                 }
             }
         }
+    }
+}
 
-        // Increment row count
-        *row_count += 1;
+fn no_transaction() -> DbError {
+    DbError::InvalidInput("No transaction is open".to_string())
+}
 
-        Ok(row_offset)
+/// Read the whole data log of the database at `path`, every segment in turn
+fn read_data_log(path: &str) -> io::Result<Vec<u8>> {
+    let mut log = fs::read(path)?;
+    let mut id = 1;
+    while Path::new(&segment_path(path, id)).exists() {
+        log.extend(fs::read(segment_path(path, id))?);
+        id += 1;
     }
+    Ok(log)
+}
 
-    /// Write a row to the data file
-    fn write_row(&mut self, table_name: &str, row: &Row) -> io::Result<()> {
-        // Format: [ROW_MARKER][table_name_len][table_name][row_bytes_len][row_bytes]
-        const ROW_MARKER: u8 = 0xAA;
+/// Offset of the first row record at or after `start` that reads back whole
+/// for a table in `tables`, or the end of the log if there is none. Only row
+/// records carry a checksum, so only they can be trusted after damage.
+fn next_row_record(
+    log: &[u8],
+    start: u64,
+    bounds: RecordBounds,
+    tables: &HashMap<String, (TableSchema, u64)>,
+) -> u64 {
+    (start..bounds.end)
+        .find(|&offset| {
+            log[offset as usize] == ROW_MARKER
+                && matches!(
+                    read_record(&mut &log[offset as usize..], offset, bounds),
+                    Ok(Some((Record::Row { table, .. }, _))) if tables.contains_key(&table)
+                )
+        })
+        .unwrap_or(bounds.end)
+}
 
-        let row_bytes = bincode::serialize(row).map_err(|e| {
-            io::Error::new(io::ErrorKind::InvalidData, format!("Serialize error: {}", e))
-        })?;
+/// The values of `row` at `columns`, in that order
+fn project_row(row: &Row, columns: &[usize]) -> Row {
+    let values = columns
+        .iter()
+        .map(|&i| row.get(i).cloned().unwrap_or(Value::Null))
+        .collect();
+    Row::new(values)
+}
+
+/// Positions and names of a table's UNIQUE columns
+fn unique_columns(schema: &TableSchema) -> Vec<(usize, String)> {
+    schema
+        .columns
+        .iter()
+        .enumerate()
+        .filter(|(_, column)| column.unique)
+        .map(|(i, column)| (i, column.name.clone()))
+        .collect()
+}
 
-        let table_name_bytes = table_name.as_bytes();
+/// Path of data log segment `id`; the first segment is the log path itself
+fn segment_path(path: &str, id: usize) -> String {
+    if id == 0 {
+        path.to_string()
+    } else {
+        format!("{}.{}", path, id)
+    }
+}
 
-        let mut writer = BufWriter::new(&self.data_file);
+/// Finish a compaction whose log was completed before a crash: remove the
+/// old segments and move the compacted log into place
+fn finish_compaction(path: &str) -> io::Result<()> {
+    let compacted_path = format!("{}.compacted", path);
+    if !Path::new(&compacted_path).exists() {
+        return Ok(());
+    }
 
-        // Write marker
-        writer.write_all(&[ROW_MARKER])?;
+    // Offsets in the compacted log differ, so a checkpoint no longer applies
+    let checkpoint_path = format!("{}.checkpoint", path);
+    if Path::new(&checkpoint_path).exists() {
+        fs::remove_file(&checkpoint_path)?;
+    }
 
-        // Write table name length and name
-        let table_name_len = table_name_bytes.len() as u16;
-        writer.write_all(&table_name_len.to_le_bytes())?;
-        writer.write_all(table_name_bytes)?;
+    // Remove the newest segments first, so the ones left never have a gap
+    let mut segment_count = 1;
+    while Path::new(&segment_path(path, segment_count)).exists() {
+        segment_count += 1;
+    }
+    for id in (1..segment_count).rev() {
+        fs::remove_file(segment_path(path, id))?;
+    }
 
-        // Write row bytes length and bytes
-        let row_len = row_bytes.len() as u32;
-        writer.write_all(&row_len.to_le_bytes())?;
-        writer.write_all(&row_bytes)?;
+    fs::rename(&compacted_path, path)
+}
 
-        writer.flush()?;
+/// Encode a schema record, returning its length in bytes
+fn write_schema_record<W: Write>(writer: &mut W, schema: &TableSchema) -> io::Result<u64> {
+    // Format: [SCHEMA_MARKER][version][schema_bytes_length][schema_bytes]
+    //         [crc32(version + schema_bytes)]
+    let schema_bytes = bincode::serialize(schema).map_err(|e| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("Serialize error: {}", e))
+    })?;
 
-        // Update offset
-        self.current_offset += 1 + 2 + table_name_bytes.len() as u64 + 4 + row_bytes.len() as u64;
+    // Write marker
+    writer.write_all(&[SCHEMA_MARKER])?;
 
-        Ok(())
-    }
+    // Write layout version
+    let version_bytes = SCHEMA_VERSION.to_le_bytes();
+    writer.write_all(&version_bytes)?;
 
-    /// Scan all rows in a table (slow path - no index)
-    pub fn scan(&mut self, table_name: &str) -> io::Result<Vec<Row>> {
-        let mut rows = Vec::new();
+    // Write length (as u32)
+    let len = schema_bytes.len() as u32;
+    writer.write_all(&len.to_le_bytes())?;
 
-        // Rewind to start of file
-        self.data_file.seek(SeekFrom::Start(0))?;
+    // Write schema bytes
+    writer.write_all(&schema_bytes)?;
 
-        let mut reader = BufReader::new(&self.data_file);
-        let mut marker = [0u8; 1];
+    // Write checksum
+    let mut crc = Crc32::new();
+    crc.update(&version_bytes);
+    crc.update(&schema_bytes);
+    writer.write_all(&crc.finalize().to_le_bytes())?;
 
-        loop {
-            // Try to read marker
-            match reader.read_exact(&mut marker) {
-                Ok(_) => {}
-                Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
-                Err(e) => return Err(e),
-            }
+    Ok(1 + 4 + 4 + schema_bytes.len() as u64 + 4)
+}
 
-            match marker[0] {
-                0xFF => {
-                    // Schema record - skip it
-                    let mut len_bytes = [0u8; 4];
-                    reader.read_exact(&mut len_bytes)?;
-                    let len = u32::from_le_bytes(len_bytes) as usize;
-
-                    let mut schema_bytes = vec![0u8; len];
-                    reader.read_exact(&mut schema_bytes)?;
-                }
-                0xAA => {
-                    // Row record
-                    let mut table_name_len_bytes = [0u8; 2];
-                    reader.read_exact(&mut table_name_len_bytes)?;
-                    let table_name_len = u16::from_le_bytes(table_name_len_bytes) as usize;
-
-                    let mut table_name_bytes = vec![0u8; table_name_len];
-                    reader.read_exact(&mut table_name_bytes)?;
-                    let current_table = String::from_utf8_lossy(&table_name_bytes);
-
-                    let mut row_len_bytes = [0u8; 4];
-                    reader.read_exact(&mut row_len_bytes)?;
-                    let row_len = u32::from_le_bytes(row_len_bytes) as usize;
-
-                    let mut row_bytes = vec![0u8; row_len];
-                    reader.read_exact(&mut row_bytes)?;
-
-                    // Only deserialize if it's our table
-                    if current_table == table_name {
-                        let row: Row = bincode::deserialize(&row_bytes).map_err(|e| {
-                            io::Error::new(
-                                io::ErrorKind::InvalidData,
-                                format!("Deserialize error: {}", e),
-                            )
-                        })?;
-                        rows.push(row);
-                    }
-                }
-                _ => {
-                    return Err(io::Error::new(
-                        io::ErrorKind::InvalidData,
-                        format!("Unknown marker: {:#x}", marker[0]),
-                    ));
-                }
-            }
-        }
+/// Encode a row record, returning its length in bytes
+fn write_row_record<W: Write>(writer: &mut W, table_name: &str, row: &Row) -> io::Result<u64> {
+    write_row_record_with(writer, table_name, row, &mut Vec::new())
+}
 
-        Ok(rows)
+/// Encode a row record like `write_row_record`, serializing the row into
+/// `row_bytes` so writers of many rows can reuse one buffer
+fn write_row_record_with<W: Write>(
+    writer: &mut W,
+    table_name: &str,
+    row: &Row,
+    row_bytes: &mut Vec<u8>,
+) -> io::Result<u64> {
+    // Format: [ROW_MARKER][table_name_len][table_name][row_bytes_len][row_bytes]
+    //         [crc32(table_name + row_bytes)]
+    row_bytes.clear();
+    bincode::serialize_into(&mut *row_bytes, row).map_err(|e| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("Serialize error: {}", e))
+    })?;
+
+    // Write marker
+    writer.write_all(&[ROW_MARKER])?;
+
+    // Write table name length and name
+    let table_name_len = write_name(writer, table_name)?;
+
+    // Write row bytes length and bytes
+    let row_len = row_bytes.len() as u32;
+    writer.write_all(&row_len.to_le_bytes())?;
+    writer.write_all(row_bytes)?;
+
+    // Write checksum
+    let mut crc = Crc32::new();
+    crc.update(table_name.as_bytes());
+    crc.update(row_bytes);
+    writer.write_all(&crc.finalize().to_le_bytes())?;
+
+    Ok(1 + table_name_len + 4 + row_bytes.len() as u64 + 4)
+}
+
+/// Encode a tombstone record, returning its length in bytes
+fn write_tombstone_record<W: Write>(
+    writer: &mut W,
+    table_name: &str,
+    row_offset: u64,
+) -> io::Result<u64> {
+    // Format: [TOMBSTONE_MARKER][table_name_len][table_name][row_offset]
+    writer.write_all(&[TOMBSTONE_MARKER])?;
+
+    // Write table name length and name
+    let table_name_len = write_name(writer, table_name)?;
+
+    // Write offset of the deleted row
+    writer.write_all(&row_offset.to_le_bytes())?;
+
+    Ok(1 + table_name_len + 8)
+}
+
+/// Encode a drop-table record, returning its length in bytes
+fn write_drop_table_record<W: Write>(writer: &mut W, table_name: &str) -> io::Result<u64> {
+    // Format: [DROP_TABLE_MARKER][table_name_len][table_name]
+    writer.write_all(&[DROP_TABLE_MARKER])?;
+
+    // Write table name length and name
+    let table_name_len = write_name(writer, table_name)?;
+
+    Ok(1 + table_name_len)
+}
+
+/// Encode a truncated table record, returning its length in bytes
+fn write_truncate_record<W: Write>(writer: &mut W, table_name: &str) -> io::Result<u64> {
+    // Format: [TRUNCATE_MARKER][table_name_len][table_name]
+    writer.write_all(&[TRUNCATE_MARKER])?;
+
+    // Write table name length and name
+    let table_name_len = write_name(writer, table_name)?;
+
+    Ok(1 + table_name_len)
+}
+
+/// Encode an index definition record, returning its length in bytes
+fn write_index_record<W: Write, S: AsRef<str>>(
+    writer: &mut W,
+    table_name: &str,
+    column_names: &[S],
+    kind: IndexKind,
+) -> io::Result<u64> {
+    // Format: [INDEX_MARKER|RANGE_INDEX_MARKER|CASE_INSENSITIVE_INDEX_MARKER]
+    //         [table_name_len][table_name][column_name_len][column_name]
+    //     or: [COMPOSITE_INDEX_MARKER][table_name_len][table_name][column_count]
+    //         ([column_name_len][column_name])*
+    let marker = match kind {
+        IndexKind::Hash => INDEX_MARKER,
+        IndexKind::Range => RANGE_INDEX_MARKER,
+        IndexKind::Composite => COMPOSITE_INDEX_MARKER,
+        IndexKind::CaseInsensitive => CASE_INSENSITIVE_INDEX_MARKER,
+    };
+    writer.write_all(&[marker])?;
+
+    // Write table name
+    let mut record_len = 1 + write_name(writer, table_name)?;
+
+    // Only a composite index has more than one column, so only it stores a count
+    if let IndexKind::Composite = kind {
+        writer.write_all(&(column_names.len() as u16).to_le_bytes())?;
+        record_len += 2;
+    }
+    for column_name in column_names {
+        record_len += write_name(writer, column_name.as_ref())?;
     }
 
-    /// Create an index on a column (fast path)
-    pub fn create_index(&mut self, table_name: &str, column_name: &str) -> io::Result<()> {
-        // Get schema
-        let (schema, _) = self.tables.get(table_name).ok_or_else(|| {
-            io::Error::new(
-                io::ErrorKind::NotFound,
-                format!("Table '{}' not found", table_name),
-            )
-        })?;
+    Ok(record_len)
+}
 
-        // Check column exists
-        let column_index = schema.get_column_index(column_name).ok_or_else(|| {
-            io::Error::new(
-                io::ErrorKind::NotFound,
-                format!("Column '{}' not found in table '{}'", column_name, table_name),
-            )
-        })?;
+/// Encode a dropped index record, returning its length in bytes
+fn write_drop_index_record<W: Write>(
+    writer: &mut W,
+    table_name: &str,
+    column_name: &str,
+) -> io::Result<u64> {
+    // Format: [DROP_INDEX_MARKER][table_name_len][table_name][column_name_len][column_name]
+    writer.write_all(&[DROP_INDEX_MARKER])?;
 
-        // Create the index structure
-        let mut index = Index::new(table_name.to_string(), column_name.to_string(), column_index);
+    // Write table and column names
+    let table_name_len = write_name(writer, table_name)?;
+    let column_name_len = write_name(writer, column_name)?;
 
-        // Build the index by scanning the file
-        self.data_file.seek(SeekFrom::Start(0))?;
+    Ok(1 + table_name_len + column_name_len)
+}
 
-        let mut reader = BufReader::new(&self.data_file);
-        let mut marker = [0u8; 1];
-        let mut current_offset = 0u64;
+/// Encode a transaction begin, commit or rollback record, returning its length in bytes
+fn write_transaction_record<W: Write>(writer: &mut W, marker: u8) -> io::Result<u64> {
+    // Format: [BEGIN_MARKER|COMMIT_MARKER|ROLLBACK_MARKER]
+    writer.write_all(&[marker])?;
 
-        loop {
-            match reader.read_exact(&mut marker) {
-                Ok(_) => {}
-                Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
-                Err(e) => return Err(e),
-            }
+    Ok(1)
+}
 
-            match marker[0] {
-                0xFF => {
-                    // Schema - skip
-                    let mut len_bytes = [0u8; 4];
-                    reader.read_exact(&mut len_bytes)?;
-                    let len = u32::from_le_bytes(len_bytes) as usize;
+/// Write a length-prefixed table or column name, returning its encoded length
+fn write_name<W: Write>(writer: &mut W, name: &str) -> io::Result<u64> {
+    let name_bytes = name.as_bytes();
 
-                    let mut schema_bytes = vec![0u8; len];
-                    reader.read_exact(&mut schema_bytes)?;
+    let name_len = name_bytes.len() as u16;
+    writer.write_all(&name_len.to_le_bytes())?;
+    writer.write_all(name_bytes)?;
 
-                    current_offset += 1 + 4 + len as u64;
-                }
-                0xAA => {
-                    // Row
-                    let row_start_offset = current_offset;
+    Ok(2 + name_bytes.len() as u64)
+}
+
+/// Read the next record from the log, along with its total length in bytes.
+/// `offset` is the record's position in the file, used for error reporting.
+/// Returns `None` once the end of the file is reached, and a `TornRecord`
+/// error if the file ends partway through the record.
+fn read_record<R: Read>(
+    reader: &mut R,
+    offset: u64,
+    bounds: RecordBounds,
+) -> io::Result<Option<(Record, u64)>> {
+    // Try to read marker
+    let mut marker = [0u8; 1];
+    match reader.read_exact(&mut marker) {
+        Ok(_) => {}
+        Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
 
-                    let mut table_name_len_bytes = [0u8; 2];
-                    reader.read_exact(&mut table_name_len_bytes)?;
-                    let table_name_len = u16::from_le_bytes(table_name_len_bytes) as usize;
+    match read_record_body(reader, marker[0], offset, bounds) {
+        Ok(record) => Ok(Some(record)),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Err(torn_record(offset)),
+        Err(e) => Err(e),
+    }
+}
 
-                    let mut table_name_bytes = vec![0u8; table_name_len];
-                    reader.read_exact(&mut table_name_bytes)?;
-                    let current_table = String::from_utf8_lossy(&table_name_bytes);
+/// Read the rest of a record whose marker has been read
+fn read_record_body<R: Read>(
+    reader: &mut R,
+    marker: u8,
+    offset: u64,
+    bounds: RecordBounds,
+) -> io::Result<(Record, u64)> {
+    match marker {
+        SCHEMA_MARKER => {
+            let mut version_bytes = [0u8; 4];
+            reader.read_exact(&mut version_bytes)?;
+            let version = u32::from_le_bytes(version_bytes);
+
+            let mut len_bytes = [0u8; 4];
+            reader.read_exact(&mut len_bytes)?;
+            let len = u32::from_le_bytes(len_bytes);
+            let len = check_record_length(len, offset, 1 + 4 + 4, bounds)?;
+
+            let mut schema_bytes = vec![0u8; len];
+            reader.read_exact(&mut schema_bytes)?;
+
+            let mut crc = Crc32::new();
+            crc.update(&version_bytes);
+            crc.update(&schema_bytes);
+            verify_checksum(reader, crc.finalize(), offset)?;
+
+            let schema = decode_schema(version, &schema_bytes)?;
+            Ok((Record::Schema(schema), 1 + 4 + 4 + len as u64 + 4))
+        }
+        SCHEMA_V0_MARKER | SCHEMA_V1_MARKER => {
+            let mut len_bytes = [0u8; 4];
+            reader.read_exact(&mut len_bytes)?;
+            let len = u32::from_le_bytes(len_bytes);
+            let len = check_record_length(len, offset, 1 + 4, bounds)?;
 
-                    let mut row_len_bytes = [0u8; 4];
-                    reader.read_exact(&mut row_len_bytes)?;
-                    let row_len = u32::from_le_bytes(row_len_bytes) as usize;
+            let mut schema_bytes = vec![0u8; len];
+            reader.read_exact(&mut schema_bytes)?;
 
-                    let mut row_bytes = vec![0u8; row_len];
-                    reader.read_exact(&mut row_bytes)?;
+            verify_checksum(reader, crc32(&schema_bytes), offset)?;
 
-                    current_offset += 1 + 2 + table_name_len as u64 + 4 + row_len as u64;
+            let version = if marker == SCHEMA_V0_MARKER { 0 } else { 1 };
+            let schema = decode_schema(version, &schema_bytes)?;
+            Ok((Record::Schema(schema), 1 + 4 + len as u64 + 4))
+        }
+        ROW_MARKER => {
+            let (table, table_name_len) = read_name(reader)?;
 
-                    // If this row belongs to our table, add to index
-                    if current_table == table_name {
-                        let row: Row = bincode::deserialize(&row_bytes).map_err(|e| {
-                            io::Error::new(
-                                io::ErrorKind::InvalidData,
-                                format!("Deserialize error: {}", e),
-                            )
-                        })?;
+            let mut row_len_bytes = [0u8; 4];
+            reader.read_exact(&mut row_len_bytes)?;
+            let row_len = u32::from_le_bytes(row_len_bytes);
+            let header_len = 1 + 2 + table_name_len as u64 + 4;
+            let row_len = check_record_length(row_len, offset, header_len, bounds)?;
 
-                        if let Some(value) = row.get(column_index) {
-                            index.insert(value, row_start_offset);
-                        }
-                    }
-                }
-                _ => {
-                    return Err(io::Error::new(
-                        io::ErrorKind::InvalidData,
-                        format!("Unknown marker: {:#x}", marker[0]),
-                    ));
-                }
+            let mut row_bytes = vec![0u8; row_len];
+            reader.read_exact(&mut row_bytes)?;
+
+            let mut crc = Crc32::new();
+            crc.update(table.as_bytes());
+            crc.update(&row_bytes);
+            verify_checksum(reader, crc.finalize(), offset)?;
+
+            let record_len = 1 + 2 + table_name_len as u64 + 4 + row_len as u64 + 4;
+            Ok((Record::Row { table, bytes: row_bytes }, record_len))
+        }
+        TOMBSTONE_MARKER => {
+            let (table, table_name_len) = read_name(reader)?;
+
+            let mut row_offset_bytes = [0u8; 8];
+            reader.read_exact(&mut row_offset_bytes)?;
+            let row_offset = u64::from_le_bytes(row_offset_bytes);
+
+            let record_len = 1 + 2 + table_name_len as u64 + 8;
+            Ok((Record::Tombstone { table, row_offset }, record_len))
+        }
+        DROP_TABLE_MARKER => {
+            let (table, table_name_len) = read_name(reader)?;
+
+            let record_len = 1 + 2 + table_name_len as u64;
+            Ok((Record::DropTable { table }, record_len))
+        }
+        TRUNCATE_MARKER => {
+            let (table, table_name_len) = read_name(reader)?;
+
+            let record_len = 1 + 2 + table_name_len as u64;
+            Ok((Record::Truncate { table }, record_len))
+        }
+        INDEX_MARKER | RANGE_INDEX_MARKER | CASE_INSENSITIVE_INDEX_MARKER => {
+            let (table, table_name_len) = read_name(reader)?;
+            let (column, column_name_len) = read_name(reader)?;
+
+            let kind = match marker {
+                RANGE_INDEX_MARKER => IndexKind::Range,
+                CASE_INSENSITIVE_INDEX_MARKER => IndexKind::CaseInsensitive,
+                _ => IndexKind::Hash,
+            };
+
+            let record_len = 1 + 2 + table_name_len as u64 + 2 + column_name_len as u64;
+            let columns = vec![column];
+            Ok((Record::Index { table, columns, kind }, record_len))
+        }
+        COMPOSITE_INDEX_MARKER => {
+            let (table, table_name_len) = read_name(reader)?;
+
+            let mut column_count_bytes = [0u8; 2];
+            reader.read_exact(&mut column_count_bytes)?;
+            let column_count = u16::from_le_bytes(column_count_bytes);
+
+            let mut record_len = 1 + 2 + table_name_len as u64 + 2;
+            let mut columns = Vec::with_capacity(column_count as usize);
+            for _ in 0..column_count {
+                let (column, column_name_len) = read_name(reader)?;
+                record_len += 2 + column_name_len as u64;
+                columns.push(column);
             }
+
+            let kind = IndexKind::Composite;
+            Ok((Record::Index { table, columns, kind }, record_len))
         }
+        DROP_INDEX_MARKER => {
+            let (table, table_name_len) = read_name(reader)?;
+            let (column, column_name_len) = read_name(reader)?;
 
-        // Store the index
-        self.indexes
-            .entry(table_name.to_string())
-            .or_insert_with(HashMap::new)
-            .insert(column_name.to_string(), index);
+            let record_len = 1 + 2 + table_name_len as u64 + 2 + column_name_len as u64;
+            Ok((Record::DropIndex { table, column }, record_len))
+        }
+        BEGIN_MARKER => Ok((Record::Begin, 1)),
+        COMMIT_MARKER => Ok((Record::Commit, 1)),
+        ROLLBACK_MARKER => Ok((Record::Rollback, 1)),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Unknown marker {:#x} in record at offset {}", marker, offset),
+        )),
+    }
+}
 
-        println!("✓ Created index on {}.{}", table_name, column_name);
-        Ok(())
+/// Decode a checkpoint file, or `None` if it is damaged
+fn decode_checkpoint(bytes: &[u8]) -> Option<Checkpoint> {
+    let (crc_bytes, checkpoint_bytes) = bytes.split_first_chunk::<4>()?;
+    if u32::from_le_bytes(*crc_bytes) != crc32(checkpoint_bytes) {
+        return None;
+    }
+    deserialize(checkpoint_bytes).ok()
+}
+
+/// Check the body length a record at `offset` claims, before allocating for
+/// it. `header_len` is the number of bytes of the record before the body,
+/// which is followed by a 4-byte checksum.
+fn check_record_length(
+    len: u32,
+    offset: u64,
+    header_len: u64,
+    bounds: RecordBounds,
+) -> io::Result<usize> {
+    let len = len as u64;
+    if len > bounds.max_record_size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Record at offset {} claims {} bytes, over the {} byte record limit",
+                offset, len, bounds.max_record_size
+            ),
+        ));
     }
 
-    /// Lookup rows using an index (fast path)
-    pub fn index_lookup(&mut self, table_name: &str, column_name: &str, value: &Value) -> io::Result<Vec<Row>> {
-        // Check if index exists
-        let offsets = self
-            .indexes
-            .get(table_name)
-            .and_then(|table_indexes| table_indexes.get(column_name))
-            .and_then(|index| index.lookup(value))
-            .ok_or_else(|| {
-                io::Error::new(
-                    io::ErrorKind::NotFound,
-                    format!("No index on {}.{}", table_name, column_name),
-                )
-            })?;
+    // A body and checksum running past the end of the log were cut short
+    if len + 4 > bounds.end.saturating_sub(offset + header_len) {
+        return Err(torn_record(offset));
+    }
 
-        let mut rows = Vec::new();
+    Ok(len as usize)
+}
 
-        // Read each row from disk using the offset
-        for &offset in offsets {
-            let row = self.read_row_at_offset(offset)?;
-            rows.push(row);
-        }
+/// A record the log ends partway through, as a crash while it was being
+/// written leaves it
+#[derive(Debug)]
+struct TornRecord {
+    offset: u64,
+}
 
-        Ok(rows)
+impl fmt::Display for TornRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Record at offset {} is cut short by the end of the log", self.offset)
     }
+}
 
-    /// Read a single row from a specific file offset
-    fn read_row_at_offset(&mut self, offset: u64) -> io::Result<Row> {
-        self.data_file.seek(SeekFrom::Start(offset))?;
+impl Error for TornRecord {}
 
-        let mut reader = BufReader::new(&self.data_file);
+fn torn_record(offset: u64) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, TornRecord { offset })
+}
 
-        // Read marker
-        let mut marker = [0u8; 1];
-        reader.read_exact(&mut marker)?;
+/// Whether reading a record failed because the log ends partway through it
+fn is_torn(e: &io::Error) -> bool {
+    e.get_ref().is_some_and(|inner| inner.is::<TornRecord>())
+}
 
-        if marker[0] != 0xAA {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Expected row marker",
+/// Read a stored checksum and compare it against the computed one
+fn verify_checksum<R: Read>(reader: &mut R, expected: u32, offset: u64) -> io::Result<()> {
+    let mut crc_bytes = [0u8; 4];
+    reader.read_exact(&mut crc_bytes)?;
+
+    if u32::from_le_bytes(crc_bytes) != expected {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Checksum mismatch in record at offset {}", offset),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Read a length-prefixed table or column name, returning it with its encoded length
+fn read_name<R: Read>(reader: &mut R) -> io::Result<(String, usize)> {
+    let mut table_name_len_bytes = [0u8; 2];
+    reader.read_exact(&mut table_name_len_bytes)?;
+    let table_name_len = u16::from_le_bytes(table_name_len_bytes) as usize;
+
+    let mut table_name_bytes = vec![0u8; table_name_len];
+    reader.read_exact(&mut table_name_bytes)?;
+
+    Ok((String::from_utf8_lossy(&table_name_bytes).into_owned(), table_name_len))
+}
+
+fn deserialize<T: DeserializeOwned>(bytes: &[u8]) -> io::Result<T> {
+    bincode::deserialize(bytes).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Deserialize error: {}", e),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::types::{Column, ColumnType, IntWidth};
+
+    /// A database path in a fresh scratch directory, removed when dropped
+    struct ScratchDb {
+        dir: std::path::PathBuf,
+    }
+
+    impl ScratchDb {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "selfhealdb-test-{}-{}",
+                std::process::id(),
+                name
             ));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            ScratchDb { dir }
         }
 
-        // Read table name
-        let mut table_name_len_bytes = [0u8; 2];
-        reader.read_exact(&mut table_name_len_bytes)?;
-        let table_name_len = u16::from_le_bytes(table_name_len_bytes) as usize;
+        fn path(&self) -> String {
+            self.dir.join("test.db").to_string_lossy().into_owned()
+        }
+    }
 
-        let mut table_name_bytes = vec![0u8; table_name_len];
-        reader.read_exact(&mut table_name_bytes)?;
+    impl Drop for ScratchDb {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
 
-        // Read row
-        let mut row_len_bytes = [0u8; 4];
-        reader.read_exact(&mut row_len_bytes)?;
-        let row_len = u32::from_le_bytes(row_len_bytes) as usize;
+    fn create_numbers(storage: &mut BitcaskStorage) {
+        let column = Column {
+            name: "n".to_string(),
+            column_type: ColumnType::Integer,
+            int_width: IntWidth::Big,
+            nullable: false,
+            unique: false,
+            primary_key: false,
+            auto_increment: false,
+            default: None,
+        };
+        storage
+            .create_table(TableSchema::new("numbers".to_string(), vec![column]))
+            .unwrap();
+    }
 
-        let mut row_bytes = vec![0u8; row_len];
-        reader.read_exact(&mut row_bytes)?;
+    fn numbers(storage: &BitcaskStorage) -> Vec<Value> {
+        storage
+            .scan_iter("numbers")
+            .unwrap()
+            .map(|entry| entry.unwrap().1.values[0].clone())
+            .collect()
+    }
 
-        let row: Row = bincode::deserialize(&row_bytes).map_err(|e| {
-            io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!("Deserialize error: {}", e),
+    /// Cut `bytes` bytes off the end of a file
+    fn chop(path: &str, bytes: u64) {
+        let file = OpenOptions::new().write(true).open(path).unwrap();
+        let len = file.metadata().unwrap().len();
+        file.set_len(len - bytes).unwrap();
+    }
+
+    #[test]
+    fn open_truncates_torn_tail() {
+        let db = ScratchDb::new("torn-tail");
+        let mut storage = BitcaskStorage::new(&db.path()).unwrap();
+        create_numbers(&mut storage);
+        for n in 1..=3 {
+            storage
+                .insert("numbers", Row::new(vec![Value::Integer(n)]))
+                .unwrap();
+        }
+        drop(storage);
+        let full_len = fs::metadata(db.path()).unwrap().len();
+        chop(&db.path(), 3);
+
+        let mut storage = BitcaskStorage::open(&db.path()).unwrap();
+        assert_eq!(numbers(&storage), [Value::Integer(1), Value::Integer(2)]);
+        assert_eq!(storage.row_count("numbers"), Some(2));
+
+        // New rows go where the torn one was, and survive another reopen
+        storage
+            .insert("numbers", Row::new(vec![Value::Integer(4)]))
+            .unwrap();
+        drop(storage);
+        assert_eq!(fs::metadata(db.path()).unwrap().len(), full_len);
+        let storage = BitcaskStorage::open(&db.path()).unwrap();
+        assert_eq!(
+            numbers(&storage),
+            [Value::Integer(1), Value::Integer(2), Value::Integer(4)]
+        );
+    }
+
+    #[test]
+    fn open_rolls_back_transaction_with_torn_tail() {
+        let db = ScratchDb::new("torn-transaction");
+        let mut storage = BitcaskStorage::new(&db.path()).unwrap();
+        create_numbers(&mut storage);
+        storage
+            .insert("numbers", Row::new(vec![Value::Integer(1)]))
+            .unwrap();
+        storage.begin_transaction().unwrap();
+        storage
+            .insert("numbers", Row::new(vec![Value::Integer(2)]))
+            .unwrap();
+        storage
+            .insert("numbers", Row::new(vec![Value::Integer(3)]))
+            .unwrap();
+        storage.flush().unwrap();
+        drop(storage);
+        chop(&db.path(), 1);
+
+        let storage = BitcaskStorage::open(&db.path()).unwrap();
+        assert_eq!(numbers(&storage), [Value::Integer(1)]);
+        assert_eq!(storage.row_count("numbers"), Some(1));
+    }
+
+    #[test]
+    fn read_only_open_ignores_torn_tail() {
+        let db = ScratchDb::new("torn-read-only");
+        let mut storage = BitcaskStorage::new(&db.path()).unwrap();
+        create_numbers(&mut storage);
+        storage
+            .insert("numbers", Row::new(vec![Value::Integer(1)]))
+            .unwrap();
+        storage
+            .insert("numbers", Row::new(vec![Value::Integer(2)]))
+            .unwrap();
+        drop(storage);
+        chop(&db.path(), 3);
+        let chopped_len = fs::metadata(db.path()).unwrap().len();
+
+        let storage = BitcaskStorage::open_read_only(&db.path()).unwrap();
+        assert_eq!(numbers(&storage), [Value::Integer(1)]);
+        assert_eq!(fs::metadata(db.path()).unwrap().len(), chopped_len);
+    }
+
+    #[test]
+    fn open_restores_rows_and_indexes_from_checkpoint() {
+        let db = ScratchDb::new("checkpoint");
+        let mut storage = BitcaskStorage::new(&db.path()).unwrap();
+        storage.set_checkpoint_interval(0);
+        create_numbers(&mut storage);
+        storage.create_index("numbers", &["n"]).unwrap();
+        storage.create_range_index("numbers", "n").unwrap();
+        let offsets = storage
+            .insert_batch(
+                "numbers",
+                (1..=5).map(|n| Row::new(vec![Value::Integer(n)])).collect(),
             )
-        })?;
+            .unwrap();
+        storage.delete("numbers", offsets[1]).unwrap();
+        storage.checkpoint().unwrap();
+
+        // Written after the checkpoint, so replayed on open
+        storage
+            .insert("numbers", Row::new(vec![Value::Integer(6)]))
+            .unwrap();
+        storage.delete("numbers", offsets[3]).unwrap();
+        drop(storage);
+        assert!(Path::new(&format!("{}.checkpoint", db.path())).exists());
+
+        let storage = BitcaskStorage::open(&db.path()).unwrap();
+        let expected = [1, 3, 5, 6].map(Value::Integer);
+        assert_eq!(numbers(&storage), expected);
+        assert_eq!(storage.row_count("numbers"), Some(4));
+
+        // Deleted rows stay out of the indexes rebuilt on open
+        for n in 1..=6 {
+            let rows = storage
+                .index_lookup("numbers", "n", &Value::Integer(n))
+                .unwrap();
+            let expected_rows = usize::from(expected.contains(&Value::Integer(n)));
+            assert_eq!(rows.len(), expected_rows, "hash index lookup of {}", n);
+        }
+        let range = storage
+            .range_lookup_with_offsets(
+                "numbers",
+                "n",
+                Bound::Included(Value::Integer(2)),
+                Bound::Unbounded,
+            )
+            .unwrap();
+        let range: Vec<Value> = range
+            .into_iter()
+            .map(|(_, row)| row.values[0].clone())
+            .collect();
+        assert_eq!(range, [3, 5, 6].map(Value::Integer));
+    }
 
-        Ok(row)
+    #[test]
+    fn short_read_reports_record_offset() {
+        // A tombstone whose row offset is missing
+        let mut bytes = vec![TOMBSTONE_MARKER];
+        write_name(&mut bytes, "numbers").unwrap();
+        let bounds = RecordBounds {
+            end: u64::MAX,
+            max_record_size: DEFAULT_MAX_RECORD_SIZE,
+        };
+
+        let Err(e) = read_record(&mut bytes.as_slice(), 42, bounds) else {
+            panic!("expected a torn record");
+        };
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert!(is_torn(&e));
+        assert!(e.to_string().contains("offset 42"), "{}", e);
     }
 
-    /// Get table schema
-    pub fn get_schema(&self, table_name: &str) -> Option<&TableSchema> {
-        self.tables.get(table_name).map(|(schema, _)| schema)
+    #[test]
+    fn record_length_counts_checksum() {
+        // A row body that fits only if its 4-byte checksum is left out
+        let bounds = RecordBounds {
+            end: 100,
+            max_record_size: DEFAULT_MAX_RECORD_SIZE,
+        };
+        assert!(check_record_length(86, 0, 10, bounds).is_ok());
+        let e = check_record_length(87, 0, 10, bounds).unwrap_err();
+        assert!(is_torn(&e));
     }
 }
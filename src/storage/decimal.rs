@@ -0,0 +1,195 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt;
+
+/// Most digits a DECIMAL can hold, as the digits are kept in an i128
+pub const MAX_PRECISION: u8 = 38;
+
+/// An exact decimal number: `units` scaled down by 10^`scale`, so 19.90 at
+/// scale 2 is 1990 units. Values with different scales compare by their
+/// numeric value, so 1.5 equals 1.50.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Decimal {
+    units: i128,
+    scale: u8,
+}
+
+impl Decimal {
+    /// The number `units` × 10^-`scale`
+    pub fn new(units: i128, scale: u8) -> Self {
+        Decimal { units, scale }
+    }
+
+    pub fn from_i64(i: i64) -> Self {
+        Decimal::new(i as i128, 0)
+    }
+
+    /// The closest decimal to a float, with as many digits as it takes to
+    /// tell the float apart from its neighbours, so 0.1 becomes exactly 0.1.
+    /// Returns `None` for infinities, NaN and floats too large to hold.
+    pub fn from_f64(x: f64) -> Option<Self> {
+        if !x.is_finite() {
+            return None;
+        }
+        Decimal::parse(&x.to_string())
+    }
+
+    /// Parse plain decimal notation: an optional sign, digits and an optional
+    /// fractional part, such as `-12.50`. Returns `None` for anything else,
+    /// including numbers with more than `MAX_PRECISION` digits.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let (negative, digits) = match s.as_bytes().first()? {
+            b'-' => (true, &s[1..]),
+            b'+' => (false, &s[1..]),
+            _ => (false, s),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        if whole.is_empty() && fraction.is_empty()
+            || !whole
+                .bytes()
+                .chain(fraction.bytes())
+                .all(|b| b.is_ascii_digit())
+            || fraction.len() > MAX_PRECISION as usize
+        {
+            return None;
+        }
+
+        let mut units: i128 = 0;
+        for b in whole.bytes().chain(fraction.bytes()) {
+            units = units.checked_mul(10)?.checked_add((b - b'0') as i128)?;
+        }
+        let decimal = Decimal::new(if negative { -units } else { units }, fraction.len() as u8);
+        (decimal.digits() <= MAX_PRECISION as u32).then_some(decimal)
+    }
+
+    pub fn scale(self) -> u8 {
+        self.scale
+    }
+
+    /// Number of digits, before and after the point, e.g. 4 for 19.90
+    pub fn digits(self) -> u32 {
+        self.units
+            .unsigned_abs()
+            .checked_ilog10()
+            .map_or(1, |log| log + 1)
+    }
+
+    /// Round or extend to `scale` digits after the point. Dropped digits
+    /// round half away from zero, so 2.345 becomes 2.35 and -2.345 becomes
+    /// -2.35 at scale 2. Returns `None` if the result doesn't fit.
+    pub fn rescale(self, scale: u8) -> Option<Self> {
+        match scale.cmp(&self.scale) {
+            Ordering::Equal => Some(self),
+            Ordering::Greater => {
+                let factor = pow10(scale - self.scale)?;
+                Some(Decimal::new(self.units.checked_mul(factor)?, scale))
+            }
+            Ordering::Less => {
+                let factor = pow10(self.scale - scale)?;
+                let quotient = self.units / factor;
+                let remainder = self.units % factor;
+                // Round away from zero when the dropped digits are at least half
+                let round = if remainder.unsigned_abs() * 2 >= factor.unsigned_abs() {
+                    self.units.signum()
+                } else {
+                    0
+                };
+                Some(Decimal::new(quotient + round, scale))
+            }
+        }
+    }
+
+    pub fn checked_neg(self) -> Option<Self> {
+        Some(Decimal::new(self.units.checked_neg()?, self.scale))
+    }
+
+    /// Exact sum, at the larger of the two scales
+    pub fn checked_add(self, other: Decimal) -> Option<Self> {
+        let (a, b, scale) = self.align(other)?;
+        Some(Decimal::new(a.checked_add(b)?, scale))
+    }
+
+    /// Exact difference, at the larger of the two scales
+    pub fn checked_sub(self, other: Decimal) -> Option<Self> {
+        let (a, b, scale) = self.align(other)?;
+        Some(Decimal::new(a.checked_sub(b)?, scale))
+    }
+
+    /// Exact product, whose scale is the sum of the two scales
+    pub fn checked_mul(self, other: Decimal) -> Option<Self> {
+        let scale = self.scale.checked_add(other.scale)?;
+        if scale > MAX_PRECISION {
+            return None;
+        }
+        Some(Decimal::new(self.units.checked_mul(other.units)?, scale))
+    }
+
+    /// The nearest float, for comparing with and computing on Floats
+    pub fn to_f64(self) -> f64 {
+        // Parsing the digits rounds once, where dividing by a power of ten
+        // could round twice
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+
+    /// Both values' units at their larger scale
+    fn align(self, other: Decimal) -> Option<(i128, i128, u8)> {
+        let scale = self.scale.max(other.scale);
+        Some((
+            self.rescale(scale)?.units,
+            other.rescale(scale)?.units,
+            scale,
+        ))
+    }
+}
+
+/// 10^exp, or `None` if it doesn't fit in an i128
+fn pow10(exp: u8) -> Option<i128> {
+    10i128.checked_pow(exp as u32)
+}
+
+// Compare the whole parts, then the fractional parts at a common scale. Both
+// parts share the number's sign, and a fraction of at most MAX_PRECISION
+// digits always fits, so no scaling can overflow.
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let scale = self.scale.max(other.scale);
+        let parts = |d: &Decimal| {
+            let factor = 10i128.pow(d.scale as u32);
+            let fraction = d.units % factor * 10i128.pow((scale - d.scale) as u32);
+            (d.units / factor, fraction)
+        };
+        parts(self).cmp(&parts(other))
+    }
+}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+// Display with exactly `scale` digits after the point, e.g. 19.90
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = self.units.unsigned_abs().to_string();
+        let scale = self.scale as usize;
+        let sign = if self.units < 0 { "-" } else { "" };
+        let text = if scale == 0 {
+            format!("{}{}", sign, digits)
+        } else {
+            let digits = format!("{:0>width$}", digits, width = scale + 1);
+            let (whole, fraction) = digits.split_at(digits.len() - scale);
+            format!("{}{}.{}", sign, whole, fraction)
+        };
+        f.pad(&text)
+    }
+}
@@ -0,0 +1,307 @@
+use super::bitcask::{BitcaskStorage, ScanIter, StorageStats};
+use super::types::{IndexStats, Row, TableSchema, Value};
+use crate::error::DbResult;
+use std::ops::Bound;
+
+/// What the query executor needs from a storage engine: tables and their
+/// rows, indexes over them, and transactions. Rows are identified by an
+/// offset the engine hands out when it stores them, which stays valid until
+/// the row is deleted.
+///
+/// `BitcaskStorage` implements this both on disk and in memory
+/// (`BitcaskStorage::in_memory`); another engine, or a mock for testing the
+/// executor, only has to provide these methods.
+pub trait Storage {
+    /// Iterator over a table's rows, paired with their offsets
+    type Scan<'a>: Iterator<Item = DbResult<(u64, Row)>>
+    where
+        Self: 'a;
+
+    /// Create a new table
+    fn create_table(&mut self, schema: TableSchema) -> DbResult<()>;
+
+    /// Drop a table along with its rows and indexes
+    fn drop_table(&mut self, table_name: &str) -> DbResult<()>;
+
+    /// Delete every row of a table, keeping its schema and indexes
+    fn truncate(&mut self, table_name: &str) -> DbResult<()>;
+
+    /// Get table schema
+    fn get_schema(&self, table_name: &str) -> Option<&TableSchema>;
+
+    /// Schemas of every table, sorted by name
+    fn list_tables(&self) -> Vec<&TableSchema>;
+
+    /// Get the number of live rows in a table without scanning it
+    fn row_count(&self, table_name: &str) -> Option<u64>;
+
+    /// Give each row with a NULL in the table's AUTO_INCREMENT column the
+    /// next number in sequence, returning the column's value in every row
+    fn assign_ids(&mut self, table_name: &str, rows: &mut [Row]) -> DbResult<Vec<i64>>;
+
+    /// Insert many rows into a table, all or none of them. Returns the
+    /// offsets of the new rows, in order.
+    fn insert_batch(&mut self, table_name: &str, rows: Vec<Row>) -> DbResult<Vec<u64>>;
+
+    /// Delete the row stored at `offset`
+    fn delete(&mut self, table_name: &str, offset: u64) -> DbResult<()>;
+
+    /// Iterate over the rows of a table
+    fn scan_iter(&self, table_name: &str) -> DbResult<Self::Scan<'_>>;
+
+    /// Iterate over the rows of a table, keeping only the columns at
+    /// `col_indices`, in that order
+    fn scan_columns_iter(
+        &self,
+        table_name: &str,
+        col_indices: &[usize],
+    ) -> DbResult<Self::Scan<'_>>;
+
+    /// Fetch the row whose primary key is `key`, paired with its offset
+    fn get_by_primary_key_with_offset(
+        &self,
+        table_name: &str,
+        key: &Value,
+    ) -> DbResult<Option<(u64, Row)>>;
+
+    /// Create an index on one column, or a composite index on several
+    fn create_index(&mut self, table_name: &str, column_names: &[&str]) -> DbResult<()>;
+
+    /// Create an index on a text column whose lookups ignore case
+    fn create_case_insensitive_index(
+        &mut self,
+        table_name: &str,
+        column_name: &str,
+    ) -> DbResult<()>;
+
+    /// Create an ordered index on a column, usable for range queries
+    fn create_range_index(&mut self, table_name: &str, column_name: &str) -> DbResult<()>;
+
+    /// Drop every index on a column
+    fn drop_index(&mut self, table_name: &str, column_name: &str) -> DbResult<()>;
+
+    /// Whether a column has a hash index, which answers equality lookups
+    fn has_hash_index(&self, table_name: &str, column_name: &str) -> bool;
+
+    /// Whether a column has a range index
+    fn has_range_index(&self, table_name: &str, column_name: &str) -> bool;
+
+    /// The columns of a table that have an index of their own, in name order
+    fn list_indexes(&self, table_name: &str) -> Vec<&str>;
+
+    /// The columns of each composite index on a table, in index order
+    fn composite_index_columns(&self, table_name: &str) -> Vec<&[String]>;
+
+    /// Lookup rows using an index, paired with their offsets
+    fn index_lookup_with_offsets(
+        &self,
+        table_name: &str,
+        column_name: &str,
+        value: &Value,
+    ) -> DbResult<Vec<(u64, Row)>>;
+
+    /// Lookup rows whose column value falls within the bounds using a range
+    /// index, paired with their offsets
+    fn range_lookup_with_offsets(
+        &self,
+        table_name: &str,
+        column_name: &str,
+        lower: Bound<Value>,
+        upper: Bound<Value>,
+    ) -> DbResult<Vec<(u64, Row)>>;
+
+    /// Lookup rows using a composite index, paired with their offsets.
+    /// `values` are given in the same order as `column_names`.
+    fn composite_lookup_with_offsets(
+        &self,
+        table_name: &str,
+        column_names: &[String],
+        values: &[Value],
+    ) -> DbResult<Vec<(u64, Row)>>;
+
+    /// Statistics of the hash index on a column, if it has one
+    fn index_stats(&self, table_name: &str, column_name: &str) -> Option<IndexStats>;
+
+    /// Statistics of the range index on a column, if it has one
+    fn range_index_stats(&self, table_name: &str, column_name: &str) -> Option<IndexStats>;
+
+    /// Statistics of the composite index on exactly these columns, if there is one
+    fn composite_index_stats(
+        &self,
+        table_name: &str,
+        column_names: &[String],
+    ) -> Option<IndexStats>;
+
+    /// Start a transaction
+    fn begin_transaction(&mut self) -> DbResult<()>;
+
+    /// Commit the open transaction
+    fn commit(&mut self) -> DbResult<()>;
+
+    /// Roll back the open transaction, undoing its inserts and deletes
+    fn rollback(&mut self) -> DbResult<()>;
+
+    /// Push any buffered writes to the underlying storage
+    fn flush(&mut self) -> DbResult<()>;
+
+    /// Report how big the database is and how much of it is stale
+    fn stats(&self) -> DbResult<StorageStats>;
+}
+
+// Each method forwards to the inherent method of the same name, which keeps
+// working for callers that don't import the trait
+impl Storage for BitcaskStorage {
+    type Scan<'a> = ScanIter<'a>;
+
+    fn create_table(&mut self, schema: TableSchema) -> DbResult<()> {
+        BitcaskStorage::create_table(self, schema)
+    }
+
+    fn drop_table(&mut self, table_name: &str) -> DbResult<()> {
+        BitcaskStorage::drop_table(self, table_name)
+    }
+
+    fn truncate(&mut self, table_name: &str) -> DbResult<()> {
+        BitcaskStorage::truncate(self, table_name)
+    }
+
+    fn get_schema(&self, table_name: &str) -> Option<&TableSchema> {
+        BitcaskStorage::get_schema(self, table_name)
+    }
+
+    fn list_tables(&self) -> Vec<&TableSchema> {
+        BitcaskStorage::list_tables(self)
+    }
+
+    fn row_count(&self, table_name: &str) -> Option<u64> {
+        BitcaskStorage::row_count(self, table_name)
+    }
+
+    fn assign_ids(&mut self, table_name: &str, rows: &mut [Row]) -> DbResult<Vec<i64>> {
+        BitcaskStorage::assign_ids(self, table_name, rows)
+    }
+
+    fn insert_batch(&mut self, table_name: &str, rows: Vec<Row>) -> DbResult<Vec<u64>> {
+        BitcaskStorage::insert_batch(self, table_name, rows)
+    }
+
+    fn delete(&mut self, table_name: &str, offset: u64) -> DbResult<()> {
+        BitcaskStorage::delete(self, table_name, offset)
+    }
+
+    fn scan_iter(&self, table_name: &str) -> DbResult<ScanIter<'_>> {
+        BitcaskStorage::scan_iter(self, table_name)
+    }
+
+    fn scan_columns_iter(&self, table_name: &str, col_indices: &[usize]) -> DbResult<ScanIter<'_>> {
+        BitcaskStorage::scan_columns_iter(self, table_name, col_indices)
+    }
+
+    fn get_by_primary_key_with_offset(
+        &self,
+        table_name: &str,
+        key: &Value,
+    ) -> DbResult<Option<(u64, Row)>> {
+        BitcaskStorage::get_by_primary_key_with_offset(self, table_name, key)
+    }
+
+    fn create_index(&mut self, table_name: &str, column_names: &[&str]) -> DbResult<()> {
+        BitcaskStorage::create_index(self, table_name, column_names)
+    }
+
+    fn create_case_insensitive_index(
+        &mut self,
+        table_name: &str,
+        column_name: &str,
+    ) -> DbResult<()> {
+        BitcaskStorage::create_case_insensitive_index(self, table_name, column_name)
+    }
+
+    fn create_range_index(&mut self, table_name: &str, column_name: &str) -> DbResult<()> {
+        BitcaskStorage::create_range_index(self, table_name, column_name)
+    }
+
+    fn drop_index(&mut self, table_name: &str, column_name: &str) -> DbResult<()> {
+        BitcaskStorage::drop_index(self, table_name, column_name)
+    }
+
+    fn has_hash_index(&self, table_name: &str, column_name: &str) -> bool {
+        BitcaskStorage::has_hash_index(self, table_name, column_name)
+    }
+
+    fn has_range_index(&self, table_name: &str, column_name: &str) -> bool {
+        BitcaskStorage::has_range_index(self, table_name, column_name)
+    }
+
+    fn list_indexes(&self, table_name: &str) -> Vec<&str> {
+        BitcaskStorage::list_indexes(self, table_name)
+    }
+
+    fn composite_index_columns(&self, table_name: &str) -> Vec<&[String]> {
+        BitcaskStorage::composite_index_columns(self, table_name)
+    }
+
+    fn index_lookup_with_offsets(
+        &self,
+        table_name: &str,
+        column_name: &str,
+        value: &Value,
+    ) -> DbResult<Vec<(u64, Row)>> {
+        BitcaskStorage::index_lookup_with_offsets(self, table_name, column_name, value)
+    }
+
+    fn range_lookup_with_offsets(
+        &self,
+        table_name: &str,
+        column_name: &str,
+        lower: Bound<Value>,
+        upper: Bound<Value>,
+    ) -> DbResult<Vec<(u64, Row)>> {
+        BitcaskStorage::range_lookup_with_offsets(self, table_name, column_name, lower, upper)
+    }
+
+    fn composite_lookup_with_offsets(
+        &self,
+        table_name: &str,
+        column_names: &[String],
+        values: &[Value],
+    ) -> DbResult<Vec<(u64, Row)>> {
+        BitcaskStorage::composite_lookup_with_offsets(self, table_name, column_names, values)
+    }
+
+    fn index_stats(&self, table_name: &str, column_name: &str) -> Option<IndexStats> {
+        BitcaskStorage::index_stats(self, table_name, column_name)
+    }
+
+    fn range_index_stats(&self, table_name: &str, column_name: &str) -> Option<IndexStats> {
+        BitcaskStorage::range_index_stats(self, table_name, column_name)
+    }
+
+    fn composite_index_stats(
+        &self,
+        table_name: &str,
+        column_names: &[String],
+    ) -> Option<IndexStats> {
+        BitcaskStorage::composite_index_stats(self, table_name, column_names)
+    }
+
+    fn begin_transaction(&mut self) -> DbResult<()> {
+        BitcaskStorage::begin_transaction(self)
+    }
+
+    fn commit(&mut self) -> DbResult<()> {
+        BitcaskStorage::commit(self)
+    }
+
+    fn rollback(&mut self) -> DbResult<()> {
+        BitcaskStorage::rollback(self)
+    }
+
+    fn flush(&mut self) -> DbResult<()> {
+        BitcaskStorage::flush(self)
+    }
+
+    fn stats(&self) -> DbResult<StorageStats> {
+        BitcaskStorage::stats(self)
+    }
+}
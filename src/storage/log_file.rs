@@ -0,0 +1,85 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::sync::{Arc, RwLock};
+
+/// The file operations the storage engine needs, so the same engine code
+/// runs on files on disk or on buffers in memory.
+/// Writes always append to the end of the file.
+pub trait LogFile: Write + Send + Sync {
+    /// Read into `buf` starting at `offset`, returning the number of bytes read
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize>;
+
+    /// Current length of the file in bytes
+    fn size(&self) -> io::Result<u64>;
+
+    /// Force everything written so far to durable storage
+    fn sync(&self) -> io::Result<()>;
+
+    /// Open a second handle on the same file
+    fn try_clone(&self) -> io::Result<Box<dyn LogFile>>;
+}
+
+/// Files must be opened in append mode
+impl LogFile for File {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        #[cfg(unix)]
+        let read = std::os::unix::fs::FileExt::read_at(self, buf, offset)?;
+        #[cfg(windows)]
+        let read = std::os::windows::fs::FileExt::seek_read(self, buf, offset)?;
+        Ok(read)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        self.sync_data()
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn LogFile>> {
+        Ok(Box::new(File::try_clone(self)?))
+    }
+}
+
+/// A growable in-memory buffer standing in for a file.
+/// Clones share the same buffer, like handles on the same file.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryFile {
+    bytes: Arc<RwLock<Vec<u8>>>,
+}
+
+impl Write for MemoryFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // A panic can't leave the buffer half-updated, so a poisoned lock is safe to use
+        let mut bytes = self.bytes.write().unwrap_or_else(|e| e.into_inner());
+        bytes.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl LogFile for MemoryFile {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let bytes = self.bytes.read().unwrap_or_else(|e| e.into_inner());
+        let available = bytes.get(offset as usize..).unwrap_or_default();
+        let read = available.len().min(buf.len());
+        buf[..read].copy_from_slice(&available[..read]);
+        Ok(read)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.bytes.read().unwrap_or_else(|e| e.into_inner()).len() as u64)
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn LogFile>> {
+        Ok(Box::new(self.clone()))
+    }
+}
@@ -1,5 +1,13 @@
 pub mod bitcask;
+pub mod checksum;
+pub mod decimal;
+pub mod engine;
+pub mod log_file;
+pub mod timestamp;
 pub mod types;
 
-pub use bitcask::BitcaskStorage;
-pub use types::{Column, ColumnType, Row, TableSchema, Value};
+pub use bitcask::{
+    BitcaskStorage, DurabilityMode, LoadReport, RecoveryReport, ScanIter, StorageStats,
+};
+pub use engine::Storage;
+pub use types::{Column, ColumnType, IndexStats, IntWidth, Row, TableSchema, Value};
@@ -1,5 +1,12 @@
+use super::decimal::Decimal;
+use super::timestamp::{format_timestamp, parse_timestamp};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use sqlparser::ast::Value as SqlValue;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Bound;
 
 // Represents the data type of a column
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -7,6 +14,49 @@ pub enum ColumnType {
     Integer,
     Text,
     Float,
+    // Dates and date-times, stored as seconds since the Unix epoch (UTC)
+    Timestamp,
+    // Exact numbers of up to `precision` digits, `scale` of them after the
+    // decimal point, e.g. DECIMAL(10,2) for 12345678.90
+    Decimal { precision: u8, scale: u8 },
+}
+
+// The declared width of an Integer column, which bounds the values it holds.
+// Values are stored as i64 whatever the width.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum IntWidth {
+    // SMALLINT: 16 bits
+    Small,
+    // INT / INTEGER: 32 bits
+    Int,
+    // BIGINT: 64 bits
+    #[default]
+    Big,
+}
+
+impl IntWidth {
+    // Smallest and largest values a column of this width accepts
+    pub fn bounds(self) -> (i64, i64) {
+        match self {
+            IntWidth::Small => (i16::MIN as i64, i16::MAX as i64),
+            IntWidth::Int => (i32::MIN as i64, i32::MAX as i64),
+            IntWidth::Big => (i64::MIN, i64::MAX),
+        }
+    }
+
+    pub fn contains(self, value: i64) -> bool {
+        let (min, max) = self.bounds();
+        (min..=max).contains(&value)
+    }
+
+    // SQL name of the type
+    pub fn sql_name(self) -> &'static str {
+        match self {
+            IntWidth::Small => "SMALLINT",
+            IntWidth::Int => "INTEGER",
+            IntWidth::Big => "BIGINT",
+        }
+    }
 }
 
 // Represents a column definition in a table
@@ -14,39 +64,313 @@ pub enum ColumnType {
 pub struct Column {
     pub name: String,
     pub column_type: ColumnType,
+    // Range of an Integer column's values; ignored for other types
+    pub int_width: IntWidth,
+    // Whether the column accepts NULL (false for NOT NULL columns)
+    pub nullable: bool,
+    // Whether every non-NULL value in the column must be distinct
+    pub unique: bool,
+    // Whether the column is the table's primary key, which is also UNIQUE and NOT NULL
+    pub primary_key: bool,
+    // Whether an INSERT that leaves the column NULL gets the next number in
+    // sequence (AUTO_INCREMENT or SERIAL); only Integer columns can be
+    pub auto_increment: bool,
+    // Value used when an INSERT doesn't provide one
+    pub default: Option<Value>,
+}
+
+impl Column {
+    // SQL name of the column's type, e.g. SMALLINT, TEXT or DECIMAL(10,2)
+    pub fn type_name(&self) -> String {
+        match self.column_type {
+            ColumnType::Integer => self.int_width.sql_name().to_string(),
+            ColumnType::Text => "TEXT".to_string(),
+            ColumnType::Float => "FLOAT".to_string(),
+            ColumnType::Timestamp => "TIMESTAMP".to_string(),
+            ColumnType::Decimal { precision, scale } => {
+                format!("DECIMAL({},{})", precision, scale)
+            }
+        }
+    }
+
+    // The default value as a SQL literal, e.g. 'n/a' or 0
+    pub fn default_sql(&self) -> Option<String> {
+        self.default.as_ref().map(|value| match value {
+            Value::Text(s) => format!("'{}'", s.replace('\'', "''")),
+            Value::Timestamp(t) => format!("'{}'", format_timestamp(*t)),
+            Value::Null => "NULL".to_string(),
+            value => value.to_string(),
+        })
+    }
+}
+
+// Display a column as its definition in CREATE TABLE, e.g. `id INTEGER PRIMARY KEY`
+impl fmt::Display for Column {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.name, self.type_name())?;
+        if self.primary_key {
+            write!(f, " PRIMARY KEY")?;
+        } else {
+            if !self.nullable {
+                write!(f, " NOT NULL")?;
+            }
+            if self.unique {
+                write!(f, " UNIQUE")?;
+            }
+        }
+        if self.auto_increment {
+            write!(f, " AUTO_INCREMENT")?;
+        }
+        match self.default_sql() {
+            Some(default) => write!(f, " DEFAULT {}", default),
+            None => Ok(()),
+        }
+    }
 }
 
 // Represents a single value in a row
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Value {
     Integer(i64),
     Text(String),
     Float(f64),
     Null,
+    // Seconds since the Unix epoch (UTC)
+    Timestamp(i64),
+    // An exact decimal, displayed with all of its scale's digits
+    Decimal(Decimal),
 }
 
-impl Value {
-    // Convert value to string for display
-    pub fn to_string(&self) -> String {
+// Display values for output: Text is unquoted, and Floats always keep a
+// decimal point (or exponent) so 2.0 can't be mistaken for the Integer 2
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Pad rather than write so width and alignment flags are honoured
         match self {
-            Value::Integer(i) => i.to_string(),
-            Value::Text(s) => s.clone(),
-            Value::Float(f) => f.to_string(),
-            Value::Null => "Null".to_string(),
+            Value::Integer(i) => fmt::Display::fmt(i, f),
+            Value::Text(s) => f.pad(s),
+            Value::Float(x) => f.pad(&format!("{:?}", x)),
+            Value::Null => f.pad("Null"),
+            Value::Timestamp(t) => f.pad(&format_timestamp(*t)),
+            Value::Decimal(d) => fmt::Display::fmt(d, f),
         }
     }
+}
 
+impl Value {
     // Check if this value matches the column type
     pub fn matches_type(&self, col_type: &ColumnType) -> bool {
+        matches!(
+            (self, col_type),
+            (Value::Integer(_), ColumnType::Integer)
+                | (Value::Text(_), ColumnType::Text)
+                | (Value::Float(_), ColumnType::Float)
+                | (Value::Timestamp(_), ColumnType::Timestamp)
+                | (Value::Decimal(_), ColumnType::Decimal { .. })
+                | (Value::Null, _)
+        )
+    }
+
+    // Convert a value to the given column type where there is a natural
+    // conversion (a date string for a Timestamp column, an Integer for a Float
+    // column, any number for a Decimal column); otherwise return it
+    // unchanged. The executor applies this to literals before inserting them,
+    // while validate_row stays strict, so a Float for an Integer column is
+    // still rejected rather than truncated.
+    //
+    // Numbers for a Decimal column are rounded to its scale, half away from
+    // zero: 2.345 becomes 2.35 and -2.345 becomes -2.35 in a DECIMAL(10,2).
+    // A Float is first converted to the shortest decimal that reads back as
+    // the same float, so 0.1 is stored as exactly 0.10.
+    pub fn coerce_to(self, col_type: &ColumnType) -> Value {
         match (self, col_type) {
-            (Value::Integer(_), ColumnType::Integer) => true,
-            (Value::Text(_), ColumnType::Text) => true,
-            (Value::Float(_), ColumnType::Float) => true,
-            (Value::Null, _) => true,
-            _ => false,
+            (Value::Text(s), ColumnType::Timestamp) => match parse_timestamp(&s) {
+                Some(t) => Value::Timestamp(t),
+                None => Value::Text(s),
+            },
+            (Value::Integer(i), ColumnType::Float) => Value::Float(i as f64),
+            (Value::Decimal(d), ColumnType::Float) => Value::Float(d.to_f64()),
+            (value, ColumnType::Decimal { scale, .. }) => {
+                let decimal = match value {
+                    Value::Integer(i) => Some(Decimal::from_i64(i)),
+                    Value::Float(x) => Decimal::from_f64(x),
+                    Value::Decimal(d) => Some(d),
+                    _ => None,
+                };
+                match decimal.and_then(|d| d.rescale(*scale)) {
+                    Some(d) => Value::Decimal(d),
+                    None => value,
+                }
+            }
+            (value, _) => value,
+        }
+    }
+
+    // Convert a value so it can be compared with `other`, e.g. a date string
+    // compared against a Timestamp
+    pub fn coerce_to_match(self, other: &Value) -> Value {
+        match other {
+            Value::Timestamp(_) => self.coerce_to(&ColumnType::Timestamp),
+            _ => self,
         }
     }
 
+    // Convert a SQL literal to a value. Numbers with a decimal point become
+    // Floats, and booleans become the Integers 1 and 0, as there is no
+    // boolean type. Placeholders and other literals are not values.
+    pub fn from_sql(sql_value: &SqlValue) -> Result<Value, String> {
+        match sql_value {
+            SqlValue::Number(n, _) => {
+                if n.contains('.') {
+                    n.parse().map(Value::Float).map_err(|_| format!("Invalid float: {}", n))
+                } else {
+                    n.parse().map(Value::Integer).map_err(|_| format!("Invalid integer: {}", n))
+                }
+            }
+            SqlValue::SingleQuotedString(s) | SqlValue::DoubleQuotedString(s) => {
+                Ok(Value::Text(s.clone()))
+            }
+            SqlValue::Boolean(b) => Ok(Value::Integer(*b as i64)),
+            SqlValue::Null => Ok(Value::Null),
+            _ => Err(format!("Unsupported value type: {:?}", sql_value)),
+        }
+    }
+}
+
+// Values are totally ordered so they can be used as BTreeMap keys:
+// - Null sorts before every other value
+// - Integer and Float compare numerically (Integer(2) < Float(2.5),
+//   Integer(3) == Float(3.0)); integers beyond 2^53 lose precision
+// - Decimals compare exactly with Decimals and Integers (1.50 == 1.5 and
+//   2.00 == Integer(2)), and with Floats as the nearest float
+// - Text compares lexicographically by bytes and sorts after all numbers
+// - Timestamps compare chronologically and sort after Text
+// - NaN sorts above every other number, and all NaNs are equal whatever
+//   their sign or payload, so they group together in GROUP BY and DISTINCT
+// Predicates should check `is_comparable` first: the cross-type order is
+// only there to make the ordering total, not to give `5 < 'a'` a meaning.
+impl Ord for Value {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Value::Null, Value::Null) => Ordering::Equal,
+            (Value::Integer(a), Value::Integer(b)) => a.cmp(b),
+            (Value::Float(a), Value::Float(b)) => cmp_f64(*a, *b),
+            (Value::Integer(a), Value::Float(b)) => cmp_f64(*a as f64, *b),
+            (Value::Float(a), Value::Integer(b)) => cmp_f64(*a, *b as f64),
+            (Value::Decimal(a), Value::Decimal(b)) => a.cmp(b),
+            (Value::Decimal(a), Value::Integer(b)) => a.cmp(&Decimal::from_i64(*b)),
+            (Value::Integer(a), Value::Decimal(b)) => Decimal::from_i64(*a).cmp(b),
+            (Value::Decimal(a), Value::Float(b)) => cmp_f64(a.to_f64(), *b),
+            (Value::Float(a), Value::Decimal(b)) => cmp_f64(*a, b.to_f64()),
+            (Value::Text(a), Value::Text(b)) => a.cmp(b),
+            (Value::Timestamp(a), Value::Timestamp(b)) => a.cmp(b),
+            _ => self.type_rank().cmp(&other.type_rank()),
+        }
+    }
+}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Value {}
+
+// Hashing agrees with equality, so values can be HashMap and HashSet keys:
+// Integer, Float and Decimal hash by numeric value, so Integer(3),
+// Float(3.0) and Decimal(3.00) hash alike, -0.0 hashes like 0.0 and every
+// NaN hashes alike. Text hashes by its bytes and Null by its type alone.
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.type_rank().hash(state);
+        match self {
+            Value::Null => {}
+            Value::Integer(i) => hash_f64(*i as f64, state),
+            Value::Float(x) => hash_f64(*x, state),
+            Value::Text(s) => s.hash(state),
+            Value::Timestamp(t) => t.hash(state),
+            Value::Decimal(d) => hash_f64(d.to_f64(), state),
+        }
+    }
+}
+
+fn hash_f64<H: Hasher>(x: f64, state: &mut H) {
+    ordered_bits(x).hash(state);
+}
+
+impl Value {
+    // Whether two values can be meaningfully compared in a predicate: numbers
+    // with numbers, Text with Text and Timestamps with Timestamps.
+    // Nothing is comparable with Null.
+    pub fn is_comparable(&self, other: &Value) -> bool {
+        !matches!(self, Value::Null) && self.type_rank() == other.type_rank()
+    }
+
+    // Whether this value can be compared with the values of a column of the
+    // given type, by the same rule as `is_comparable`
+    pub fn is_comparable_with_type(&self, col_type: &ColumnType) -> bool {
+        let rank = match col_type {
+            ColumnType::Integer | ColumnType::Float | ColumnType::Decimal { .. } => 1,
+            ColumnType::Text => 2,
+            ColumnType::Timestamp => 3,
+        };
+        !matches!(self, Value::Null) && self.type_rank() == rank
+    }
+
+    // Position of this value's type in the cross-type ordering
+    fn type_rank(&self) -> u8 {
+        match self {
+            Value::Null => 0,
+            Value::Integer(_) | Value::Float(_) | Value::Decimal(_) => 1,
+            Value::Text(_) => 2,
+            Value::Timestamp(_) => 3,
+        }
+    }
+
+    // Bytes this value occupies, counting the text it owns on the heap
+    fn memory_usage(&self) -> usize {
+        let heap = match self {
+            Value::Text(s) => s.capacity(),
+            _ => 0,
+        };
+        std::mem::size_of::<Value>() + heap
+    }
+}
+
+// Compare floats numerically, with every NaN equal and above all numbers
+fn cmp_f64(a: f64, b: f64) -> Ordering {
+    ordered_bits(a).cmp(&ordered_bits(b))
+}
+
+// Encode a float as a u64 whose unsigned order is the float's numeric order,
+// which is what range index keys on float columns are sorted by. IEEE 754
+// bits already sort like unsigned integers for positive floats; negative
+// floats sort backwards, so their bits are all flipped, and positive floats
+// get only the sign bit flipped to move them above the negatives:
+//   -inf < -1.5 < -0.0 == 0.0 < 1.5 < inf < NaN
+// -0.0 is folded into 0.0 and every NaN into one canonical NaN first, so
+// equal values encode alike and all NaNs sort together above +inf.
+fn ordered_bits(x: f64) -> u64 {
+    let x = if x == 0.0 {
+        0.0
+    } else if x.is_nan() {
+        f64::NAN
+    } else {
+        x
+    };
+    let bits = x.to_bits();
+    if bits >> 63 == 1 {
+        !bits
+    } else {
+        bits | (1 << 63)
+    }
 }
 
 // Represent a single row of data
@@ -68,6 +392,40 @@ impl Row {
     pub fn get_mut(&mut self, index: usize) -> Option<&mut Value> {
         self.values.get_mut(index)
     }
+
+    // Typed getters: each returns None if the index is out of range or the
+    // value is NULL or of another type
+
+    pub fn get_int(&self, index: usize) -> Option<i64> {
+        match self.get(index)? {
+            Value::Integer(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    pub fn get_text(&self, index: usize) -> Option<&str> {
+        match self.get(index)? {
+            Value::Text(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn get_float(&self, index: usize) -> Option<f64> {
+        match self.get(index)? {
+            Value::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    // There is no boolean column type, so booleans are stored as the
+    // Integers 0 and 1; any other value is not a boolean
+    pub fn get_bool(&self, index: usize) -> Option<bool> {
+        match self.get(index)? {
+            Value::Integer(0) => Some(false),
+            Value::Integer(1) => Some(true),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -91,6 +449,16 @@ impl TableSchema {
         self.columns.iter().find(|c| c.name == name)
     }
 
+    // Index of the primary key column, if the table has one
+    pub fn primary_key(&self) -> Option<usize> {
+        self.columns.iter().position(|c| c.primary_key)
+    }
+
+    // Index of the AUTO_INCREMENT column, if the table has one
+    pub fn auto_increment_column(&self) -> Option<usize> {
+        self.columns.iter().position(|c| c.auto_increment)
+    }
+
     // Validate that a row matches this schema
     pub fn validate_row(&self, row: &Row) -> Result<(), String> {
         if row.values.len() != self.columns.len() {
@@ -102,24 +470,90 @@ impl TableSchema {
         }
 
         for (i, (value, column)) in row.values.iter().zip(self.columns.iter()).enumerate() {
+            if !column.nullable && matches!(value, Value::Null) {
+                return Err(format!("Column {} (index {}) cannot be NULL", column.name, i));
+            }
+
             if !value.matches_type(&column.column_type) {
                 return Err(format!(
                     "Column {} (index {}) expects {:?} but got {:?}",
                     column.name, i, column.column_type, value
                 ));
             }
+
+            if let Value::Integer(int) = value
+                && !column.int_width.contains(*int)
+            {
+                let (min, max) = column.int_width.bounds();
+                return Err(format!(
+                    "Column {} (index {}) is {}, which can't hold {} (range {} to {})",
+                    column.name,
+                    i,
+                    column.int_width.sql_name(),
+                    int,
+                    min,
+                    max
+                ));
+            }
+
+            if let (Value::Decimal(decimal), ColumnType::Decimal { precision, .. }) =
+                (value, &column.column_type)
+                && decimal.digits() > *precision as u32
+            {
+                return Err(format!(
+                    "Column {} (index {}) is {}, which can't hold {}",
+                    column.name,
+                    i,
+                    column.type_name(),
+                    decimal
+                ));
+            }
         }
 
         Ok(())
     }
 }
 
+// How selective an index is, used to choose between indexes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IndexStats {
+    // Number of distinct keys
+    pub cardinality: usize,
+    // Average number of rows sharing a key; 0 for an empty index
+    pub avg_rows_per_key: f64,
+}
+
+impl IndexStats {
+    fn from_map<'a>(offsets: impl ExactSizeIterator<Item = &'a Vec<u64>>) -> Self {
+        let cardinality = offsets.len();
+        let rows: usize = offsets.map(Vec::len).sum();
+        let avg_rows_per_key = if cardinality == 0 {
+            0.0
+        } else {
+            rows as f64 / cardinality as f64
+        };
+        IndexStats {
+            cardinality,
+            avg_rows_per_key,
+        }
+    }
+}
+
+// Approximate bytes held by one index entry's list of row offsets
+fn offsets_memory(offsets: &Vec<u64>) -> usize {
+    std::mem::size_of::<Vec<u64>>() + offsets.capacity() * std::mem::size_of::<u64>()
+}
+
 // Represents an in-memory index for fast lookups
 #[derive(Debug, Clone)]
 pub struct Index {
     pub table_name: String,
     pub column_name: String,
     pub column_index: usize,
+    // Whether text keys are folded to lowercase. This changes what equality
+    // means for lookups through the index: `= 'Bob'` finds a stored 'bob',
+    // although the same condition evaluated without the index would not.
+    pub case_insensitive: bool,
     // Maps value -> list of row offsets in the file
     pub index_map: HashMap<String, Vec<u64>>,
 }
@@ -130,17 +564,322 @@ impl Index {
             table_name,
             column_name,
             column_index,
+            case_insensitive: false,
             index_map: HashMap::new(),
         }
     }
 
     pub fn insert(&mut self, value: &Value, offset:u64) {
-        let key = value.to_string();
-        self.index_map.entry(key).or_insert_with(Vec::new).push(offset);
+        let key = self.key(value);
+        self.index_map.entry(key).or_default().push(offset);
+    }
+
+    // Remove a single row offset, dropping the key once it has no rows left
+    pub fn remove(&mut self, value: &Value, offset: u64) {
+        let key = self.key(value);
+        if let Some(offsets) = self.index_map.get_mut(&key) {
+            offsets.retain(|&o| o != offset);
+            if offsets.is_empty() {
+                self.index_map.remove(&key);
+            }
+        }
+    }
+
+    // Remove every listed offset, dropping keys left with no rows.
+    // Returns how many offsets were removed.
+    pub fn remove_offsets(&mut self, offsets: &HashSet<u64>) -> usize {
+        let mut removed = 0;
+        self.index_map.retain(|_, key_offsets| {
+            let before = key_offsets.len();
+            key_offsets.retain(|offset| !offsets.contains(offset));
+            removed += before - key_offsets.len();
+            !key_offsets.is_empty()
+        });
+        removed
     }
 
     pub fn lookup(&self, value: &Value) -> Option<&Vec<u64>> {
-        let key = value.to_string();
+        let key = self.key(value);
         self.index_map.get(&key)
     }
+
+    // Number of distinct keys
+    pub fn cardinality(&self) -> usize {
+        self.index_map.len()
+    }
+
+    // Average number of rows an equality lookup finds
+    pub fn avg_rows_per_key(&self) -> f64 {
+        self.stats().avg_rows_per_key
+    }
+
+    pub fn stats(&self) -> IndexStats {
+        IndexStats::from_map(self.index_map.values())
+    }
+
+    // Approximate bytes held by the keys and offsets, not counting the
+    // map's own bookkeeping
+    pub fn memory_usage(&self) -> usize {
+        self.index_map
+            .iter()
+            .map(|(key, offsets)| {
+                std::mem::size_of::<String>() + key.capacity() + offsets_memory(offsets)
+            })
+            .sum()
+    }
+
+    // Hash key for a value. Integral floats share their integer's key so
+    // that `price = 2` finds a stored 2.0, decimals drop trailing zeros so
+    // that 2.50 shares 2.5's key, and text is lowercased when the index is
+    // case-insensitive.
+    fn key(&self, value: &Value) -> String {
+        match value {
+            Value::Float(f) if f.fract() == 0.0 && f.abs() < 1e15 => (*f as i64).to_string(),
+            Value::Decimal(d) if d.scale() > 0 => {
+                let digits = d.to_string();
+                digits.trim_end_matches('0').trim_end_matches('.').to_string()
+            }
+            Value::Text(s) if self.case_insensitive => s.to_lowercase(),
+            _ => value.to_string(),
+        }
+    }
+}
+
+// Represents an ordered in-memory index supporting range lookups
+#[derive(Debug, Clone)]
+pub struct RangeIndex {
+    pub table_name: String,
+    pub column_name: String,
+    pub column_index: usize,
+    // Maps value -> list of row offsets in the file, ordered by value;
+    // float keys order by `ordered_bits`, so range scans on floats are exact
+    pub index_map: BTreeMap<Value, Vec<u64>>,
+}
+
+impl RangeIndex {
+    pub fn new(table_name: String, column_name: String, column_index: usize) -> Self {
+        RangeIndex {
+            table_name,
+            column_name,
+            column_index,
+            index_map: BTreeMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, value: &Value, offset: u64) {
+        self.index_map.entry(value.clone()).or_default().push(offset);
+    }
+
+    // Remove a single row offset, dropping the key once it has no rows left
+    pub fn remove(&mut self, value: &Value, offset: u64) {
+        if let Some(offsets) = self.index_map.get_mut(value) {
+            offsets.retain(|&o| o != offset);
+            if offsets.is_empty() {
+                self.index_map.remove(value);
+            }
+        }
+    }
+
+    // Remove every listed offset, dropping keys left with no rows.
+    // Returns how many offsets were removed.
+    pub fn remove_offsets(&mut self, offsets: &HashSet<u64>) -> usize {
+        let mut removed = 0;
+        self.index_map.retain(|_, key_offsets| {
+            let before = key_offsets.len();
+            key_offsets.retain(|offset| !offsets.contains(offset));
+            removed += before - key_offsets.len();
+            !key_offsets.is_empty()
+        });
+        removed
+    }
+
+    // Number of distinct keys
+    pub fn cardinality(&self) -> usize {
+        self.index_map.len()
+    }
+
+    // Average number of rows an equality lookup finds
+    pub fn avg_rows_per_key(&self) -> f64 {
+        self.stats().avg_rows_per_key
+    }
+
+    pub fn stats(&self) -> IndexStats {
+        IndexStats::from_map(self.index_map.values())
+    }
+
+    // Approximate bytes held by the keys and offsets, not counting the
+    // map's own bookkeeping
+    pub fn memory_usage(&self) -> usize {
+        self.index_map
+            .iter()
+            .map(|(key, offsets)| key.memory_usage() + offsets_memory(offsets))
+            .sum()
+    }
+
+    // Get the offsets of all rows whose value falls within the bounds
+    pub fn lookup_range(&self, lower: Bound<Value>, upper: Bound<Value>) -> Vec<u64> {
+        // BTreeMap::range panics on inverted or empty exclusive ranges
+        if let (
+            Bound::Included(lo) | Bound::Excluded(lo),
+            Bound::Included(hi) | Bound::Excluded(hi),
+        ) = (&lower, &upper)
+        {
+            let both_included = matches!((&lower, &upper), (Bound::Included(_), Bound::Included(_)));
+            if lo > hi || (lo == hi && !both_included) {
+                return Vec::new();
+            }
+        }
+
+        self.index_map
+            .range((lower, upper))
+            .flat_map(|(_, offsets)| offsets.iter().copied())
+            .collect()
+    }
+}
+
+// Represents an in-memory index on several columns together, for lookups
+// that constrain every one of them
+#[derive(Debug, Clone)]
+pub struct CompositeIndex {
+    pub table_name: String,
+    pub column_names: Vec<String>,
+    pub column_indexes: Vec<usize>,
+    // Maps the tuple of the columns' values -> list of row offsets in the file.
+    // Tuples compare column by column with Value's own ordering, so the map is
+    // sorted by the first column, then the second, and so on, and a key never
+    // runs into the next column the way a concatenated string could.
+    pub index_map: BTreeMap<Vec<Value>, Vec<u64>>,
+}
+
+impl CompositeIndex {
+    pub fn new(table_name: String, column_names: Vec<String>, column_indexes: Vec<usize>) -> Self {
+        CompositeIndex {
+            table_name,
+            column_names,
+            column_indexes,
+            index_map: BTreeMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, row: &Row, offset: u64) {
+        self.index_map.entry(self.key(row)).or_default().push(offset);
+    }
+
+    // Remove a single row offset, dropping the key once it has no rows left
+    pub fn remove(&mut self, row: &Row, offset: u64) {
+        let key = self.key(row);
+        if let Some(offsets) = self.index_map.get_mut(&key) {
+            offsets.retain(|&o| o != offset);
+            if offsets.is_empty() {
+                self.index_map.remove(&key);
+            }
+        }
+    }
+
+    // Remove every listed offset, dropping keys left with no rows.
+    // Returns how many offsets were removed.
+    pub fn remove_offsets(&mut self, offsets: &HashSet<u64>) -> usize {
+        let mut removed = 0;
+        self.index_map.retain(|_, key_offsets| {
+            let before = key_offsets.len();
+            key_offsets.retain(|offset| !offsets.contains(offset));
+            removed += before - key_offsets.len();
+            !key_offsets.is_empty()
+        });
+        removed
+    }
+
+    // Look up the rows holding `values`, given in the index's column order
+    pub fn lookup(&self, values: &[Value]) -> Option<&Vec<u64>> {
+        self.index_map.get(values)
+    }
+
+    // Number of distinct tuples
+    pub fn cardinality(&self) -> usize {
+        self.index_map.len()
+    }
+
+    // Average number of rows a lookup finds
+    pub fn avg_rows_per_key(&self) -> f64 {
+        self.stats().avg_rows_per_key
+    }
+
+    pub fn stats(&self) -> IndexStats {
+        IndexStats::from_map(self.index_map.values())
+    }
+
+    // Approximate bytes held by the keys and offsets, not counting the
+    // map's own bookkeeping
+    pub fn memory_usage(&self) -> usize {
+        self.index_map
+            .iter()
+            .map(|(key, offsets)| {
+                let key_size: usize = key.iter().map(Value::memory_usage).sum();
+                std::mem::size_of::<Vec<Value>>() + key_size + offsets_memory(offsets)
+            })
+            .sum()
+    }
+
+    // The row's values for the indexed columns, in index order
+    fn key(&self, row: &Row) -> Vec<Value> {
+        self.column_indexes
+            .iter()
+            .map(|&i| row.get(i).cloned().unwrap_or(Value::Null))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ordered_bits_follow_numeric_order() {
+        let ascending = [
+            f64::NEG_INFINITY,
+            f64::MIN,
+            -1.5,
+            -1.0,
+            -f64::MIN_POSITIVE,
+            -5e-324,
+            0.0,
+            5e-324,
+            f64::MIN_POSITIVE,
+            1.0,
+            1.5,
+            f64::MAX,
+            f64::INFINITY,
+        ];
+        for pair in ascending.windows(2) {
+            assert!(
+                ordered_bits(pair[0]) < ordered_bits(pair[1]),
+                "{} should sort below {}",
+                pair[0],
+                pair[1]
+            );
+        }
+    }
+
+    #[test]
+    fn ordered_bits_fold_negative_zero() {
+        assert_eq!(ordered_bits(-0.0), ordered_bits(0.0));
+        assert_eq!(Value::Float(-0.0), Value::Float(0.0));
+        assert!(ordered_bits(-0.0) > ordered_bits(-5e-324));
+        assert!(ordered_bits(-0.0) < ordered_bits(5e-324));
+    }
+
+    #[test]
+    fn ordered_bits_put_every_nan_above_infinity() {
+        let negative_nan = -f64::NAN;
+        let other_payload = f64::from_bits(f64::NAN.to_bits() | 1);
+        assert!(negative_nan.is_nan() && other_payload.is_nan());
+
+        for nan in [f64::NAN, negative_nan, other_payload] {
+            assert_eq!(ordered_bits(nan), ordered_bits(f64::NAN));
+            assert!(ordered_bits(nan) > ordered_bits(f64::INFINITY));
+            assert_eq!(cmp_f64(nan, f64::NAN), Ordering::Equal);
+        }
+        assert_eq!(Value::Float(negative_nan), Value::Float(f64::NAN));
+        assert!(Value::Float(f64::NAN) > Value::Float(f64::INFINITY));
+    }
 }